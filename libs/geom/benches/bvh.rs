@@ -0,0 +1,69 @@
+// Compares the flattened `Bvh` with the pointer based `BoxedBvh` on a mesh:
+//
+//     cargo bench -p geom -- [model.obj]
+
+extern crate geom;
+extern crate rand;
+extern crate utils;
+
+use std::env;
+use std::fs::File;
+
+use geom::{Point, Ray, Vector};
+use geom::shape::{Bound, BoundBox, Bvh, BoxedBvh, BvhOptions, ObjParser, Triangle};
+use utils::time_it;
+
+
+const N_RAYS: usize = 500000;
+
+
+/// Rays from a sphere around the model towards random points inside its
+/// bound, so that most of them hit it.
+fn random_rays(bound: &BoundBox) -> Vec<Ray> {
+    let center = bound.center();
+    let ranges = [bound.range(0), bound.range(1), bound.range(2)];
+    let radius = (bound.surface_area() / 6.0).sqrt() * 2.0;
+    let half = Vector::new(0.5, 0.5, 0.5);
+    (0..N_RAYS)
+        .map(|_| {
+            let from = center + (rand::random::<Vector>() - half).direction() * radius;
+            let t = rand::random::<Vector>();
+            let lerp = |axis: usize, t: f64| ranges[axis].0 + (ranges[axis].1 - ranges[axis].0) * t;
+            let to = Point::new(lerp(0, t.x), lerp(1, t.y), lerp(2, t.z));
+            Ray::from_to(from, to)
+        })
+        .collect()
+}
+
+fn report<F: Fn(&Ray) -> bool>(name: &str, build_time: f64, memory: usize, rays: &[Ray], hit: F) {
+    let (hits, trace_time) = time_it(|| rays.iter().filter(|ray| hit(ray)).count());
+    println!("{:>8}: built in {:.3}s, {:.2} MiB, {:.3} Mrays/s ({} hits)",
+             name,
+             build_time,
+             memory as f64 / (1024.0 * 1024.0),
+             rays.len() as f64 / trace_time / 1e6,
+             hits);
+}
+
+fn main() {
+    let path = env::args().skip(1)
+        .filter(|arg| !arg.starts_with("-"))
+        .next()
+        .unwrap_or(concat!(env!("CARGO_MANIFEST_DIR"), "/../../scenes/models/buddha.obj")
+                   .to_string());
+    let mut file = File::open(&path).expect("can't open the model");
+    let triangles: Vec<Triangle> = ObjParser::new().parse(&mut file).expect("invalid model");
+    let bound = triangles.iter().fold(BoundBox::empty(), |b, t| b.union(&t.bound()));
+    let rays = random_rays(&bound);
+    println!("{}: {} triangles, {} rays", path, triangles.len(), rays.len());
+
+    let options = BvhOptions::default();
+    let (boxed, build_time) = time_it(|| BoxedBvh::new(triangles.clone(), &options));
+    report("boxed", build_time, boxed.memory_footprint(), &rays,
+           |ray| boxed.intersect(ray).is_some());
+    drop(boxed);
+
+    let (linear, build_time) = time_it(|| Bvh::new(triangles.clone(), &options));
+    report("linear", build_time, linear.memory_footprint(), &rays,
+           |ray| linear.intersect(ray).is_some());
+}
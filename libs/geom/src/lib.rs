@@ -5,11 +5,13 @@ extern crate utils;
 mod vector;
 mod point;
 mod ray;
+mod transform;
 pub mod shape;
 
 pub use self::vector::{Vector, UnitVector};
 pub use self::point::{Point};
 pub use self::ray::Ray;
+pub use self::transform::Transform;
 
 pub type Axis = u8;
 
@@ -14,12 +14,31 @@ pub struct BoundBox {
 }
 
 impl BoundBox {
+    pub fn new(p_min: Point, p_max: Point) -> BoundBox {
+        BoundBox {
+            p_min: p_min,
+            p_max: p_max
+        }
+    }
+
     pub fn empty() -> BoundBox {
         BoundBox {
             p_min: Point::new(f64::INFINITY, f64::INFINITY, f64::INFINITY),
             p_max: Point::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY)
         }
     }
+
+    /// Bound of shapes which extend to infinity.
+    pub fn infinite() -> BoundBox {
+        BoundBox {
+            p_min: Point::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+            p_max: Point::new(f64::INFINITY, f64::INFINITY, f64::INFINITY)
+        }
+    }
+
+    pub fn is_finite(&self) -> bool {
+        (0..3).all(|axis| self.p_min[axis].is_finite() && self.p_max[axis].is_finite())
+    }
 }
 
 impl BoundBox {
@@ -69,6 +88,40 @@ impl BoundBox {
         true
     }
 
+    /// Range of `t` in front of the origin of the ray where it is inside the
+    /// box.
+    pub fn ray_range(&self, ray: &Ray) -> Option<(f64, f64)> {
+        let mut min_t: f64 = 0.0;
+        let mut max_t = f64::INFINITY;
+        for axis in 0..3 {
+            let inv_dir = 1.0 / ray.direction[axis];
+            let t1 = (self.p_min[axis] - ray.origin[axis]) * inv_dir;
+            let t2 = (self.p_max[axis] - ray.origin[axis]) * inv_dir;
+            // a ray parallel to a slab gives NaN at its side, which `min` and
+            // `max` skip
+            min_t = min_t.max(t1.min(t2));
+            max_t = max_t.min(t1.max(t2));
+        }
+        if min_t <= max_t {
+            Some((min_t, max_t))
+        } else {
+            None
+        }
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let d = self.diag();
+        if d.x < 0.0 || d.y < 0.0 || d.z < 0.0 {
+            return 0.0;
+        }
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
+    /// Extent of the box along the `axis`.
+    pub fn range(&self, axis: Axis) -> (f64, f64) {
+        (self.p_min[axis], self.p_max[axis])
+    }
+
     fn diag(&self) -> Vector {
         self.p_max - self.p_min
     }
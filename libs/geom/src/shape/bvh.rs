@@ -0,0 +1,560 @@
+use std::f32;
+use std::f64;
+use std::mem;
+use std::cmp::min;
+
+use Axis;
+use Point;
+use Ray;
+use shape::{Intersection, Shape};
+use shape::bound_box::{BoundBox, Bound};
+
+/// How shapes of a node are divided between its children.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SplitMethod {
+    /// Split in halves along the longest axis.
+    Median,
+    /// Binned surface area heuristic: choose the split which minimizes the
+    /// expected cost of tracing a ray through the node.
+    Sah,
+}
+
+
+/// Parameters of BVH construction. The costs of traversing a node and of
+/// intersecting a shape are relative, only their ratio matters.
+#[derive(Debug, Clone, Copy)]
+pub struct BvhOptions {
+    pub split: SplitMethod,
+    /// Nodes with more shapes are always split. Smaller nodes are split
+    /// only if the SAH predicts it to be beneficial.
+    pub max_leaf_size: usize,
+    pub traversal_cost: f64,
+    pub intersection_cost: f64,
+}
+
+impl Default for BvhOptions {
+    fn default() -> BvhOptions {
+        BvhOptions {
+            split: SplitMethod::Sah,
+            max_leaf_size: 4,
+            traversal_cost: 0.125,
+            intersection_cost: 1.0,
+        }
+    }
+}
+
+
+const N_BINS: usize = 12;
+
+
+/// Tree used during construction. Leaves refer to ranges of shapes in the
+/// order in which the leaves were created.
+enum Node {
+    Leaf { first: usize, count: usize, bound: BoundBox },
+    Interior {
+        children: [Box<Node>; 2],
+        axis: Axis,
+        bound: BoundBox,
+    }
+}
+
+
+struct Item {
+    index: usize,
+    bound: BoundBox,
+    center: Point,
+}
+
+
+impl Node {
+    fn interior(l: Box<Node>, r: Box<Node>, axis: Axis) -> Node {
+        let bound = l.bound().union(&r.bound());
+        Node::Interior {
+            children: [l, r],
+            axis: axis,
+            bound: bound
+        }
+    }
+
+    fn leaf(items: Vec<Item>, bound: BoundBox, order: &mut Vec<usize>) -> Node {
+        let first = order.len();
+        order.extend(items.iter().map(|item| item.index));
+        Node::Leaf {
+            first: first,
+            count: items.len(),
+            bound: bound,
+        }
+    }
+
+    fn bound(&self) -> BoundBox {
+        match self {
+            &Node::Leaf {bound, ..} => bound,
+            &Node::Interior { bound, ..} => bound
+        }
+    }
+
+
+    fn build(items: Vec<Item>, options: &BvhOptions, order: &mut Vec<usize>) -> Node {
+        assert!(items.len() > 0);
+        let bound = items.iter().fold(BoundBox::empty(), |b, item| b.union(&item.bound));
+        if items.len() == 1 {
+            return Node::leaf(items, bound, order);
+        }
+
+        let axis = items.iter().map(|item| item.center)
+            .collect::<BoundBox>()
+            .longext_axis();
+
+        let (left, right) = match options.split {
+            SplitMethod::Median => {
+                if items.len() <= options.max_leaf_size {
+                    return Node::leaf(items, bound, order);
+                }
+                Node::median_split(items, axis)
+            }
+            SplitMethod::Sah => match Node::sah_split(items, axis, &bound, options) {
+                Ok(split) => split,
+                Err(items) => return Node::leaf(items, bound, order),
+            },
+        };
+
+        let left = Box::new(Node::build(left, options, order));
+        let right = Box::new(Node::build(right, options, order));
+        Node::interior(left, right, axis)
+    }
+
+    fn median_split(mut items: Vec<Item>, axis: Axis) -> (Vec<Item>, Vec<Item>) {
+        items.sort_by(|a, b| a.center[axis].partial_cmp(&b.center[axis]).unwrap());
+        let right = items.split_off(items.len() / 2);
+        (items, right)
+    }
+
+    /// Returns the items back if it is cheaper to keep them in a leaf.
+    fn sah_split(items: Vec<Item>,
+                 axis: Axis,
+                 bound: &BoundBox,
+                 options: &BvhOptions)
+                 -> Result<(Vec<Item>, Vec<Item>), Vec<Item>> {
+        let n = items.len();
+        let (lo, hi) = items.iter().map(|item| item.center).collect::<BoundBox>().range(axis);
+        if hi <= lo {
+            // all centers coincide, no split can separate the shapes
+            return if n <= options.max_leaf_size {
+                Err(items)
+            } else {
+                Ok(Node::median_split(items, axis))
+            };
+        }
+
+        let bin_of = |item: &Item| {
+            let b = ((item.center[axis] - lo) / (hi - lo) * N_BINS as f64) as usize;
+            b.min(N_BINS - 1)
+        };
+        let mut counts = [0usize; N_BINS];
+        let mut bounds = [BoundBox::empty(); N_BINS];
+        for item in items.iter() {
+            let b = bin_of(item);
+            counts[b] += 1;
+            bounds[b] = bounds[b].union(&item.bound);
+        }
+
+        // cost of splitting after each bin, computed with a sweep from both
+        // sides
+        let mut left_area = [0.0; N_BINS];
+        let mut left_count = [0usize; N_BINS];
+        let (mut acc, mut count) = (BoundBox::empty(), 0);
+        for i in 0..N_BINS {
+            acc = acc.union(&bounds[i]);
+            count += counts[i];
+            left_area[i] = acc.surface_area();
+            left_count[i] = count;
+        }
+        let mut best = (f64::INFINITY, 0);
+        let (mut acc, mut count) = (BoundBox::empty(), 0);
+        for i in (0..N_BINS - 1).rev() {
+            acc = acc.union(&bounds[i + 1]);
+            count += counts[i + 1];
+            let cost = left_area[i] * left_count[i] as f64 + acc.surface_area() * count as f64;
+            if cost < best.0 {
+                best = (cost, i);
+            }
+        }
+
+        let area = bound.surface_area();
+        let split_cost = options.traversal_cost
+            + options.intersection_cost * if area > 0.0 { best.0 / area } else { n as f64 };
+        let leaf_cost = options.intersection_cost * n as f64;
+        if n <= options.max_leaf_size && leaf_cost <= split_cost {
+            return Err(items);
+        }
+
+        let (left, right): (Vec<_>, Vec<_>) = items.into_iter()
+            .partition(|item| bin_of(item) <= best.1);
+        if left.is_empty() || right.is_empty() {
+            let items = left.into_iter().chain(right.into_iter()).collect();
+            return Ok(Node::median_split(items, axis));
+        }
+        Ok((left, right))
+    }
+}
+
+
+/// Builds the tree and permutes the shapes so that each leaf refers to a
+/// contiguous range of them.
+fn build<T: Bound>(shapes: Vec<T>, options: &BvhOptions) -> (Node, Vec<T>) {
+    assert!(shapes.len() > 0, "can't build a bvh without shapes");
+    assert!(options.max_leaf_size > 0);
+    assert!(options.max_leaf_size <= u16::max_value() as usize);
+    let items = shapes.iter()
+        .enumerate()
+        .map(|(i, s)| {
+            let bound = s.bound();
+            Item { index: i, bound: bound, center: bound.center() }
+        })
+        .collect();
+    let mut order = Vec::with_capacity(shapes.len());
+    let root = Node::build(items, options, &mut order);
+
+    let mut slots = shapes.into_iter().map(Some).collect::<Vec<_>>();
+    let shapes = order.iter().map(|&i| slots[i].take().unwrap()).collect();
+    (root, shapes)
+}
+
+
+/// Node of the flattened tree, 32 bytes in size so that two of them fit a
+/// cache line. The first child of an interior node immediately follows it.
+#[repr(C, align(32))]
+struct LinearNode {
+    /// Bound rounded outwards to single precision.
+    p_min: [f32; 3],
+    p_max: [f32; 3],
+    /// Index of the first shape of a leaf or of the second child of an
+    /// interior node.
+    offset: u32,
+    /// Zero for interior nodes.
+    n_shapes: u16,
+    axis: Axis,
+}
+
+impl LinearNode {
+    fn new(bound: &BoundBox, offset: usize, n_shapes: usize, axis: Axis) -> LinearNode {
+        assert!(offset <= u32::max_value() as usize);
+        let (x, y, z) = (bound.range(0), bound.range(1), bound.range(2));
+        LinearNode {
+            p_min: [round_down(x.0), round_down(y.0), round_down(z.0)],
+            p_max: [round_up(x.1), round_up(y.1), round_up(z.1)],
+            offset: offset as u32,
+            n_shapes: n_shapes as u16,
+            axis: axis,
+        }
+    }
+
+    /// Slab test. A ray lying in the plane of a slab gives NaNs, which fail
+    /// the comparisons and are thus ignored.
+    fn is_intersected(&self, origin: &[f64; 3], inv_dir: &[f64; 3], mut max_t: f64) -> bool {
+        let mut min_t = 0.0;
+        for axis in 0..3 {
+            let t1 = (self.p_min[axis] as f64 - origin[axis]) * inv_dir[axis];
+            let t2 = (self.p_max[axis] as f64 - origin[axis]) * inv_dir[axis];
+            let (t_near, t_far) = if inv_dir[axis] < 0.0 { (t2, t1) } else { (t1, t2) };
+            if t_near > min_t {
+                min_t = t_near;
+            }
+            if t_far < max_t {
+                max_t = t_far;
+            }
+            if max_t < min_t {
+                return false
+            }
+        }
+        true
+    }
+}
+
+fn round_down(x: f64) -> f32 {
+    let y = x as f32;
+    if y as f64 <= x {
+        y
+    } else if y.is_infinite() {
+        f32::MAX
+    } else {
+        y - y.abs() * f32::EPSILON - f32::MIN_POSITIVE
+    }
+}
+
+fn round_up(x: f64) -> f32 {
+    -round_down(-x)
+}
+
+
+/// Bounding volume hierarchy stored as an array of nodes in depth-first
+/// order, with the shapes of the leaves in a separate array. Shapes must be
+/// bounded.
+pub struct Bvh<T: Bound> {
+    nodes: Vec<LinearNode>,
+    shapes: Vec<T>,
+    bound: BoundBox,
+}
+
+impl<T: Bound> Bvh<T>  {
+    pub fn new(shapes: Vec<T>, options: &BvhOptions) -> Bvh<T> {
+        let (root, shapes) = build(shapes, options);
+        let mut nodes = Vec::new();
+        flatten(&root, &mut nodes);
+        Bvh {
+            nodes: nodes,
+            shapes: shapes,
+            bound: root.bound(),
+        }
+    }
+
+    pub fn bound(&self) -> BoundBox {
+        self.bound
+    }
+
+    /// The shapes in the order of the leaves.
+    pub fn shapes(&self) -> &[T] {
+        &self.shapes
+    }
+
+    /// Bytes occupied by the nodes and the shapes.
+    pub fn memory_footprint(&self) -> usize {
+        self.nodes.capacity() * mem::size_of::<LinearNode>()
+            + self.shapes.capacity() * mem::size_of::<T>()
+    }
+
+    /// Calls `hit` for the shapes whose bounds the ray crosses closer than
+    /// `max_t` and than the hits reported so far. `hit` returns the distance
+    /// to the intersection with the shape, if any.
+    pub fn traverse<F>(&self, ray: &Ray, mut max_t: f64, mut hit: F)
+        where F: FnMut(&T) -> Option<f64>
+    {
+        let origin = [ray.origin[0], ray.origin[1], ray.origin[2]];
+        let inv_dir = [1.0 / ray.direction[0], 1.0 / ray.direction[1], 1.0 / ray.direction[2]];
+        let mut todo = Vec::with_capacity(64);
+
+        let mut current = 0;
+        loop {
+            let node = &self.nodes[current];
+            if node.is_intersected(&origin, &inv_dir, max_t) {
+                if node.n_shapes > 0 {
+                    let first = node.offset as usize;
+                    for shape in self.shapes[first..first + node.n_shapes as usize].iter() {
+                        if let Some(t) = hit(shape) {
+                            max_t = max_t.min(t);
+                        }
+                    }
+                } else {
+                    // visit the nearer child first
+                    let second = node.offset as usize;
+                    if inv_dir[node.axis as usize] < 0.0 {
+                        todo.push(current + 1);
+                        current = second;
+                    } else {
+                        todo.push(second);
+                        current = current + 1;
+                    }
+                    continue;
+                }
+            }
+            match todo.pop() {
+                Some(next) => current = next,
+                None => break,
+            }
+        }
+    }
+}
+
+impl<T: Shape> Bvh<T> {
+    pub fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+        let mut result = None;
+        self.traverse(ray, f64::INFINITY, |shape| {
+            shape.intersect(ray).map(|i| {
+                let new_result = match result {
+                    None => i,
+                    Some(j) => min(i, j)
+                };
+                result = Some(new_result);
+                i.t
+            })
+        });
+        result
+    }
+}
+
+fn flatten(node: &Node, nodes: &mut Vec<LinearNode>) {
+    match node {
+        &Node::Leaf { first, count, ref bound } =>
+            nodes.push(LinearNode::new(bound, first, count, 0)),
+        &Node::Interior { ref children, axis, ref bound } => {
+            let idx = nodes.len();
+            nodes.push(LinearNode::new(bound, 0, 0, axis));
+            flatten(&children[0], nodes);
+            nodes[idx].offset = nodes.len() as u32;
+            flatten(&children[1], nodes);
+        }
+    }
+}
+
+
+/// Layout used before `Bvh`: every node is allocated separately and leaves
+/// own copies of their shapes. Kept only to benchmark against.
+pub struct BoxedBvh<T: Shape + Clone> {
+    root: BoxedNode<T>
+}
+
+enum BoxedNode<T> {
+    Leaf { shapes: Vec<T>, bound: BoundBox },
+    Interior {
+        children: [Box<BoxedNode<T>>; 2],
+        axis: Axis,
+        bound: BoundBox,
+    }
+}
+
+impl<T: Shape + Clone> BoxedBvh<T> {
+    pub fn new(shapes: Vec<T>, options: &BvhOptions) -> BoxedBvh<T> {
+        fn convert<T: Clone>(node: &Node, shapes: &[T]) -> BoxedNode<T> {
+            match node {
+                &Node::Leaf { first, count, bound } => BoxedNode::Leaf {
+                    shapes: shapes[first..first + count].to_vec(),
+                    bound: bound,
+                },
+                &Node::Interior { ref children, axis, bound } => BoxedNode::Interior {
+                    children: [Box::new(convert(&children[0], shapes)),
+                               Box::new(convert(&children[1], shapes))],
+                    axis: axis,
+                    bound: bound,
+                },
+            }
+        }
+
+        let (root, shapes) = build(shapes, options);
+        BoxedBvh { root: convert(&root, &shapes) }
+    }
+
+    /// Bytes occupied by the nodes and the shapes.
+    pub fn memory_footprint(&self) -> usize {
+        fn node_size<T>(node: &BoxedNode<T>) -> usize {
+            mem::size_of::<BoxedNode<T>>() + match node {
+                &BoxedNode::Leaf { ref shapes, .. } => shapes.capacity() * mem::size_of::<T>(),
+                &BoxedNode::Interior { ref children, .. } =>
+                    node_size(&children[0]) + node_size(&children[1]),
+            }
+        }
+        node_size(&self.root)
+    }
+
+    pub fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+        let mut todo = Vec::with_capacity(64);
+        let mut result = None;
+        let mut t_bound = f64::INFINITY;
+
+        todo.push(&self.root);
+        while let Some(node) = todo.pop() {
+            match node {
+                &BoxedNode::Leaf {ref shapes, ref bound} => {
+                    if !bound.is_intersected(ray, t_bound) {
+                        continue;
+                    }
+                    for shape in shapes.iter() {
+                        if let Some(i) = shape.intersect(ray) {
+                            let new_result = match result {
+                                None => i,
+                                Some(j) => min(i, j)
+                            };
+                            t_bound = t_bound.min(new_result.t);
+                            result = Some(new_result);
+                        }
+                    }
+                },
+                &BoxedNode::Interior {ref children, axis, ref bound} => {
+                    if !bound.is_intersected(ray, t_bound) {
+                        continue;
+                    }
+                    if ray.direction[axis] < 0.0 {
+                        todo.push(&children[0]);
+                        todo.push(&children[1]);
+                    } else {
+                        todo.push(&children[1]);
+                        todo.push(&children[0]);
+                    }
+                }
+            }
+        }
+
+        result
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::mem;
+    use super::*;
+    use super::{LinearNode, round_down, round_up};
+    use shape::{Shape, Triangle};
+    use Point;
+    use Ray;
+    use utils::props::{check_prop, check_prop2};
+
+    fn triangles() -> Vec<Triangle> {
+        let mut result = Vec::new();
+        for i in 0..20 {
+            for j in 0..20 {
+                let (x, y) = (i as f64 * 0.1 - 1.0, j as f64 * 0.1 - 1.0);
+                let z = (x * 3.0).sin() * (y * 2.0).cos() * 0.3;
+                result.push(Triangle::new(
+                    Point::new(x, y, z),
+                    Point::new(x + 0.1, y, z),
+                    Point::new(x, y + 0.1, z + 0.05)));
+            }
+        }
+        result
+    }
+
+    #[test]
+    fn test_bvh_splits_agree() {
+        assert_eq!(mem::size_of::<LinearNode>(), 32);
+        assert_eq!(mem::align_of::<LinearNode>(), 32);
+
+        let shapes = triangles();
+        let median_options = BvhOptions {
+            split: SplitMethod::Median,
+            max_leaf_size: 1,
+            ..BvhOptions::default()
+        };
+        let sah = Bvh::new(shapes.clone(), &BvhOptions::default());
+        let median = Bvh::new(shapes.clone(), &median_options);
+        let boxed = BoxedBvh::new(shapes.clone(), &median_options);
+        assert!(median.memory_footprint() < boxed.memory_footprint());
+        let mut hits = 0;
+
+        check_prop2(|from: Point, to: Point| {
+            let from = Point::new(from[0] % 2.0, from[1] % 2.0, 1.0 + from[2] % 1.0);
+            let to = Point::new(to[0] % 1.0, to[1] % 1.0, -1.0);
+            let ray = Ray::from_to(from, to);
+            let expected = shapes.iter().filter_map(|s| s.intersect(&ray)).min();
+            for result in &[sah.intersect(&ray), median.intersect(&ray), boxed.intersect(&ray)] {
+                match (*result, expected) {
+                    (None, None) => (),
+                    (Some(i), Some(j)) => assert!((i.t - j.t).abs() < 1e-9),
+                    _ => panic!("bvh disagrees with brute force"),
+                }
+            }
+            if expected.is_some() {
+                hits += 1;
+            }
+        });
+        assert!(hits > 10);
+    }
+
+    #[test]
+    fn test_rounding_is_conservative() {
+        check_prop(|p: Point| {
+            for &x in &[p[0], p[1] * 1e-30, p[2] * 1e30, -p[0] * 1e-40] {
+                assert!(round_down(x) as f64 <= x && x <= round_up(x) as f64);
+            }
+        });
+    }
+}
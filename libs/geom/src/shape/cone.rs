@@ -0,0 +1,174 @@
+use std::f64::consts::PI;
+
+use Point;
+use Vector;
+use Ray;
+use super::{Intersection, Interval, Shape, Solid, first_in_front, pair_crossings};
+use super::bound_box::{Bound, BoundBox};
+use super::frame::Frame;
+
+
+/// Cone with the disk of the `radius` at the `base` and the tip at the
+/// `apex`, optionally closed by the base disk. Texture coordinates are as
+/// for the `Cylinder`. Only capped cones are solids.
+pub struct Cone {
+    frame: Frame,
+    radius: f64,
+    height: f64,
+    capped: bool,
+}
+
+impl Cone {
+    pub fn new(base: Point, apex: Point, radius: f64, capped: bool) -> Cone {
+        let axis = apex - base;
+        assert!(radius > 0.0 && !axis.is_almost_zero());
+        Cone {
+            frame: Frame::new(base, axis.direction()),
+            radius: radius,
+            height: axis.length(),
+            capped: capped,
+        }
+    }
+
+    /// Radius of the cone shrinks by `slope` per unit of height.
+    fn slope(&self) -> f64 {
+        self.radius / self.height
+    }
+
+    fn crossings(&self, ray: &Ray) -> Vec<Intersection> {
+        let o = self.frame.to_local(ray.origin);
+        let d = self.frame.vector_to_local(ray.direction.into());
+        let (r, h) = (self.radius, self.height);
+        let k2 = self.slope() * self.slope();
+        let mut result = Vec::new();
+
+        // x^2 + y^2 = k^2 (h - z)^2
+        let a = d.x * d.x + d.y * d.y - k2 * d.z * d.z;
+        let b = 2.0 * (o.x * d.x + o.y * d.y + k2 * (h - o.z) * d.z);
+        let c = o.x * o.x + o.y * o.y - k2 * (h - o.z) * (h - o.z);
+        let roots = if a.abs() < 1e-12 {
+            // the ray is parallel to the slant
+            if b.abs() < 1e-12 { vec![] } else { vec![-c / b] }
+        } else {
+            let disc = b * b - 4.0 * a * c;
+            if disc < 0.0 {
+                vec![]
+            } else {
+                vec![(-b - disc.sqrt()) / (2.0 * a), (-b + disc.sqrt()) / (2.0 * a)]
+            }
+        };
+        for t in roots {
+            let p = o + d * t;
+            if (0.0..=h).contains(&p.z) {
+                result.push(self.side_intersection(ray, t, p));
+            }
+        }
+
+        if self.capped && d.z != 0.0 {
+            let t = -o.z / d.z;
+            let p = o + d * t;
+            if p.x * p.x + p.y * p.y <= r * r {
+                result.push(self.cap_intersection(ray, t, p));
+            }
+        }
+
+        result.sort();
+        result
+    }
+
+    fn side_intersection(&self, ray: &Ray, t: f64, p: Vector) -> Intersection {
+        let f = &self.frame;
+        let k = self.slope();
+        let gradient = Vector::new(p.x, p.y, k * k * (self.height - p.z));
+        // the tip has no normal, use the axis
+        let normal = if gradient.is_almost_zero() {
+            f.w
+        } else {
+            f.vector_to_world(gradient).direction()
+        };
+        let u = 0.5 + p.y.atan2(p.x) / (2.0 * PI);
+        let v = p.z / self.height;
+        let rho = (p.x * p.x + p.y * p.y).sqrt();
+        let (cos, sin) = if rho > 0.0 { (p.x / rho, p.y / rho) } else { (0.0, 0.0) };
+        Intersection::new(t, ray.along(t), [u, v], normal)
+            .with_tangents(f.vector_to_world(Vector::new(-p.y, p.x, 0.0) * (2.0 * PI)),
+                           f.vector_to_world(Vector::new(-k * cos, -k * sin, 1.0) * self.height))
+            .with_uv_density(1.0 / (2.0 * PI * self.radius))
+    }
+
+    fn cap_intersection(&self, ray: &Ray, t: f64, p: Vector) -> Intersection {
+        let f = &self.frame;
+        let uv = [0.5 + p.x / (2.0 * self.radius), 0.5 + p.y / (2.0 * self.radius)];
+        Intersection::new(t, ray.along(t), uv, -f.w)
+            .with_tangents(f.u * (2.0 * self.radius), f.v * (2.0 * self.radius))
+            .with_uv_density(1.0 / (2.0 * self.radius))
+    }
+}
+
+impl Shape for Cone {
+    fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+        first_in_front(self.crossings(ray))
+    }
+}
+
+impl Solid for Cone {
+    fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        assert!(self.capped, "an open cone is not a solid");
+        pair_crossings(self.crossings(ray))
+    }
+}
+
+impl Bound for Cone {
+    fn bound(&self) -> BoundBox {
+        let apex = self.frame.origin + self.frame.w * self.height;
+        self.frame.circle_bound(0.0, self.radius).union(&apex.bound())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {Point, Ray, Vector};
+    use shape::{Shape, Solid};
+
+    #[test]
+    fn hit_on_cone() {
+        // the radius is half of the distance to the apex
+        let (base, apex) = (Point::new(0.0, 0.0, -1.0), Point::new(0.0, 0.0, 1.0));
+        let capped = Cone::new(base, apex, 1.0, true);
+        let open = Cone::new(base, apex, 1.0, false);
+        let is_normal = |i: &Intersection, normal: Vector| {
+            (Vector::from(i.normal) - normal.direction().into()).is_almost_zero()
+        };
+
+        // across the middle, where the radius is 0.5
+        let ray = Ray::from_to(Point::new(-5.0, 0.0, 0.0), Point::new(0.0, 0.0, 0.0));
+        let i = capped.intersect(&ray).unwrap();
+        assert!((i.t - 4.5).abs() < 1e-9);
+        assert!(is_normal(&i, Vector::new(-2.0, 0.0, 1.0)));
+        assert!((i.local_coordinates[1] - 0.5).abs() < 1e-9);
+        let intervals = capped.intervals(&ray);
+        assert_eq!(intervals.len(), 1);
+        assert!((intervals[0].exit.t - 5.5).abs() < 1e-9);
+        assert!(is_normal(&intervals[0].exit, Vector::new(2.0, 0.0, 1.0)));
+
+        // up through the base, out through the side where the radius is 0.25
+        let ray = Ray::from_to(Point::new(0.25, 0.0, -5.0), Point::new(0.25, 0.0, 0.0));
+        let i = capped.intersect(&ray).unwrap();
+        assert!((i.t - 4.0).abs() < 1e-9);
+        assert!(is_normal(&i, Vector::new(0.0, 0.0, -1.0)));
+        let intervals = capped.intervals(&ray);
+        assert_eq!(intervals.len(), 1);
+        assert!((intervals[0].exit.t - 5.5).abs() < 1e-9);
+        assert!(is_normal(&intervals[0].exit, Vector::new(2.0, 0.0, 1.0)));
+        // without the base the inside of the side is hit
+        let i = open.intersect(&ray).unwrap();
+        assert!((i.t - 5.5).abs() < 1e-9);
+
+        // beside the cone
+        let ray = Ray::from_to(Point::new(-5.0, 0.6, 0.0), Point::new(0.0, 0.6, 0.0));
+        assert!(capped.intersect(&ray).is_none());
+        assert!(capped.intervals(&ray).is_empty());
+    }
+}
@@ -0,0 +1,149 @@
+use std::sync::Arc;
+
+use Dot;
+use Ray;
+use super::{Intersection, Interval, Shape, Solid};
+use super::bound_box::{Bound, BoundBox};
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CsgOperation {
+    Union,
+    Intersection,
+    /// Points of the first solid which are not in the second one.
+    Difference,
+}
+
+impl CsgOperation {
+    fn contains(self, in_left: bool, in_right: bool) -> bool {
+        match self {
+            CsgOperation::Union => in_left || in_right,
+            CsgOperation::Intersection => in_left && in_right,
+            CsgOperation::Difference => in_left && !in_right,
+        }
+    }
+}
+
+
+/// Constructive solid geometry: a boolean combination of two solids.
+pub struct Csg {
+    operation: CsgOperation,
+    children: [Arc<Solid>; 2],
+    bound: BoundBox,
+}
+
+impl Csg {
+    pub fn new(operation: CsgOperation, left: Arc<Solid>, right: Arc<Solid>) -> Csg {
+        let bound = match operation {
+            CsgOperation::Union => left.bound().union(&right.bound()),
+            CsgOperation::Intersection | CsgOperation::Difference => left.bound(),
+        };
+        Csg {
+            operation: operation,
+            children: [left, right],
+            bound: bound,
+        }
+    }
+}
+
+impl Solid for Csg {
+    fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        // sweep along the ray over the boundaries of both children
+        let mut events = Vec::new();
+        for (child, solid) in self.children.iter().enumerate() {
+            for interval in solid.intervals(ray) {
+                events.push((interval.enter, child, true));
+                events.push((interval.exit, child, false));
+            }
+        }
+        events.sort_by(|a, b| a.0.t.partial_cmp(&b.0.t).unwrap());
+
+        let mut result = Vec::new();
+        let mut inside = [false, false];
+        let mut enter = None;
+        for (i, child, entering) in events {
+            let was_inside = self.operation.contains(inside[0], inside[1]);
+            inside[child] = entering;
+            let is_inside = self.operation.contains(inside[0], inside[1]);
+            if !was_inside && is_inside {
+                enter = Some(outwards(i, ray, false));
+            } else if was_inside && !is_inside {
+                if let Some(enter) = enter.take() {
+                    result.push(Interval { enter: enter, exit: outwards(i, ray, true) });
+                }
+            }
+        }
+        result
+    }
+}
+
+/// Turns the normal against the ray where it enters the solid and along the
+/// ray where it exits. Boundaries of a difference which come from the second
+/// solid would point inwards otherwise.
+fn outwards(i: Intersection, ray: &Ray, exit: bool) -> Intersection {
+    if (i.normal.dot(ray.direction) > 0.0) != exit { i.flipped() } else { i }
+}
+
+impl Shape for Csg {
+    fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+        self.intervals(ray).into_iter()
+            .flat_map(|interval| vec![interval.enter, interval.exit])
+            .find(|i| i.t > 0.0)
+    }
+}
+
+impl Bound for Csg {
+    fn bound(&self) -> BoundBox {
+        self.bound
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use super::*;
+    use shape::{Shape, Solid, Sphere};
+    use {Point, Ray, Vector, Dot};
+    use utils::props::check_prop2;
+
+    #[test]
+    fn test_csg_of_spheres() {
+        let a = Arc::new(Sphere::new(Point::new(-0.5, 0.0, 0.0), 1.0));
+        let b = Arc::new(Sphere::new(Point::new(0.5, 0.0, 0.0), 1.0));
+        let union = Csg::new(CsgOperation::Union, a.clone(), b.clone());
+        let intersection = Csg::new(CsgOperation::Intersection, a.clone(), b.clone());
+        let difference = Csg::new(CsgOperation::Difference, a.clone(), b.clone());
+        let inside = |s: &Sphere, p: Point| {
+            let ray = Ray::from_to(p, p + Vector::new(1.0, 0.0, 0.0));
+            s.intervals(&ray).iter().any(|i| i.enter.t <= 0.0 && 0.0 <= i.exit.t)
+        };
+        let mut hits = 0;
+
+        check_prop2(|from: Point, to: Point| {
+            let from = Point::new(from[0] * 4.0 - 2.0, from[1] * 4.0 - 2.0, 3.0);
+            let to = Point::new(to[0] - 0.5, to[1] - 0.5, to[2] - 0.5);
+            let ray = Ray::from_to(from, to);
+            for &(ref csg, op) in &[(&union, CsgOperation::Union),
+                                    (&intersection, CsgOperation::Intersection),
+                                    (&difference, CsgOperation::Difference)] {
+                let mut previous = ::std::f64::NEG_INFINITY;
+                for interval in csg.intervals(&ray) {
+                    assert!(previous <= interval.enter.t && interval.enter.t <= interval.exit.t);
+                    previous = interval.exit.t;
+                    // the middle of an interval is inside, the normals look outwards
+                    let middle = ray.along((interval.enter.t + interval.exit.t) / 2.0);
+                    assert!(op.contains(inside(&a, middle), inside(&b, middle)));
+                    assert!(interval.enter.normal.dot(ray.direction) <= 0.0);
+                    assert!(interval.exit.normal.dot(ray.direction) >= 0.0);
+                }
+            }
+            if let Some(i) = intersection.intersect(&ray) {
+                hits += 1;
+                let u = union.intersect(&ray).unwrap();
+                assert!(u.t <= i.t);
+            }
+        });
+        assert!(hits > 10);
+    }
+}
@@ -0,0 +1,130 @@
+use Point;
+use Vector;
+use Ray;
+use super::{Intersection, Interval, Shape, Solid, first_in_front, pair_crossings};
+use super::bound_box::{Bound, BoundBox};
+
+
+/// Axis aligned box. Each face is mapped to the unit square of texture
+/// coordinates.
+pub struct Cuboid {
+    min: Point,
+    max: Point,
+}
+
+impl Cuboid {
+    pub fn new(min: Point, max: Point) -> Cuboid {
+        assert!(min[0] < max[0] && min[1] < max[1] && min[2] < max[2]);
+        Cuboid {
+            min: min,
+            max: max,
+        }
+    }
+
+    /// Slab test along the whole line of the ray.
+    fn crossings(&self, ray: &Ray) -> Vec<Intersection> {
+        let mut near = (f64::NEG_INFINITY, 0);
+        let mut far = (f64::INFINITY, 0);
+        for axis in 0..3 {
+            let inv_dir = 1.0 / ray.direction[axis];
+            let t1 = (self.min[axis] - ray.origin[axis]) * inv_dir;
+            let t2 = (self.max[axis] - ray.origin[axis]) * inv_dir;
+            let (t_near, t_far) = if inv_dir < 0.0 { (t2, t1) } else { (t1, t2) };
+            if t_near > near.0 {
+                near = (t_near, axis);
+            }
+            if t_far < far.0 {
+                far = (t_far, axis);
+            }
+        }
+        // NaN slabs of rays along a face are never taken, so the ends are
+        // either numbers or infinite
+        if near.0 > far.0 || !near.0.is_finite() || !far.0.is_finite() {
+            return Vec::new();
+        }
+        vec![self.face_intersection(ray, near.0, near.1, false),
+             self.face_intersection(ray, far.0, far.1, true)]
+    }
+
+    fn face_intersection(&self, ray: &Ray, t: f64, axis: u8, exit: bool) -> Intersection {
+        let point = ray.along(t);
+        let unit = |axis: u8| Vector::new(
+            if axis == 0 { 1.0 } else { 0.0 },
+            if axis == 1 { 1.0 } else { 0.0 },
+            if axis == 2 { 1.0 } else { 0.0 });
+        let outwards = if (ray.direction[axis] > 0.0) == exit { 1.0 } else { -1.0 };
+        let normal = (unit(axis) * outwards).direction();
+
+        let (b, c) = ((axis + 1) % 3, (axis + 2) % 3);
+        let size = |axis: u8| self.max[axis] - self.min[axis];
+        let coordinate = |axis: u8| ((point[axis] - self.min[axis]) / size(axis)).clamp(0.0, 1.0);
+        Intersection::new(t, point, [coordinate(b), coordinate(c)], normal)
+            .with_tangents(unit(b) * size(b), unit(c) * size(c))
+            .with_uv_density(1.0 / (size(b) * size(c)).sqrt())
+    }
+}
+
+impl Shape for Cuboid {
+    fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+        first_in_front(self.crossings(ray))
+    }
+}
+
+impl Solid for Cuboid {
+    fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        pair_crossings(self.crossings(ray))
+    }
+}
+
+impl Bound for Cuboid {
+    fn bound(&self) -> BoundBox {
+        BoundBox::new(self.min, self.max)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {Point, Ray, Cross, Dot};
+    use shape::Shape;
+    use utils::props::check_prop2;
+
+    #[test]
+    fn hit_on_cuboid() {
+        let mut hits = 0;
+        let mut misses = 0;
+        let cuboid = Cuboid::new(Point::new(-1.0, -0.5, 0.0), Point::new(1.0, 0.5, 2.0));
+        let center = Point::new(0.0, 0.0, 1.0);
+
+        check_prop2(|from: Point, to: Point| {
+            let from = Point::new(from[0] * 8.0 - 4.0, from[1] * 8.0 - 4.0, -5.0);
+            let to = Point::new(to[0] * 4.0 - 2.0, to[1] * 2.0 - 1.0, to[2] * 2.0);
+            let ray = Ray::from_to(from, to);
+
+            match cuboid.intersect(&ray) {
+                None => misses += 1,
+                Some(i) => {
+                    hits += 1;
+                    let p = i.point;
+                    let on_face = (0..3).filter(|&a| {
+                        (p[a] - cuboid.min[a]).abs() < 1e-9 || (p[a] - cuboid.max[a]).abs() < 1e-9
+                    }).count();
+                    let inside = (0..3).all(|a| {
+                        cuboid.min[a] - 1e-9 <= p[a] && p[a] <= cuboid.max[a] + 1e-9
+                    });
+                    assert!(on_face >= 1 && inside);
+                    assert!(ray.direction.cross(p - ray.origin).is_almost_zero());
+                    // the normal looks outwards and against the ray
+                    assert!(i.normal.dot(p - center) > 0.0);
+                    assert!(i.normal.dot(ray.direction) < 0.0);
+                    let (u, v) = (i.local_coordinates[0], i.local_coordinates[1]);
+                    assert!((0.0..=1.0).contains(&u) && (0.0..=1.0).contains(&v));
+                }
+            }
+        });
+
+        assert!(hits > 1);
+        assert!(misses > 1);
+    }
+}
@@ -0,0 +1,152 @@
+use std::f64::consts::PI;
+
+use Point;
+use Vector;
+use Ray;
+use super::{Intersection, Interval, Shape, Solid, first_in_front, pair_crossings};
+use super::bound_box::{Bound, BoundBox};
+use super::frame::Frame;
+
+
+/// Cylinder between the centers of its `base` and `top`, optionally closed
+/// by disks. `u` texture coordinate goes around the axis and `v` along it,
+/// the caps are mapped to the unit square. Only capped cylinders are solids.
+pub struct Cylinder {
+    frame: Frame,
+    radius: f64,
+    height: f64,
+    capped: bool,
+}
+
+impl Cylinder {
+    pub fn new(base: Point, top: Point, radius: f64, capped: bool) -> Cylinder {
+        let axis = top - base;
+        assert!(radius > 0.0 && !axis.is_almost_zero());
+        Cylinder {
+            frame: Frame::new(base, axis.direction()),
+            radius: radius,
+            height: axis.length(),
+            capped: capped,
+        }
+    }
+
+    fn crossings(&self, ray: &Ray) -> Vec<Intersection> {
+        let o = self.frame.to_local(ray.origin);
+        let d = self.frame.vector_to_local(ray.direction.into());
+        let (r, h) = (self.radius, self.height);
+        let mut result = Vec::new();
+
+        let a = d.x * d.x + d.y * d.y;
+        let b = 2.0 * (o.x * d.x + o.y * d.y);
+        let c = o.x * o.x + o.y * o.y - r * r;
+        let disc = b * b - 4.0 * a * c;
+        if a > 1e-12 && disc >= 0.0 {
+            for &t in &[(-b - disc.sqrt()) / (2.0 * a), (-b + disc.sqrt()) / (2.0 * a)] {
+                let p = o + d * t;
+                if (0.0..=h).contains(&p.z) {
+                    result.push(self.side_intersection(ray, t, p));
+                }
+            }
+        }
+
+        if self.capped && d.z != 0.0 {
+            for &(z, outwards) in &[(0.0, -1.0), (h, 1.0)] {
+                let t = (z - o.z) / d.z;
+                let p = o + d * t;
+                if p.x * p.x + p.y * p.y <= r * r {
+                    result.push(self.cap_intersection(ray, t, p, outwards));
+                }
+            }
+        }
+
+        result.sort();
+        result
+    }
+
+    fn side_intersection(&self, ray: &Ray, t: f64, p: Vector) -> Intersection {
+        let f = &self.frame;
+        let normal = f.vector_to_world(Vector::new(p.x, p.y, 0.0)).direction();
+        let u = 0.5 + p.y.atan2(p.x) / (2.0 * PI);
+        let v = p.z / self.height;
+        Intersection::new(t, ray.along(t), [u, v], normal)
+            .with_tangents(f.vector_to_world(Vector::new(-p.y, p.x, 0.0) * (2.0 * PI)),
+                           f.w * self.height)
+            .with_uv_density(1.0 / (2.0 * PI * self.radius))
+    }
+
+    fn cap_intersection(&self, ray: &Ray, t: f64, p: Vector, outwards: f64) -> Intersection {
+        let f = &self.frame;
+        let uv = [0.5 + p.x / (2.0 * self.radius), 0.5 + p.y / (2.0 * self.radius)];
+        Intersection::new(t, ray.along(t), uv, (f.w * outwards).direction())
+            .with_tangents(f.u * (2.0 * self.radius), f.v * (2.0 * self.radius))
+            .with_uv_density(1.0 / (2.0 * self.radius))
+    }
+}
+
+impl Shape for Cylinder {
+    fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+        first_in_front(self.crossings(ray))
+    }
+}
+
+impl Solid for Cylinder {
+    fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        assert!(self.capped, "an open cylinder is not a solid");
+        pair_crossings(self.crossings(ray))
+    }
+}
+
+impl Bound for Cylinder {
+    fn bound(&self) -> BoundBox {
+        self.frame.circle_bound(0.0, self.radius)
+            .union(&self.frame.circle_bound(self.height, self.radius))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {Point, Ray, Vector};
+    use shape::{Shape, Solid};
+
+    #[test]
+    fn hit_on_cylinder() {
+        let (base, top) = (Point::new(1.0, 0.0, 0.0), Point::new(1.0, 2.0, 0.0));
+        let capped = Cylinder::new(base, top, 0.5, true);
+        let open = Cylinder::new(base, top, 0.5, false);
+        let is_normal = |i: &Intersection, x: f64, y: f64, z: f64| {
+            (Vector::from(i.normal) - Vector::new(x, y, z)).is_almost_zero()
+        };
+
+        // across the side halfway up
+        let ray = Ray::from_to(Point::new(1.0, 1.0, 5.0), Point::new(1.0, 1.0, 0.0));
+        for cylinder in &[&capped, &open] {
+            let i = cylinder.intersect(&ray).unwrap();
+            assert!((i.t - 4.5).abs() < 1e-9);
+            assert!(is_normal(&i, 0.0, 0.0, 1.0));
+            assert!((i.local_coordinates[1] - 0.5).abs() < 1e-9);
+        }
+        let intervals = capped.intervals(&ray);
+        assert_eq!(intervals.len(), 1);
+        assert!((intervals[0].exit.t - 5.5).abs() < 1e-9);
+        assert!(is_normal(&intervals[0].exit, 0.0, 0.0, -1.0));
+
+        // along the axis, through both caps
+        let ray = Ray::from_to(Point::new(1.0, -5.0, 0.0), Point::new(1.0, 0.0, 0.0));
+        let i = capped.intersect(&ray).unwrap();
+        assert!((i.t - 5.0).abs() < 1e-9);
+        assert!(is_normal(&i, 0.0, -1.0, 0.0));
+        assert!((i.local_coordinates[0] - 0.5).abs() < 1e-9);
+        let intervals = capped.intervals(&ray);
+        assert_eq!(intervals.len(), 1);
+        assert!((intervals[0].exit.t - 7.0).abs() < 1e-9);
+        assert!(is_normal(&intervals[0].exit, 0.0, 1.0, 0.0));
+        assert!(open.intersect(&ray).is_none());
+
+        // beside the cylinder
+        let ray = Ray::from_to(Point::new(1.6, 1.0, 5.0), Point::new(1.6, 1.0, 0.0));
+        assert!(capped.intersect(&ray).is_none());
+        assert!(capped.intervals(&ray).is_empty());
+    }
+}
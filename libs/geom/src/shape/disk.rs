@@ -0,0 +1,109 @@
+use std::f64::consts::PI;
+
+use Point;
+use UnitVector;
+use Ray;
+use super::{Intersection, Shape, Surface, SurfaceSample};
+use super::bound_box::{Bound, BoundBox};
+use super::frame::Frame;
+
+
+/// Flat disk. The square around it is mapped to the unit square of texture
+/// coordinates.
+pub struct Disk {
+    frame: Frame,
+    radius: f64,
+}
+
+impl Disk {
+    pub fn new(center: Point, normal: UnitVector, radius: f64) -> Disk {
+        assert!(radius > 0.0);
+        Disk {
+            frame: Frame::new(center, normal),
+            radius: radius,
+        }
+    }
+}
+
+impl Shape for Disk {
+    fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+        let f = &self.frame;
+        let o = f.to_local(ray.origin);
+        let d = f.vector_to_local(ray.direction.into());
+        let t = -o.z / d.z;
+        // rays parallel to the disk give infinite or NaN `t`
+        if !t.is_finite() || t < 0.0 {
+            return None;
+        }
+        let p = o + d * t;
+        if p.x * p.x + p.y * p.y > self.radius * self.radius {
+            return None;
+        }
+        let diameter = 2.0 * self.radius;
+        let uv = [0.5 + p.x / diameter, 0.5 + p.y / diameter];
+        Some(Intersection::new(t, ray.along(t), uv, f.w)
+             .with_tangents(f.u * diameter, f.v * diameter)
+             .with_uv_density(1.0 / diameter))
+    }
+}
+
+impl Surface for Disk {
+    fn area(&self) -> f64 {
+        PI * self.radius * self.radius
+    }
+
+    fn sample_surface(&self, u: f64, v: f64) -> SurfaceSample {
+        let f = &self.frame;
+        let r = self.radius * u.sqrt();
+        let phi = 2.0 * PI * v;
+        SurfaceSample {
+            point: f.origin + f.u * (r * phi.cos()) + f.v * (r * phi.sin()),
+            normal: f.w,
+            pdf: 1.0 / self.area(),
+        }
+    }
+}
+
+impl Bound for Disk {
+    fn bound(&self) -> BoundBox {
+        self.frame.circle_bound(0.0, self.radius)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {Point, Ray, Vector};
+    use shape::Shape;
+
+    #[test]
+    fn hit_on_disk() {
+        let center = Point::new(1.0, 1.0, 1.0);
+        let normal = Vector::new(1.0, 1.0, 0.0).direction();
+        let disk = Disk::new(center, normal, 0.5);
+
+        // straight at the center
+        let ray = Ray::from_to(Point::new(3.0, 3.0, 1.0), center);
+        let i = disk.intersect(&ray).unwrap();
+        assert!((i.t - 8.0f64.sqrt()).abs() < 1e-9);
+        assert!((i.point - center).is_almost_zero());
+        assert!((Vector::from(i.normal) - Vector::from(normal)).is_almost_zero());
+        assert!((i.local_coordinates[0] - 0.5).abs() < 1e-9);
+        assert!((i.local_coordinates[1] - 0.5).abs() < 1e-9);
+
+        // inside and outside of the rim
+        let ray = Ray::from_to(Point::new(3.0, 3.0, 1.4), Point::new(1.0, 1.0, 1.4));
+        let i = disk.intersect(&ray).unwrap();
+        assert!((i.t - 8.0f64.sqrt()).abs() < 1e-9);
+        assert!((i.point - Point::new(1.0, 1.0, 1.4)).is_almost_zero());
+        let ray = Ray::from_to(Point::new(3.0, 3.0, 1.6), Point::new(1.0, 1.0, 1.6));
+        assert!(disk.intersect(&ray).is_none());
+
+        // in the plane of the disk, and away from it
+        let ray = Ray::from_to(Point::new(1.0, 1.0, 5.0), center);
+        assert!(disk.intersect(&ray).is_none());
+        let ray = Ray::from_to(Point::new(3.0, 3.0, 1.0), Point::new(5.0, 5.0, 1.0));
+        assert!(disk.intersect(&ray).is_none());
+    }
+}
@@ -0,0 +1,49 @@
+use Point;
+use UnitVector;
+use Vector;
+use Dot;
+use super::bound_box::BoundBox;
+
+
+/// Orthonormal frame in which shapes with an axis of symmetry are described:
+/// the axis is `w`, so `(x, y)` of the local coordinates are orthogonal to
+/// it.
+#[derive(Debug, Clone, Copy)]
+pub struct Frame {
+    pub origin: Point,
+    pub u: UnitVector,
+    pub v: UnitVector,
+    pub w: UnitVector,
+}
+
+impl Frame {
+    pub fn new(origin: Point, w: UnitVector) -> Frame {
+        let (u, v) = w.orthonormal_basis();
+        Frame {
+            origin: origin,
+            u: u,
+            v: v,
+            w: w,
+        }
+    }
+
+    pub fn to_local(&self, p: Point) -> Vector {
+        self.vector_to_local(p - self.origin)
+    }
+
+    pub fn vector_to_local(&self, d: Vector) -> Vector {
+        Vector::new(d.dot(self.u), d.dot(self.v), d.dot(self.w))
+    }
+
+    pub fn vector_to_world(&self, d: Vector) -> Vector {
+        self.u * d.x + self.v * d.y + self.w * d.z
+    }
+
+    /// Bound of the circle of the `radius` around the axis at `height`.
+    pub fn circle_bound(&self, height: f64, radius: f64) -> BoundBox {
+        let center = self.origin + self.w * height;
+        let extent = |axis| radius * (1.0 - self.w[axis] * self.w[axis]).max(0.0).sqrt();
+        let e = Vector::new(extent(0), extent(1), extent(2));
+        BoundBox::new(center + -e, center + e)
+    }
+}
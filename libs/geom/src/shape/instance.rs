@@ -0,0 +1,149 @@
+use std::sync::Arc;
+
+use Ray;
+use Transform;
+use super::{Intersection, Interval, Shape, Solid, Surface, SurfaceSample};
+use super::bound_box::{Bound, BoundBox};
+
+
+/// Shape placed into the scene with a transform. The shape itself is shared,
+/// so a single mesh can be instanced many times. Instances of solids are
+/// solids.
+pub struct Instance<S: Shape + ?Sized = Shape> {
+    shape: Arc<S>,
+    /// From the coordinates of the shape to the world coordinates.
+    transform: Transform,
+    bound: BoundBox,
+    scale: f64,
+}
+
+impl<S: Shape + ?Sized> Instance<S> {
+    pub fn new(shape: Arc<S>, transform: Transform) -> Instance<S> {
+        let bound = transform.bound(&shape.bound());
+        Instance {
+            shape: shape,
+            transform: transform,
+            bound: bound,
+            scale: transform.scale(),
+        }
+    }
+
+    /// The ray in the coordinates of the shape and the factor by which
+    /// distances along it are longer.
+    fn local_ray(&self, ray: &Ray) -> (Ray, f64) {
+        let to_local = self.transform.inverse();
+        let direction = to_local.vector(ray.direction.into());
+        let local_ray = Ray {
+            origin: to_local.point(ray.origin),
+            direction: direction.direction(),
+        };
+        (local_ray, direction.length())
+    }
+
+    fn to_world(&self, i: Intersection, ray: &Ray, stretch: f64) -> Intersection {
+        let t = i.t / stretch;
+        let normal = self.transform.normal(i.normal);
+        Intersection::new(t, ray.along(t), i.local_coordinates, normal)
+            .with_tangents(self.transform.vector(i.tangent.into()),
+                           self.transform.vector(i.bitangent.into()))
+            .with_uv_density(i.uv_density / self.scale)
+            .with_material_slot(i.material_slot)
+    }
+}
+
+impl<S: Shape + ?Sized> Shape for Instance<S> {
+    fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+        let (local_ray, stretch) = self.local_ray(ray);
+        self.shape.intersect(&local_ray).map(|i| self.to_world(i, ray, stretch))
+    }
+}
+
+impl<S: Solid + ?Sized> Solid for Instance<S> {
+    fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        let (local_ray, stretch) = self.local_ray(ray);
+        self.shape.intervals(&local_ray).into_iter()
+            .map(|interval| Interval {
+                enter: self.to_world(interval.enter, ray, stretch),
+                exit: self.to_world(interval.exit, ray, stretch),
+            })
+            .collect()
+    }
+}
+
+/// The area is exact only for transforms which scale uniformly, but the
+/// density of the samples accounts for any stretching.
+impl<S: Surface + ?Sized> Surface for Instance<S> {
+    fn area(&self) -> f64 {
+        self.shape.area() * self.scale * self.scale
+    }
+
+    fn sample_surface(&self, u: f64, v: f64) -> SurfaceSample {
+        let s = self.shape.sample_surface(u, v);
+        SurfaceSample {
+            point: self.transform.point(s.point),
+            normal: self.transform.normal(s.normal),
+            pdf: s.pdf / self.transform.area_scale(s.normal),
+        }
+    }
+}
+
+impl<S: Shape + ?Sized> Bound for Instance<S> {
+    fn bound(&self) -> BoundBox {
+        self.bound
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use super::*;
+    use shape::{Shape, Sphere, Quad};
+    use {Point, Ray, Transform, Vector};
+    use utils::props::check_prop2;
+
+    #[test]
+    fn test_instance_of_sphere() {
+        let unit = Arc::new(Sphere::new(Point::new(0.0, 0.0, 0.0), 1.0));
+        let transform = Transform::scaling(Vector::new(2.0, 2.0, 2.0))
+            .then(&Transform::translation(Vector::new(1.0, -1.0, 3.0)));
+        let instance = Instance::new(unit, transform);
+        let sphere = Sphere::new(Point::new(1.0, -1.0, 3.0), 2.0);
+
+        check_prop2(|from: Point, to: Point| {
+            let from = Point::new(from[0] * 10.0, from[1] * 10.0, 10.0);
+            let ray = Ray::from_to(from, to);
+            match (instance.intersect(&ray), sphere.intersect(&ray)) {
+                (None, None) => (),
+                (Some(i), Some(j)) => {
+                    assert!((i.t - j.t).abs() < 1e-9);
+                    assert!((i.point - j.point).is_almost_zero());
+                    assert!(Vector::from(i.normal).is_almost_eq(j.normal.into()));
+                    assert!(Vector::from(i.tangent).is_almost_eq(j.tangent.into()));
+                    assert!((i.uv_density - j.uv_density).abs() < 1e-9);
+                }
+                _ => panic!("instance disagrees with the sphere"),
+            }
+        });
+    }
+
+    #[test]
+    fn test_stretched_surface_samples() {
+        let quad = Arc::new(Quad::new(Point::new(0.0, 0.0, 0.0),
+                                      Vector::new(1.0, 0.0, 0.0),
+                                      Vector::new(0.0, 1.0, 0.0)));
+        let rotation = Transform::rotation(Vector::new(1.0, 0.0, 0.0).direction(), 0.5);
+        let transform = Transform::scaling(Vector::new(3.0, 2.0, 5.0)).then(&rotation);
+        let instance = Instance::new(quad, transform);
+
+        for &(u, v) in &[(0.1, 0.2), (0.5, 0.5), (0.9, 0.3)] {
+            let s = instance.sample_surface(u, v);
+            let ray = Ray::from_to(s.point + Vector::from(s.normal), s.point);
+            let hit = instance.intersect(&ray).unwrap();
+            assert!((hit.point - s.point).is_almost_zero());
+            assert!(Vector::from(hit.normal).is_almost_eq(s.normal.into()));
+            // the quad is stretched into a 3 by 2 rectangle
+            assert!((s.pdf - 1.0 / 6.0).abs() < 1e-9);
+        }
+    }
+}
@@ -1,28 +1,81 @@
-mod bvh;
 mod obj_parser;
+mod ply;
+mod smoothing;
+mod stl;
 
 
 use std::error::Error;
+use std::{f64, fmt};
 use std::io;
+use std::sync::Arc;
 
-use Ray;
-use super::{Triangle, Shape, Intersection};
-use self::bvh::Bvh;
-use self::obj_parser::ObjParser;
+use {Dot, Ray, Vector};
+use super::{Triangle, Shape, Solid, Surface, SurfaceSample, Intersection, Interval, Bvh,
+            BvhOptions};
+use super::bound_box::{Bound, BoundBox};
+
+pub use self::obj_parser::{ObjParser, ParseObjError, ObjErrorKind, FaceGroup};
+pub use self::ply::read_ply;
+pub use self::smoothing::smooth_normals;
+pub use self::stl::read_stl;
+
+
+/// Error of the PLY and STL readers.
+#[derive(Debug)]
+pub struct ParseMeshError {
+    pub description: String,
+}
+
+impl ParseMeshError {
+    fn new<S: Into<String>>(description: S) -> ParseMeshError {
+        ParseMeshError { description: description.into() }
+    }
+}
+
+impl Error for ParseMeshError {
+    fn description(&self) -> &str {
+        &self.description
+    }
+}
+
+impl fmt::Display for ParseMeshError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid mesh file: {}", self.description)
+    }
+}
+
+impl From<io::Error> for ParseMeshError {
+    fn from(e: io::Error) -> ParseMeshError {
+        ParseMeshError::new(e.to_string())
+    }
+}
 
 pub struct Mesh {
     index: Bvh<Triangle>
 }
 
 impl Mesh {
-    pub fn new(triangles: Vec<Triangle>) -> Mesh {
+    pub fn new(triangles: Vec<Triangle>, options: &BvhOptions) -> Mesh {
         Mesh {
-            index: Bvh::new(triangles)
+            index: Bvh::new(triangles, options)
         }
     }
 
-    pub fn from_obj(source: &mut io::Read) -> Result<Mesh, Box<Error>> {
-        ObjParser::new().parse(source)
+    pub fn triangles(&self) -> &[Triangle] {
+        self.index.shapes()
+    }
+
+    pub fn from_obj(source: &mut io::Read, options: &BvhOptions) -> Result<Mesh, Box<Error>> {
+        let triangles = ObjParser::new().parse(source)?;
+        Ok(Mesh::new(triangles, options))
+    }
+
+    pub fn from_ply(source: &mut io::Read, options: &BvhOptions) -> Result<Mesh, Box<Error>> {
+        Ok(Mesh::new(read_ply(source)?, options))
+    }
+
+    pub fn from_stl(source: &mut io::Read, options: &BvhOptions) -> Result<Mesh, Box<Error>> {
+        Ok(Mesh::new(read_stl(source)?, options))
     }
 }
 
@@ -32,3 +85,120 @@ impl Shape for Mesh {
         self.index.intersect(ray)
     }
 }
+
+/// Crossings of the surface alternate between entering and leaving the
+/// mesh, if it is closed.
+impl Solid for Mesh {
+    fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        // start the ray behind the mesh to find the crossings along the
+        // whole line
+        let bound = self.index.bound();
+        let (x, y, z) = (bound.range(0), bound.range(1), bound.range(2));
+        let radius = Vector::new(x.1 - x.0, y.1 - y.0, z.1 - z.0).length() / 2.0;
+        let back = (ray.origin - bound.center()).length() + radius + 1.0;
+        let shifted = Ray {
+            origin: ray.origin + ray.direction * -back,
+            direction: ray.direction,
+        };
+
+        let mut hits = Vec::new();
+        self.index.traverse(&shifted, f64::INFINITY, |triangle| {
+            if let Some(i) = triangle.intersect(&shifted) {
+                hits.push(i);
+            }
+            None
+        });
+        hits.sort();
+        // a crossing through an edge is found in both triangles
+        hits.dedup_by(|a, b| (a.t - b.t).abs() < 1e-9);
+
+        let crossing = |mut i: Intersection, exit: bool| {
+            i.t -= back;
+            if (i.normal.dot(ray.direction) > 0.0) != exit { i.flipped() } else { i }
+        };
+        hits.chunks(2)
+            .filter(|pair| pair.len() == 2)
+            .map(|pair| Interval {
+                enter: crossing(pair[0], false),
+                exit: crossing(pair[1], true),
+            })
+            .collect()
+    }
+}
+
+impl Bound for Mesh {
+    fn bound(&self) -> BoundBox {
+        self.index.bound()
+    }
+}
+
+
+/// Some of the triangles of a shared mesh, such as the faces of an emissive
+/// material, which are sampled by their area without copying them.
+pub struct MeshPart {
+    mesh: Arc<Mesh>,
+    triangles: Vec<usize>,
+    /// Running totals of the areas of the triangles.
+    cdf: Vec<f64>,
+}
+
+impl MeshPart {
+    /// `triangles` are indices into the triangles of the `mesh`.
+    pub fn new(mesh: Arc<Mesh>, triangles: Vec<usize>) -> MeshPart {
+        assert!(!triangles.is_empty(), "mesh part without triangles");
+        let mut area = 0.0;
+        let cdf = triangles.iter()
+            .map(|&k| { area += mesh.triangles()[k].area(); area })
+            .collect();
+        MeshPart {
+            mesh: mesh,
+            triangles: triangles,
+            cdf: cdf,
+        }
+    }
+
+    fn triangle(&self, k: usize) -> &Triangle {
+        &self.mesh.triangles()[self.triangles[k]]
+    }
+}
+
+impl Shape for MeshPart {
+    fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+        (0..self.triangles.len())
+            .filter_map(|k| self.triangle(k).intersect(ray))
+            .min()
+    }
+}
+
+impl Surface for MeshPart {
+    fn area(&self) -> f64 {
+        self.cdf[self.cdf.len() - 1]
+    }
+
+    /// `u` picks the triangle, then the position within its slot picks the
+    /// point of the triangle.
+    fn sample_surface(&self, u: f64, v: f64) -> SurfaceSample {
+        let x = u * self.area();
+        // the first triangle whose running total exceeds `x`
+        let k = match self.cdf.binary_search_by(|total| total.partial_cmp(&x).unwrap()) {
+            Ok(k) => k + 1,
+            Err(k) => k,
+        };
+        let k = k.min(self.cdf.len() - 1);
+        let start = if k == 0 { 0.0 } else { self.cdf[k - 1] };
+        let width = self.cdf[k] - start;
+        let u = if width > 0.0 { ((x - start) / width).max(0.0).min(1.0 - 1e-12) } else { 0.5 };
+        let sample = self.triangle(k).sample_surface(u, v);
+        SurfaceSample {
+            pdf: 1.0 / self.area(),
+            ..sample
+        }
+    }
+}
+
+impl Bound for MeshPart {
+    fn bound(&self) -> BoundBox {
+        (0..self.triangles.len())
+            .fold(BoundBox::empty(), |bound, k| bound.union(&self.triangle(k).bound()))
+    }
+}
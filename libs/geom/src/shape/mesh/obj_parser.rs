@@ -1,33 +1,101 @@
-use std::{io, fmt, num};
+use std::{io, fmt};
 use std::error::Error;
+use std::io::BufRead;
+use std::ops::Range;
 
 use Point;
-use UnitVector;
 use Vector;
 use shape::Triangle;
-use shape::mesh::Mesh;
 
 
 #[derive(Debug)]
-pub struct ParseObjError;
+pub enum ObjErrorKind {
+    Io(io::Error),
+    InvalidNumber(String),
+    /// Statement with too few or too many values, like a vertex with two
+    /// coordinates.
+    WrongValueCount,
+    /// Face vertex which is not one of `v`, `v/vt`, `v//vn` or `v/vt/vn`.
+    InvalidFaceVertex(String),
+    TooFewFaceVertices,
+    /// Zero or a reference to an element not defined before the face.
+    IndexOutOfRange(i64),
+}
+
+#[derive(Debug)]
+pub struct ParseObjError {
+    /// Counted from 1.
+    pub line: usize,
+    pub kind: ObjErrorKind,
+}
 
 impl Error for ParseObjError {
     fn description(&self) -> &str {
-        "Invalid .obj file"
+        match self.kind {
+            ObjErrorKind::Io(_) => "Can't read .obj file",
+            ObjErrorKind::InvalidNumber(_) => "Invalid number in .obj file",
+            ObjErrorKind::WrongValueCount => "Wrong number of values in .obj file",
+            ObjErrorKind::InvalidFaceVertex(_) => "Invalid face vertex in .obj file",
+            ObjErrorKind::TooFewFaceVertices => "Face with less than 3 vertices in .obj file",
+            ObjErrorKind::IndexOutOfRange(_) => "Index out of range in .obj file",
+        }
     }
 }
 
 impl fmt::Display for ParseObjError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        self.description().fmt(f)
+        write!(f, "{} at line {}", self.description(), self.line)?;
+        match self.kind {
+            ObjErrorKind::Io(ref e) => write!(f, ": {}", e),
+            ObjErrorKind::InvalidNumber(ref s) |
+            ObjErrorKind::InvalidFaceVertex(ref s) => write!(f, ": {:?}", s),
+            ObjErrorKind::IndexOutOfRange(i) => write!(f, ": {}", i),
+            _ => Ok(()),
+        }
     }
 }
 
 
+/// Consecutive faces which share the object, the groups, the material and
+/// the smoothing group.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FaceGroup {
+    pub object: Option<String>,
+    pub groups: Vec<String>,
+    pub material: Option<String>,
+    /// Zero when smoothing is off.
+    pub smoothing_group: u32,
+    /// Indices in the parsed faces.
+    pub faces: Range<usize>,
+}
+
+impl FaceGroup {
+    fn same_attributes(&self, other: &FaceGroup) -> bool {
+        self.object == other.object
+            && self.groups == other.groups
+            && self.material == other.material
+            && self.smoothing_group == other.smoothing_group
+    }
+}
+
+
+/// Reads Wavefront .obj files. Polygons are split into triangle fans, faces
+/// with degenerate triangles are skipped and unknown statements ignored.
 pub struct ObjParser {
     pub points: Vec<Point>,
-    pub normals: Vec<UnitVector>,
+    /// Normals as given, may be zero.
+    pub normals: Vec<Vector>,
+    pub uvs: Vec<[f64; 2]>,
     pub faces: Vec<Triangle>,
+    pub groups: Vec<FaceGroup>,
+    /// Names of the materials in the order of their first use. Faces of the
+    /// material `materials[i]` have the material slot `i + 1`, the faces
+    /// before any `usemtl` statement have the slot 0.
+    pub materials: Vec<String>,
+    /// Names of the files given by `mtllib` statements.
+    pub material_libraries: Vec<String>,
+    /// Attributes of the faces which follow.
+    current: FaceGroup,
 }
 
 impl ObjParser {
@@ -35,110 +103,248 @@ impl ObjParser {
         ObjParser {
             points: Vec::new(),
             normals: Vec::new(),
-            faces: Vec::new()
+            uvs: Vec::new(),
+            faces: Vec::new(),
+            groups: Vec::new(),
+            materials: Vec::new(),
+            material_libraries: Vec::new(),
+            current: FaceGroup {
+                object: None,
+                groups: Vec::new(),
+                material: None,
+                smoothing_group: 0,
+                faces: 0..0,
+            },
         }
     }
 
-    pub fn parse(mut self, source: &mut io::Read) -> Result<Mesh, Box<Error>> {
-        let mut s = String::new();
-        source.read_to_string(&mut s)?;
-        for line in s.lines() {
-            if line.starts_with("v ") {
-                self.parse_vertex(line)?;
-            } else if line.starts_with("vn ") {
-                self.parse_normal(line)?;
-            } else if line.starts_with("f ") {
-                self.parse_face(line)?;
-            }
-        }
-
-        Ok(Mesh::new(self.faces))
-    }
-
-    fn parse_vertex(&mut self, s: &str) -> Result<(), Box<Error>> {
-        let coords = ObjParser::parse_coordinates(s)?;
-        self.points.push(Point::new(coords.0, coords.1, coords.2));
-        Ok(())
+    pub fn parse(mut self, source: &mut io::Read) -> Result<Vec<Triangle>, ParseObjError> {
+        self.read(source)?;
+        Ok(self.faces)
     }
 
-    fn parse_normal(&mut self, s: &str) -> Result<(), Box<Error>> {
-        let coords = ObjParser::parse_coordinates(s)?;
-        self.normals.push(Vector::new(coords.0, coords.1, coords.2).direction());
+    /// Adds the contents of the `source` to what the parser has read so far.
+    pub fn read(&mut self, source: &mut io::Read) -> Result<(), ParseObjError> {
+        for (i, line) in io::BufReader::new(source).lines().enumerate() {
+            let error = |kind| ParseObjError { line: i + 1, kind: kind };
+            let line = line.map_err(|e| error(ObjErrorKind::Io(e)))?;
+            self.read_statement(&line).map_err(error)?;
+        }
         Ok(())
     }
 
-    fn parse_face_simple(&mut self, s: &str) -> Result<(), Box<Error>> {
-        let inds = s.split_whitespace()
-            .skip(1)
-            .map(read_index)
-            .collect::<Result<Vec<usize>, num::ParseIntError>>()?;
-
-        if inds.len() != 3 {
-            return Err(Box::new(ParseObjError));
-        }
-        let (a, b, c) = (self.points[inds[0]],
-                         self.points[inds[1]],
-                         self.points[inds[2]]);
-        if Triangle::are_valid_points(a, b, c) {
-            let f = Triangle::new(a, b, c);
-            self.faces.push(f);
+    fn read_statement(&mut self, line: &str) -> Result<(), ObjErrorKind> {
+        let line = match line.find('#') {
+            Some(comment) => &line[..comment],
+            None => line,
+        };
+        let mut words = line.split_whitespace();
+        let statement = match words.next() {
+            Some(statement) => statement,
+            None => return Ok(()),
+        };
+        let args = words.collect::<Vec<_>>();
+        match statement {
+            "v" => {
+                // `w` or vertex colors may follow
+                let coords = read_numbers(&args, 3, 7)?;
+                self.points.push(Point::new(coords[0], coords[1], coords[2]));
+            }
+            "vn" => {
+                let coords = read_numbers(&args, 3, 3)?;
+                self.normals.push(Vector::new(coords[0], coords[1], coords[2]));
+            }
+            "vt" => {
+                let coords = read_numbers(&args, 1, 3)?;
+                self.uvs.push([coords[0], coords.get(1).cloned().unwrap_or(0.0)]);
+            }
+            "f" => self.read_face(&args)?,
+            "o" => self.current.object = Some(args.join(" ")),
+            "g" => self.current.groups = args.iter().map(|s| s.to_string()).collect(),
+            "usemtl" => {
+                let name = args.join(" ");
+                if !self.materials.contains(&name) {
+                    self.materials.push(name.clone());
+                }
+                self.current.material = Some(name);
+            }
+            "mtllib" => self.material_libraries.extend(args.iter().map(|s| s.to_string())),
+            "s" => {
+                self.current.smoothing_group = match args.first() {
+                    None => return Err(ObjErrorKind::WrongValueCount),
+                    Some(&"off") => 0,
+                    Some(s) => s.parse().map_err(|_| ObjErrorKind::InvalidNumber(s.to_string()))?,
+                };
+            }
+            _ => {}
         }
         Ok(())
     }
 
-    fn parse_face_normals(&mut self, s: &str) -> Result<(), Box<Error>> {
-        fn read_group(s: &str) -> Result<(usize, usize, usize), Box<Error>> {
-            let inds = s.split('/')
-                .map(|s| read_index(s))
-                .collect::<Result<Vec<_>, _>>()?;
-            if inds.len() != 3 {
-                return Err(Box::new(ParseObjError));
-            }
-            Ok((inds[0], inds[1], inds[2]))
+    fn read_face(&mut self, args: &[&str]) -> Result<(), ObjErrorKind> {
+        if args.len() < 3 {
+            return Err(ObjErrorKind::TooFewFaceVertices);
         }
-
-        let verts = s.split_whitespace()
-            .skip(1)
-            .map(read_group)
+        let vertices = args.iter()
+            .map(|s| self.read_face_vertex(s))
             .collect::<Result<Vec<_>, _>>()?;
+        let has_normals = vertices.iter().all(|v| v.2.map_or(false, |n| !n.is_almost_zero()));
+        let has_uvs = vertices.iter().all(|v| v.1.is_some());
 
-        if verts.len() != 3 {
-            return Err(Box::new(ParseObjError));
-        }
-        let (a, b, c) = (self.points[verts[0].0],
-                         self.points[verts[1].0],
-                         self.points[verts[2].0]);
-        if Triangle::are_valid_points(a, b, c) {
-            let f = Triangle::with_normals(
-                self.points[verts[0].0], self.points[verts[1].0], self.points[verts[2].0],
-                [self.normals[verts[0].2], self.normals[verts[1].2], self.normals[verts[2].2]]);
-
-            self.faces.push(f);
+        for i in 1..vertices.len() - 1 {
+            let (a, b, c) = (&vertices[0], &vertices[i], &vertices[i + 1]);
+            if !Triangle::are_valid_points(a.0, b.0, c.0) {
+                continue;
+            }
+            let mut triangle = if has_normals {
+                let normals = [a.2.unwrap().direction(),
+                               b.2.unwrap().direction(),
+                               c.2.unwrap().direction()];
+                Triangle::with_normals(a.0, b.0, c.0, normals)
+            } else {
+                Triangle::new(a.0, b.0, c.0)
+            };
+            if has_uvs {
+                triangle = triangle.with_uvs([a.1.unwrap(), b.1.unwrap(), c.1.unwrap()]);
+            }
+            self.push_face(triangle);
         }
         Ok(())
     }
 
-    fn parse_face(&mut self, s: &str) -> Result<(), Box<Error>> {
-        if s.contains('/') {
-            self.parse_face_normals(s)
-        } else {
-            self.parse_face_simple(s)
+    /// Point, texture coordinates and normal of a vertex given as `v`,
+    /// `v/vt`, `v//vn` or `v/vt/vn`.
+    fn read_face_vertex(&self, s: &str)
+        -> Result<(Point, Option<[f64; 2]>, Option<Vector>), ObjErrorKind> {
+        let parts = s.split('/').collect::<Vec<_>>();
+        if parts.len() > 3 || parts[0].is_empty() {
+            return Err(ObjErrorKind::InvalidFaceVertex(s.to_string()));
         }
+        let point = lookup(&self.points, parts[0])?;
+        let uv = match parts.get(1) {
+            None | Some(&"") => None,
+            Some(i) => Some(lookup(&self.uvs, i)?),
+        };
+        let normal = match parts.get(2) {
+            None | Some(&"") => None,
+            Some(i) => Some(lookup(&self.normals, i)?),
+        };
+        Ok((point, uv, normal))
     }
 
-    fn parse_coordinates(s: &str) -> Result<(f64, f64, f64), Box<Error>> {
-        let coords = s.split_whitespace()
-            .skip(1)
-            .map(|s| s.parse::<f64>())
-            .collect::<Result<Vec<_>, _>>()?;
-        if coords.len() != 3 {
-            return Err(Box::new(ParseObjError));
+    fn push_face(&mut self, triangle: Triangle) {
+        let slot = match self.current.material {
+            None => 0,
+            Some(ref name) => 1 + self.materials.iter().position(|m| m == name).unwrap(),
+        };
+        let index = self.faces.len();
+        self.faces.push(triangle.with_material_slot(slot));
+        if let Some(last) = self.groups.last_mut() {
+            if last.same_attributes(&self.current) && last.faces.end == index {
+                last.faces.end += 1;
+                return;
+            }
         }
+        let mut group = self.current.clone();
+        group.faces = index..index + 1;
+        self.groups.push(group);
+    }
+}
+
+fn read_numbers(args: &[&str], min: usize, max: usize) -> Result<Vec<f64>, ObjErrorKind> {
+    if args.len() < min || args.len() > max {
+        return Err(ObjErrorKind::WrongValueCount);
+    }
+    args.iter()
+        .map(|s| s.parse::<f64>().map_err(|_| ObjErrorKind::InvalidNumber(s.to_string())))
+        .collect()
+}
 
-        Ok((coords[0], coords[1], coords[2]))
+/// Element by its index counted from 1, or from the end of the elements
+/// defined so far if it is negative.
+fn lookup<T: Copy>(elements: &[T], index: &str) -> Result<T, ObjErrorKind> {
+    let i = index.parse::<i64>()
+        .map_err(|_| ObjErrorKind::InvalidNumber(index.to_string()))?;
+    let position = if i < 0 { elements.len() as i64 + i } else { i - 1 };
+    if i == 0 || position < 0 || position >= elements.len() as i64 {
+        return Err(ObjErrorKind::IndexOutOfRange(i));
     }
+    Ok(elements[position as usize])
 }
 
-fn read_index(s: &str) -> Result<usize, num::ParseIntError> {
-    s.parse::<usize>().map(|i| i - 1)
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_faces_and_groups() {
+        let source = "# unit square and a pentagon
+            mtllib scene.mtl
+            v 0 0 0
+            v 1 0 0
+            v 1 1 0
+            v 0 1 0
+            vt 0 0
+            vt 1 0
+            vt 1 1
+            vn 0 0 1
+            o square
+            usemtl red
+            f 1/1 2/2 3/3 4/2
+            g back
+            s 1
+            f -4//1 -2//1 -3//-1
+            usemtl blue
+            v 2 2 2 1.0 # with the w coordinate
+            f 1 2 3 4 5
+            f 1/1/1 2/2/1 3/3/1
+        ";
+        let mut parser = ObjParser::new();
+        parser.read(&mut source.as_bytes()).unwrap();
+
+        assert_eq!(parser.faces.len(), 2 + 1 + 3 + 1);
+        assert_eq!(parser.material_libraries, vec!["scene.mtl".to_string()]);
+        assert_eq!(parser.materials, vec!["red".to_string(), "blue".to_string()]);
+        let summary = parser.groups.iter()
+            .map(|g| (g.object.clone().unwrap(), g.groups.clone(), g.material.clone().unwrap(),
+                      g.smoothing_group, g.faces.clone()))
+            .collect::<Vec<_>>();
+        let square = "square".to_string();
+        assert_eq!(summary, vec![
+            (square.clone(), vec![], "red".to_string(), 0, 0..2),
+            (square.clone(), vec!["back".to_string()], "red".to_string(), 1, 2..3),
+            (square.clone(), vec!["back".to_string()], "blue".to_string(), 1, 3..7),
+        ]);
+        assert!(parser.points.len() == 5 && parser.uvs.len() == 3 && parser.normals.len() == 1);
+    }
+
+    #[test]
+    fn test_errors_carry_line_numbers() {
+        let parse = |source: &str| ObjParser::new().parse(&mut source.as_bytes()).unwrap_err();
+        let base = "v 0 0 0\nv 1 0 0\nv 0 1 0\n";
+
+        let e = parse(&format!("{}f 1 2\n", base));
+        assert_eq!(e.line, 4);
+        assert!(match e.kind { ObjErrorKind::TooFewFaceVertices => true, _ => false });
+
+        let e = parse(&format!("{}\n\nf 1 2 4\n", base));
+        assert_eq!(e.line, 6);
+        assert!(match e.kind { ObjErrorKind::IndexOutOfRange(4) => true, _ => false });
+
+        for face in &["f 0 1 2", "f -4 1 2", "f 1/1 2 3", "f 1//1 2 3"] {
+            let e = parse(&format!("{}{}", base, face));
+            assert!(match e.kind { ObjErrorKind::IndexOutOfRange(_) => true, _ => false });
+        }
+        for face in &["f 1/2/3/4 2 3", "f /1 2 3"] {
+            let e = parse(&format!("{}{}", base, face));
+            assert!(match e.kind { ObjErrorKind::InvalidFaceVertex(_) => true, _ => false });
+        }
+
+        let e = parse("v 0 0\n");
+        assert!(e.line == 1 && match e.kind { ObjErrorKind::WrongValueCount => true, _ => false });
+        let e = parse("v 0 0 x\n");
+        assert!(match e.kind { ObjErrorKind::InvalidNumber(ref s) => s == "x", _ => false });
+        assert_eq!(e.to_string(), "Invalid number in .obj file at line 1: \"x\"");
+    }
 }
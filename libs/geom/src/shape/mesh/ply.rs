@@ -0,0 +1,317 @@
+use std::io;
+use std::io::BufRead;
+
+use Point;
+use Vector;
+use shape::Triangle;
+use super::ParseMeshError;
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Encoding {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn from_name(name: &str) -> Option<Scalar> {
+        Some(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+}
+
+enum Property {
+    Scalar(String, Scalar),
+    /// Name, type of the length and type of the items.
+    List(String, Scalar, Scalar),
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+
+/// Reads values of the body of a file one by one.
+struct Body<R: BufRead> {
+    source: R,
+    encoding: Encoding,
+    /// Rest of the current line of an ASCII file, reversed.
+    tokens: Vec<String>,
+}
+
+impl<R: BufRead> Body<R> {
+    fn value(&mut self, scalar: Scalar) -> Result<f64, ParseMeshError> {
+        if self.encoding == Encoding::Ascii {
+            while self.tokens.is_empty() {
+                let mut line = String::new();
+                if self.source.read_line(&mut line)? == 0 {
+                    return Err(ParseMeshError::new("unexpected end of the file"));
+                }
+                self.tokens = line.split_whitespace().rev().map(|s| s.to_string()).collect();
+            }
+            let token = self.tokens.pop().unwrap();
+            return token.parse::<f64>()
+                .map_err(|_| ParseMeshError::new(format!("invalid number {:?}", token)));
+        }
+
+        let mut bytes = [0u8; 8];
+        let size = scalar.size();
+        self.source.read_exact(&mut bytes[..size])?;
+        if self.encoding == Encoding::BinaryBigEndian {
+            bytes[..size].reverse();
+        }
+        let b = bytes;
+        Ok(match scalar {
+            Scalar::I8 => b[0] as i8 as f64,
+            Scalar::U8 => b[0] as f64,
+            Scalar::I16 => i16::from_le_bytes([b[0], b[1]]) as f64,
+            Scalar::U16 => u16::from_le_bytes([b[0], b[1]]) as f64,
+            Scalar::I32 => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            Scalar::U32 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            Scalar::F32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            Scalar::F64 => f64::from_le_bytes(b),
+        })
+    }
+}
+
+
+/// Reads triangles of a PLY file, ASCII or binary. Vertices need `x`, `y`
+/// and `z` properties and may have normals (`nx`, `ny`, `nz`) and texture
+/// coordinates (`u` and `v` or `s` and `t`). Polygons of `vertex_indices`
+/// of faces are split into triangle fans, other elements are skipped.
+pub fn read_ply(source: &mut io::Read) -> Result<Vec<Triangle>, ParseMeshError> {
+    let mut source = io::BufReader::new(source);
+    let (encoding, elements) = read_header(&mut source)?;
+    let mut body = Body { source: source, encoding: encoding, tokens: Vec::new() };
+
+    let mut points = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = Vec::new();
+    let mut faces = Vec::new();
+    for element in &elements {
+        let position = |names: &[&str]| element.properties.iter().position(|p| match *p {
+            Property::Scalar(ref name, _) => names.contains(&&name[..]),
+            _ => false,
+        });
+        let (x, y, z) = (position(&["x"]), position(&["y"]), position(&["z"]));
+        let normal = (position(&["nx"]), position(&["ny"]), position(&["nz"]));
+        let uv = (position(&["u", "s", "texture_u"]), position(&["v", "t", "texture_v"]));
+        let is_vertex = element.name == "vertex";
+        if is_vertex && (x.is_none() || y.is_none() || z.is_none()) {
+            return Err(ParseMeshError::new("vertices should have x, y and z"));
+        }
+
+        for _ in 0..element.count {
+            let mut values = Vec::with_capacity(element.properties.len());
+            for property in &element.properties {
+                match *property {
+                    Property::Scalar(_, scalar) => values.push(body.value(scalar)?),
+                    Property::List(ref name, length, item) => {
+                        let n = body.value(length)?;
+                        let items = (0..n as usize)
+                            .map(|_| body.value(item))
+                            .collect::<Result<Vec<_>, _>>()?;
+                        let is_indices = name == "vertex_indices" || name == "vertex_index";
+                        if element.name == "face" && is_indices {
+                            faces.push(items);
+                        }
+                        values.push(0.0);
+                    }
+                }
+            }
+            if !is_vertex {
+                continue;
+            }
+            points.push(Point::new(values[x.unwrap()], values[y.unwrap()], values[z.unwrap()]));
+            if let (Some(nx), Some(ny), Some(nz)) = normal {
+                normals.push(Vector::new(values[nx], values[ny], values[nz]));
+            }
+            if let (Some(u), Some(v)) = uv {
+                uvs.push([values[u], values[v]]);
+            }
+        }
+    }
+
+    let mut triangles = Vec::new();
+    for face in faces {
+        let indices = face.into_iter()
+            .map(|i| if 0.0 <= i && (i as usize) < points.len() {
+                Ok(i as usize)
+            } else {
+                Err(ParseMeshError::new(format!("vertex index {} out of range", i)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        for k in 2..indices.len() {
+            let v = [indices[0], indices[k - 1], indices[k]];
+            let (a, b, c) = (points[v[0]], points[v[1]], points[v[2]]);
+            if !Triangle::are_valid_points(a, b, c) {
+                continue;
+            }
+            let has_normals = !normals.is_empty()
+                && v.iter().all(|&i| !normals[i].is_almost_zero());
+            let mut triangle = if has_normals {
+                let n = |i: usize| normals[v[i]].direction();
+                Triangle::with_normals(a, b, c, [n(0), n(1), n(2)])
+            } else {
+                Triangle::new(a, b, c)
+            };
+            if !uvs.is_empty() {
+                triangle = triangle.with_uvs([uvs[v[0]], uvs[v[1]], uvs[v[2]]]);
+            }
+            triangles.push(triangle);
+        }
+    }
+    Ok(triangles)
+}
+
+fn read_header<R: BufRead>(source: &mut R) -> Result<(Encoding, Vec<Element>), ParseMeshError> {
+    let mut encoding = None;
+    let mut elements: Vec<Element> = Vec::new();
+    let mut is_first = true;
+    loop {
+        let mut line = String::new();
+        if source.read_line(&mut line)? == 0 {
+            return Err(ParseMeshError::new("no end_header"));
+        }
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        if is_first {
+            if words != ["ply"] {
+                return Err(ParseMeshError::new("not a PLY file"));
+            }
+            is_first = false;
+            continue;
+        }
+        let invalid = || ParseMeshError::new(format!("invalid header line {:?}", line.trim()));
+        match words.first().cloned() {
+            Some("format") => {
+                encoding = Some(match words.get(1).cloned() {
+                    Some("ascii") => Encoding::Ascii,
+                    Some("binary_little_endian") => Encoding::BinaryLittleEndian,
+                    Some("binary_big_endian") => Encoding::BinaryBigEndian,
+                    _ => return Err(invalid()),
+                });
+            }
+            Some("element") if words.len() == 3 => elements.push(Element {
+                name: words[1].to_string(),
+                count: words[2].parse().map_err(|_| invalid())?,
+                properties: Vec::new(),
+            }),
+            Some("property") => {
+                let element = elements.last_mut().ok_or_else(&invalid)?;
+                let scalar = |name: &str| Scalar::from_name(name).ok_or_else(&invalid);
+                element.properties.push(match &words[1..] {
+                    &["list", length, item, name] =>
+                        Property::List(name.to_string(), scalar(length)?, scalar(item)?),
+                    &[scalar_type, name] => Property::Scalar(name.to_string(), scalar(scalar_type)?),
+                    _ => return Err(invalid()),
+                });
+            }
+            Some("end_header") => break,
+            Some("comment") | Some("obj_info") | None => {}
+            _ => return Err(invalid()),
+        }
+    }
+    match encoding {
+        Some(encoding) => Ok((encoding, elements)),
+        None => Err(ParseMeshError::new("no format")),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shape::Shape;
+    use {Point, Ray};
+
+    /// Square made of a quad and a triangle with the normals up, with an
+    /// edge list which the reader should skip.
+    fn binary(little_endian: bool) -> Vec<u8> {
+        let format = if little_endian { "binary_little_endian" } else { "binary_big_endian" };
+        let mut file = format!("ply\nformat {} 1.0\ncomment test\n\
+                                element vertex 5\nproperty float x\nproperty float y\n\
+                                property float z\nproperty double nz\n\
+                                element edge 1\nproperty int vertex1\nproperty int vertex2\n\
+                                element face 2\nproperty list uchar int vertex_indices\n\
+                                end_header\n", format).into_bytes();
+        let mut push = |bytes: &mut [u8]| {
+            if !little_endian {
+                bytes.reverse();
+            }
+            file.extend_from_slice(bytes);
+        };
+        for &(x, y) in &[(0.0f32, 0.0f32), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0), (0.5, 2.0)] {
+            push(&mut x.to_le_bytes());
+            push(&mut y.to_le_bytes());
+            push(&mut 0.0f32.to_le_bytes());
+            push(&mut 1.0f64.to_le_bytes());
+        }
+        push(&mut 0i32.to_le_bytes());
+        push(&mut 1i32.to_le_bytes());
+        push(&mut [4]);
+        for &i in &[0i32, 1, 2, 3] {
+            push(&mut i.to_le_bytes());
+        }
+        push(&mut [3]);
+        for &i in &[3i32, 2, 4] {
+            push(&mut i.to_le_bytes());
+        }
+        file
+    }
+
+    #[test]
+    fn test_read_ply() {
+        let ascii = "ply\nformat ascii 1.0\nelement vertex 5\n\
+                     property float x\nproperty float y\nproperty float z\nproperty double nz\n\
+                     element edge 1\nproperty int vertex1\nproperty int vertex2\n\
+                     element face 2\nproperty list uchar int vertex_index\nend_header\n\
+                     0 0 0 1\n1 0 0 1\n1 1 0 1\n0 1 0 1\n0.5 2 0 1\n0 1\n4 0 1 2 3\n3 3 2 4\n";
+        let sources = vec![ascii.as_bytes().to_vec(), binary(true), binary(false)];
+        for source in sources {
+            let triangles = read_ply(&mut &source[..]).unwrap();
+            assert_eq!(triangles.len(), 3);
+            let ray = Ray::from_to(Point::new(0.5, 1.5, 1.0), Point::new(0.5, 1.5, 0.0));
+            let hits = triangles.iter().filter_map(|t| t.intersect(&ray)).collect::<Vec<_>>();
+            assert_eq!(hits.len(), 1);
+            assert!((hits[0].point - Point::new(0.5, 1.5, 0.0)).is_almost_zero());
+        }
+
+        let truncated = &binary(true)[..200];
+        assert!(read_ply(&mut &truncated[..]).is_err());
+        let out_of_range = ascii.replace("3 3 2 4", "3 3 2 5");
+        assert!(read_ply(&mut out_of_range.as_bytes()).is_err());
+    }
+}
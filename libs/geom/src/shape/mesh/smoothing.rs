@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+use std::iter::FromIterator;
+
+use {Cross, Dot, Point, Vector};
+use shape::Triangle;
+use shape::bound_box::BoundBox;
+
+
+/// Sets smooth normals of the triangles which have no vertex normals, or of
+/// all of them if `recompute`. The normal at a corner averages the normals
+/// of the faces around its vertex, weighted by their angles at the vertex.
+/// Faces more than `crease_angle` radians away from the face of the corner
+/// are left out, so the edges between them stay sharp.
+///
+/// Corners of faces are the same vertex if their positions are equal up to
+/// rounding errors.
+pub fn smooth_normals(triangles: &mut [Triangle], crease_angle: f64, recompute: bool) {
+    let bound = BoundBox::from_iter(triangles.iter().flat_map(|t| t.vertices().to_vec()));
+    let size = (0..3)
+        .map(|axis| { let (min, max) = bound.range(axis); max - min })
+        .fold(0.0, f64::max);
+    let precision = size * 1e-9;
+    let key = |p: Point| {
+        let round = |x: f64| (x / precision).round() as i64;
+        [round(p[0]), round(p[1]), round(p[2])]
+    };
+
+    // unit normals of the faces around each vertex with their angles
+    let mut faces: HashMap<[i64; 3], Vec<(Vector, f64)>> = HashMap::new();
+    for triangle in triangles.iter() {
+        let v = triangle.vertices();
+        let normal = face_normal(&v);
+        for i in 0..3 {
+            let a = v[(i + 1) % 3] - v[i];
+            let b = v[(i + 2) % 3] - v[i];
+            let cos = a.dot(b) / (a.length() * b.length());
+            let angle = cos.max(-1.0).min(1.0).acos();
+            faces.entry(key(v[i])).or_insert_with(Vec::new).push((normal, angle));
+        }
+    }
+
+    let min_cos = crease_angle.cos() - 1e-9;
+    for triangle in triangles.iter_mut() {
+        if triangle.has_vertex_normals() && !recompute {
+            continue;
+        }
+        let v = triangle.vertices();
+        let normal = face_normal(&v);
+        let corner = |p: Point| {
+            let sum = faces[&key(p)].iter()
+                .filter(|&&(n, _)| n.dot(normal) >= min_cos)
+                .fold(Vector::new(0.0, 0.0, 0.0), |sum, &(n, angle)| sum + n * angle);
+            if sum.is_almost_zero() { normal.direction() } else { sum.direction() }
+        };
+        triangle.set_vertex_normals([corner(v[0]), corner(v[1]), corner(v[2])]);
+    }
+}
+
+fn face_normal(vertices: &[Point; 3]) -> Vector {
+    let n = (vertices[1] - vertices[0]).cross(vertices[2] - vertices[0]);
+    n / n.length()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use super::*;
+    use shape::{Shape, Triangle};
+    use {Point, Ray, Vector};
+
+    /// Normal of the first triangle at a point near its corner on the ridge
+    /// of a roof with two faces at right angles.
+    fn normal_near_ridge(crease_angle: f64, recompute: bool, with_normals: bool) -> Vector {
+        let ridge = (Point::new(0.0, 1.0, 0.0), Point::new(0.0, 1.0, 1.0));
+        let left = Point::new(-1.0, 0.0, 0.0);
+        let right = Point::new(1.0, 0.0, 0.0);
+        let mut triangles = vec![Triangle::new(left, ridge.1, ridge.0),
+                                 Triangle::new(right, ridge.0, ridge.1)];
+        if with_normals {
+            let up = Vector::new(0.0, 1.0, 0.0).direction();
+            triangles[0] = Triangle::with_normals(left, ridge.1, ridge.0, [up; 3]);
+        }
+        smooth_normals(&mut triangles, crease_angle, recompute);
+
+        let ray = Ray::from_to(Point::new(-0.02, 2.0, 0.01), Point::new(-0.02, 0.0, 0.01));
+        Vector::from(triangles[0].intersect(&ray).unwrap().normal)
+    }
+
+    #[test]
+    fn test_smooth_normals() {
+        let face = Vector::new(-1.0, 1.0, 0.0).direction();
+        let smooth = Vector::new(0.0, 1.0, 0.0);
+
+        let n = normal_near_ridge(PI / 4.0, false, false);
+        assert!((n - Vector::from(face)).is_almost_zero());
+        let n = normal_near_ridge(PI * 0.6, false, false);
+        assert!(n.dot(smooth) > 0.99);
+        assert!(n.dot(smooth) < 1.0 - 1e-6);
+
+        // normals of the file are kept unless they are recomputed
+        let n = normal_near_ridge(PI / 4.0, false, true);
+        assert!((n - smooth).is_almost_zero());
+        let n = normal_near_ridge(PI / 4.0, true, true);
+        assert!((n - Vector::from(face)).is_almost_zero());
+    }
+}
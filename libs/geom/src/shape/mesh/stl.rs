@@ -0,0 +1,132 @@
+use std::io;
+use std::io::BufRead;
+
+use Point;
+use shape::Triangle;
+use super::ParseMeshError;
+
+
+/// Reads triangles of an ASCII or binary STL file. Binary files may start
+/// with `solid` too, so the text has to have a facet in its beginning to be
+/// read as ASCII. The normals of the file are ignored, the triangles are
+/// flat.
+pub fn read_stl(source: &mut io::Read) -> Result<Vec<Triangle>, ParseMeshError> {
+    let mut source = io::BufReader::new(source);
+    let is_ascii = {
+        let start = source.fill_buf()?;
+        let text = String::from_utf8_lossy(start);
+        text.trim_start().starts_with("solid") && text.contains("facet")
+    };
+    if is_ascii {
+        read_ascii(source)
+    } else {
+        read_binary(source)
+    }
+}
+
+fn read_ascii<R: BufRead>(source: R) -> Result<Vec<Triangle>, ParseMeshError> {
+    let mut triangles = Vec::new();
+    let mut vertices = Vec::new();
+    for line in source.lines() {
+        let line = line?;
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        match words.first().cloned() {
+            Some("vertex") if words.len() == 4 => {
+                let number = |s: &str| s.parse::<f64>()
+                    .map_err(|_| ParseMeshError::new(format!("invalid number {:?}", s)));
+                vertices.push(Point::new(number(words[1])?, number(words[2])?, number(words[3])?));
+            }
+            Some("vertex") => return Err(ParseMeshError::new("vertex should have 3 coordinates")),
+            Some("endloop") => {
+                if vertices.len() < 3 {
+                    return Err(ParseMeshError::new("facet with less than 3 vertices"));
+                }
+                for k in 2..vertices.len() {
+                    push_triangle(&mut triangles, vertices[0], vertices[k - 1], vertices[k]);
+                }
+                vertices.clear();
+            }
+            _ => {}
+        }
+    }
+    Ok(triangles)
+}
+
+fn read_binary<R: BufRead>(mut source: R) -> Result<Vec<Triangle>, ParseMeshError> {
+    let mut header = [0u8; 84];
+    source.read_exact(&mut header)?;
+    let count = u32::from_le_bytes([header[80], header[81], header[82], header[83]]);
+
+    let mut triangles = Vec::new();
+    // normal, 3 vertices and the attribute byte count
+    let mut record = [0u8; 50];
+    for _ in 0..count {
+        source.read_exact(&mut record)?;
+        let value = |i: usize| {
+            let b = &record[12 + 4 * i..];
+            f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64
+        };
+        let vertex = |i: usize| Point::new(value(3 * i), value(3 * i + 1), value(3 * i + 2));
+        push_triangle(&mut triangles, vertex(0), vertex(1), vertex(2));
+    }
+    Ok(triangles)
+}
+
+fn push_triangle(triangles: &mut Vec<Triangle>, a: Point, b: Point, c: Point) {
+    if Triangle::are_valid_points(a, b, c) {
+        triangles.push(Triangle::new(a, b, c));
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shape::Shape;
+    use {Point, Ray};
+
+    #[test]
+    fn test_read_stl() {
+        let ascii = "solid square
+            facet normal 0 0 1
+              outer loop
+                vertex 0 0 0
+                vertex 1 0 0
+                vertex 1 1 0
+              endloop
+            endfacet
+            facet normal 0 0 1
+              outer loop
+                vertex 0 0 0
+                vertex 1 1 0
+                vertex 0 1 0
+              endloop
+            endfacet
+            endsolid square
+        ";
+
+        // binary files can start with "solid" as well
+        let mut binary = b"solid square".to_vec();
+        binary.resize(80, b' ');
+        binary.extend_from_slice(&2u32.to_le_bytes());
+        for vertices in &[[0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0],
+                          [0.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 0.0]] {
+            for v in [0.0f32, 0.0, 1.0].iter().chain(vertices.iter()) {
+                binary.extend_from_slice(&v.to_le_bytes());
+            }
+            binary.extend_from_slice(&[0, 0]);
+        }
+
+        for source in vec![ascii.as_bytes().to_vec(), binary.clone()] {
+            let triangles = read_stl(&mut &source[..]).unwrap();
+            assert_eq!(triangles.len(), 2);
+            for &(x, y) in &[(0.2, 0.7), (0.7, 0.2)] {
+                let ray = Ray::from_to(Point::new(x, y, 1.0), Point::new(x, y, 0.0));
+                assert!(triangles.iter().any(|t| t.intersect(&ray).is_some()));
+            }
+        }
+
+        let truncated = &binary[..130];
+        assert!(read_stl(&mut &truncated[..]).is_err());
+    }
+}
@@ -1,25 +1,63 @@
 use std::cmp::{Ordering};
 use Point;
 use UnitVector;
+use Vector;
+use Cross;
+use Dot;
 use Ray;
 
+mod bvh;
+mod cone;
+mod csg;
+mod cuboid;
+mod cylinder;
+mod disk;
+mod frame;
+mod instance;
 mod mesh;
 mod plane;
+mod quad;
+mod sdf;
 mod sphere;
+mod torus;
 mod triangle;
 mod bound_box;
 
-pub use self::mesh::Mesh;
+pub use self::bound_box::{Bound, BoundBox};
+pub use self::bvh::{Bvh, BoxedBvh, BvhOptions, SplitMethod};
+pub use self::cone::Cone;
+pub use self::csg::{Csg, CsgOperation};
+pub use self::cuboid::Cuboid;
+pub use self::cylinder::Cylinder;
+pub use self::disk::Disk;
+pub use self::instance::Instance;
+pub use self::mesh::{Mesh, MeshPart, ObjParser, ParseObjError, ObjErrorKind, FaceGroup,
+                     ParseMeshError, read_ply, read_stl, smooth_normals};
 pub use self::plane::Plane;
+pub use self::quad::Quad;
+pub use self::sdf::{Sdf, Implicit};
 pub use self::sphere::Sphere;
+pub use self::torus::Torus;
 pub use self::triangle::Triangle;
 
 #[derive(Debug, Clone, Copy)]
 pub struct Intersection {
     pub t: f64,
     pub point: Point,
+    /// Texture coordinates of the point.
     pub local_coordinates: [f64; 2],
     pub normal: UnitVector,
+    /// Tangent frame of the surface: `tangent` follows the direction in
+    /// which `u` texture coordinate grows, `bitangent` is orthogonal to it
+    /// and points to the side where `v` grows.
+    pub tangent: UnitVector,
+    pub bitangent: UnitVector,
+    /// How fast texture coordinates change along the surface, in uv units
+    /// per unit of length. Zero if the shape doesn't know it.
+    pub uv_density: f64,
+    /// Which of the materials of a shape made of several ones is hit, like
+    /// the materials of the faces of a mesh. Zero for other shapes.
+    pub material_slot: usize,
 }
 
 impl Intersection {
@@ -29,13 +67,51 @@ impl Intersection {
                 normal: UnitVector) -> Intersection {
 
         assert!(!t.is_nan());
+        let (tangent, bitangent) = normal.orthonormal_basis();
         Intersection {
             t: t,
             point: point,
             local_coordinates: local_coordinates,
-            normal: normal
+            normal: normal,
+            tangent: tangent,
+            bitangent: bitangent,
+            uv_density: 0.0,
+            material_slot: 0,
         }
     }
+
+    /// Sets the tangent frame from the derivatives of the point with respect
+    /// to the texture coordinates. The frame is made orthogonal to the
+    /// normal, an arbitrary frame is kept if `dpdu` is degenerate.
+    pub fn with_tangents(mut self, dpdu: Vector, dpdv: Vector) -> Intersection {
+        let tangent = dpdu - self.normal * self.normal.dot(dpdu);
+        if tangent.is_almost_zero() {
+            return self;
+        }
+        self.tangent = tangent.direction();
+        self.bitangent = self.normal.cross(self.tangent).direction();
+        if self.bitangent.dot(dpdv) < 0.0 {
+            self.bitangent = -self.bitangent;
+        }
+        self
+    }
+
+    pub fn with_uv_density(mut self, uv_density: f64) -> Intersection {
+        self.uv_density = uv_density;
+        self
+    }
+
+    pub fn with_material_slot(mut self, material_slot: usize) -> Intersection {
+        self.material_slot = material_slot;
+        self
+    }
+
+    /// The same point with the normal turned to the other side.
+    pub fn flipped(mut self) -> Intersection {
+        self.normal = -self.normal;
+        self.bitangent = -self.bitangent;
+        self
+    }
 }
 
 impl Ord for Intersection {
@@ -59,6 +135,63 @@ impl PartialOrd for Intersection {
 impl Eq for Intersection {}
 
 
-pub trait Shape: Send + Sync {
+/// Every shape has a bound, shapes extending to infinity report
+/// `BoundBox::infinite`.
+pub trait Shape: Bound + Send + Sync {
     fn intersect(&self, ray: &Ray) -> Option<Intersection>;
 }
+
+
+/// Part of a ray inside a solid. `t` of the points can be negative.
+#[derive(Debug, Clone, Copy)]
+pub struct Interval {
+    pub enter: Intersection,
+    pub exit: Intersection,
+}
+
+/// Closed shape which divides the space into the inside and the outside.
+pub trait Solid: Shape {
+    /// All the intervals along the whole line of the ray, sorted and
+    /// disjoint. Normals point outwards.
+    fn intervals(&self, ray: &Ray) -> Vec<Interval>;
+}
+
+
+/// Point chosen on a surface, with the probability density of choosing it
+/// with respect to the surface area.
+#[derive(Debug, Clone, Copy)]
+pub struct SurfaceSample {
+    pub point: Point,
+    /// The normal of the surface on the same side as the normals of its
+    /// intersections.
+    pub normal: UnitVector,
+    pub pdf: f64,
+}
+
+/// Shape whose surface can be sampled, such as the shapes which emit light.
+pub trait Surface: Shape {
+    /// Area of the surface. It is only approximate for the shapes which
+    /// can't be sampled uniformly.
+    fn area(&self) -> f64;
+
+    /// Maps `u` and `v` in `[0, 1)` to a point of the surface.
+    fn sample_surface(&self, u: f64, v: f64) -> SurfaceSample;
+}
+
+
+/// The first crossing in front of the origin of the ray among the `hits`
+/// sorted along its line.
+fn first_in_front(hits: Vec<Intersection>) -> Option<Intersection> {
+    hits.into_iter().find(|i| i.t > 0.0)
+}
+
+/// Pairs the sorted crossings of the surface of a solid, which alternate
+/// between entering and leaving it.
+fn pair_crossings(mut hits: Vec<Intersection>) -> Vec<Interval> {
+    // a crossing through an edge or a rim is found on both sides of it
+    hits.dedup_by(|a, b| (a.t - b.t).abs() < 1e-9);
+    hits.chunks(2)
+        .filter(|pair| pair.len() == 2)
+        .map(|pair| Interval { enter: pair[0], exit: pair[1] })
+        .collect()
+}
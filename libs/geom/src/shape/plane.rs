@@ -3,11 +3,15 @@ use UnitVector;
 use Dot;
 use Ray;
 use super::{Intersection, Shape};
+use super::bound_box::{Bound, BoundBox};
 
 
+/// Texture coordinates of a plane are the coordinates of the point in a
+/// tangent frame at `origin`, so textures tile the plane with period one.
 pub struct Plane {
     origin: Point,
     normal: UnitVector,
+    tangents: (UnitVector, UnitVector),
 }
 
 
@@ -15,7 +19,8 @@ impl Plane {
     pub fn new(origin: Point, normal: UnitVector) -> Plane {
         Plane {
             origin: origin,
-            normal: normal
+            normal: normal,
+            tangents: normal.orthonormal_basis(),
         }
     }
 }
@@ -29,15 +34,26 @@ impl Shape for Plane {
         let o = ray.origin - self.origin;
         // o.dot(self.normal) + t * ray.direction.dot(self.normal) = 0
         let t = -o.dot(self.normal) / ray.direction.dot(self.normal);
-        if t < 0.0 {
+        // Rays parallel to the plane give infinite or NaN `t`.
+        if !(t >= 0.0 && t.is_finite()) {
             None
         } else {
-            let local_coordinates = [0.0, 0.0];
-            Some(Intersection::new(t, ray.along(t), local_coordinates, self.normal))
+            let point = ray.along(t);
+            let (u, v) = self.tangents;
+            let local_coordinates = [(point - self.origin).dot(u), (point - self.origin).dot(v)];
+            Some(Intersection::new(t, point, local_coordinates, self.normal)
+                 .with_tangents(u.into(), v.into())
+                 .with_uv_density(1.0))
         }
     }
 }
 
+impl Bound for Plane {
+    fn bound(&self) -> BoundBox {
+        BoundBox::infinite()
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
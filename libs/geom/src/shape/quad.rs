@@ -0,0 +1,109 @@
+use Point;
+use UnitVector;
+use Vector;
+use Cross;
+use Dot;
+use Ray;
+use super::{Intersection, Shape, Surface, SurfaceSample};
+use super::bound_box::{Bound, BoundBox};
+
+
+/// Parallelogram spanned by the edges `u` and `v` from the `corner`, which
+/// are also the directions of the texture coordinates.
+pub struct Quad {
+    corner: Point,
+    u: Vector,
+    v: Vector,
+    normal: UnitVector,
+    /// Normal scaled so that the coordinates of a point along the edges are
+    /// given by the triple products with it.
+    w: Vector,
+}
+
+impl Quad {
+    pub fn new(corner: Point, u: Vector, v: Vector) -> Quad {
+        let n = u.cross(v);
+        assert!(!n.is_almost_zero(), "edges of a quad should not be parallel");
+        Quad {
+            corner: corner,
+            u: u,
+            v: v,
+            normal: n.direction(),
+            w: n / n.dot(n),
+        }
+    }
+}
+
+impl Shape for Quad {
+    fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+        let t = (self.corner - ray.origin).dot(self.normal) / ray.direction.dot(self.normal);
+        // rays parallel to the quad give infinite or NaN `t`
+        if !t.is_finite() || t < 0.0 {
+            return None;
+        }
+        let point = ray.along(t);
+        let p = point - self.corner;
+        let alpha = self.w.dot(p.cross(self.v));
+        let beta = self.w.dot(self.u.cross(p));
+        let inside = |x: f64| (0.0..=1.0).contains(&x);
+        if !inside(alpha) || !inside(beta) {
+            return None;
+        }
+        Some(Intersection::new(t, point, [alpha, beta], self.normal)
+             .with_tangents(self.u, self.v)
+             .with_uv_density(1.0 / self.u.length()))
+    }
+}
+
+impl Surface for Quad {
+    fn area(&self) -> f64 {
+        self.u.cross(self.v).length()
+    }
+
+    fn sample_surface(&self, u: f64, v: f64) -> SurfaceSample {
+        SurfaceSample {
+            point: self.corner + self.u * u + self.v * v,
+            normal: self.normal,
+            pdf: 1.0 / self.area(),
+        }
+    }
+}
+
+impl Bound for Quad {
+    fn bound(&self) -> BoundBox {
+        let c = self.corner;
+        vec![c, c + self.u, c + self.v, c + self.u + self.v].into_iter().collect()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {Point, Ray, Vector};
+    use shape::Shape;
+
+    #[test]
+    fn hit_on_quad() {
+        // the quad lies in the plane y = z
+        let corner = Point::new(0.0, 0.0, 0.0);
+        let (u, v) = (Vector::new(2.0, 0.0, 0.0), Vector::new(1.0, 1.0, 1.0));
+        let quad = Quad::new(corner, u, v);
+
+        let ray = Ray::from_to(Point::new(1.5, 0.5, 5.0), Point::new(1.5, 0.5, 0.0));
+        let i = quad.intersect(&ray).unwrap();
+        assert!((i.t - 4.5).abs() < 1e-9);
+        assert!((i.point - Point::new(1.5, 0.5, 0.5)).is_almost_zero());
+        assert!((i.local_coordinates[0] - 0.5).abs() < 1e-9);
+        assert!((i.local_coordinates[1] - 0.5).abs() < 1e-9);
+        let normal = Vector::new(0.0, -1.0, 1.0).direction();
+        assert!((Vector::from(i.normal) - Vector::from(normal)).is_almost_zero());
+        assert!((Vector::from(i.tangent) - Vector::new(1.0, 0.0, 0.0)).is_almost_zero());
+
+        // past the far end of `u`, and away from the quad
+        let ray = Ray::from_to(Point::new(3.5, 0.5, 5.0), Point::new(3.5, 0.5, 0.0));
+        assert!(quad.intersect(&ray).is_none());
+        let ray = Ray::from_to(Point::new(1.5, 0.5, 5.0), Point::new(1.5, 0.5, 10.0));
+        assert!(quad.intersect(&ray).is_none());
+    }
+}
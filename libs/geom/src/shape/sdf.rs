@@ -0,0 +1,322 @@
+use std::f64::consts::PI;
+
+use Point;
+use UnitVector;
+use Vector;
+use Dot;
+use Ray;
+use super::{Intersection, Shape};
+use super::bound_box::{Bound, BoundBox};
+
+
+/// Sphere tracing gives up after this many steps, which happens with rays
+/// grazing the surface.
+const MAX_STEPS: usize = 512;
+/// Points closer to the surface are taken for hits.
+const HIT_DISTANCE: f64 = 1e-5;
+
+
+/// Signed distance function: the distance to the surface, negative inside
+/// it. The combinations may only give a lower bound of the distance, which is
+/// still enough for sphere tracing.
+#[derive(Debug, Clone)]
+pub enum Sdf {
+    Sphere {
+        center: Point,
+        radius: f64,
+    },
+    Cuboid {
+        center: Point,
+        half_size: Vector,
+    },
+    /// Cuboid with its edges rounded by the `radius`, within the same
+    /// `half_size`.
+    RoundedCuboid {
+        center: Point,
+        half_size: Vector,
+        radius: f64,
+    },
+    /// Torus around the `y` axis through its center.
+    Torus {
+        center: Point,
+        major_radius: f64,
+        minor_radius: f64,
+    },
+    /// Segment between `a` and `b` thickened by the `radius`.
+    Capsule {
+        a: Point,
+        b: Point,
+        radius: f64,
+    },
+    /// Union blending the shapes where they are closer than `smoothness`.
+    SmoothUnion {
+        smoothness: f64,
+        left: Box<Sdf>,
+        right: Box<Sdf>,
+    },
+    /// The `left` shape with the `right` one carved out of it, rounding the
+    /// edges of the cut by `smoothness`.
+    SmoothSubtraction {
+        smoothness: f64,
+        left: Box<Sdf>,
+        right: Box<Sdf>,
+    },
+    /// Copies of the shape shifted by multiples of the `period`, `copies` more
+    /// of them on each side of the original along each axis. The shape should
+    /// fit in its cell around the origin for the distance to hold.
+    Repeat {
+        period: Vector,
+        copies: [u32; 3],
+        sdf: Box<Sdf>,
+    },
+}
+
+impl Sdf {
+    pub fn distance(&self, p: Point) -> f64 {
+        match *self {
+            Sdf::Sphere { center, radius } => (p - center).length() - radius,
+            Sdf::Cuboid { center, half_size } => cuboid_distance(p - center, half_size),
+            Sdf::RoundedCuboid { center, half_size, radius } => {
+                let inner = half_size - Vector::new(radius, radius, radius);
+                cuboid_distance(p - center, inner) - radius
+            }
+            Sdf::Torus { center, major_radius, minor_radius } => {
+                let q = p - center;
+                let rho = (q.x * q.x + q.z * q.z).sqrt() - major_radius;
+                (rho * rho + q.y * q.y).sqrt() - minor_radius
+            }
+            Sdf::Capsule { a, b, radius } => {
+                let (pa, ba) = (p - a, b - a);
+                let h = (pa.dot(ba) / ba.dot(ba)).max(0.0).min(1.0);
+                (pa - ba * h).length() - radius
+            }
+            Sdf::SmoothUnion { smoothness: k, ref left, ref right } => {
+                let (d1, d2) = (left.distance(p), right.distance(p));
+                if k == 0.0 {
+                    return d1.min(d2);
+                }
+                let h = (0.5 + 0.5 * (d2 - d1) / k).max(0.0).min(1.0);
+                d2 * (1.0 - h) + d1 * h - k * h * (1.0 - h)
+            }
+            Sdf::SmoothSubtraction { smoothness: k, ref left, ref right } => {
+                let (d1, d2) = (left.distance(p), right.distance(p));
+                if k == 0.0 {
+                    return d1.max(-d2);
+                }
+                let h = (0.5 - 0.5 * (d1 + d2) / k).max(0.0).min(1.0);
+                d1 * (1.0 - h) - d2 * h + k * h * (1.0 - h)
+            }
+            Sdf::Repeat { period, copies, ref sdf } => {
+                let cell = |axis: usize| {
+                    let s = period[axis as u8];
+                    if s == 0.0 {
+                        return 0.0;
+                    }
+                    let n = copies[axis] as f64;
+                    s * (p[axis as u8] / s).round().max(-n).min(n)
+                };
+                sdf.distance(p + -Vector::new(cell(0), cell(1), cell(2)))
+            }
+        }
+    }
+}
+
+/// Distance to the surface of the cuboid around the origin.
+fn cuboid_distance(p: Vector, half_size: Vector) -> f64 {
+    let q = Vector::new(p.x.abs() - half_size.x,
+                        p.y.abs() - half_size.y,
+                        p.z.abs() - half_size.z);
+    let outside = Vector::new(q.x.max(0.0), q.y.max(0.0), q.z.max(0.0)).length();
+    let inside = q.x.max(q.y).max(q.z).min(0.0);
+    outside + inside
+}
+
+fn expand(bound: BoundBox, by: Vector) -> BoundBox {
+    let (x, y, z) = (bound.range(0), bound.range(1), bound.range(2));
+    BoundBox::new(Point::new(x.0 - by.x, y.0 - by.y, z.0 - by.z),
+                  Point::new(x.1 + by.x, y.1 + by.y, z.1 + by.z))
+}
+
+impl Bound for Sdf {
+    fn bound(&self) -> BoundBox {
+        match *self {
+            Sdf::Sphere { center, radius } =>
+                expand(center.bound(), Vector::new(radius, radius, radius)),
+            Sdf::Cuboid { center, half_size } |
+            Sdf::RoundedCuboid { center, half_size, .. } => expand(center.bound(), half_size),
+            Sdf::Torus { center, major_radius, minor_radius } => {
+                let r = major_radius + minor_radius;
+                expand(center.bound(), Vector::new(r, minor_radius, r))
+            }
+            Sdf::Capsule { a, b, radius } =>
+                expand(a.bound().union(&b.bound()), Vector::new(radius, radius, radius)),
+            // blending lowers the distance by at most a quarter of `k`
+            Sdf::SmoothUnion { smoothness: k, ref left, ref right } =>
+                expand(left.bound().union(&right.bound()), Vector::new(k, k, k) / 4.0),
+            Sdf::SmoothSubtraction { ref left, .. } => left.bound(),
+            Sdf::Repeat { period, copies, ref sdf } => {
+                let reach = |axis: usize| (period[axis as u8] * copies[axis] as f64).abs();
+                expand(sdf.bound(), Vector::new(reach(0), reach(1), reach(2)))
+            }
+        }
+    }
+}
+
+
+/// Surface where the signed distance function is zero, intersected by
+/// sphere tracing. Texture coordinates map the direction of the normal as on
+/// the `Sphere`.
+pub struct Implicit {
+    sdf: Sdf,
+    bound: BoundBox,
+}
+
+impl Implicit {
+    pub fn new(sdf: Sdf) -> Implicit {
+        Implicit {
+            bound: sdf.bound(),
+            sdf: sdf,
+        }
+    }
+
+    fn normal_at(&self, p: Point) -> Option<UnitVector> {
+        let h = HIT_DISTANCE;
+        let derivative = |step: Vector| {
+            (self.sdf.distance(p + step) - self.sdf.distance(p + -step)) / (2.0 * h)
+        };
+        let gradient = Vector::new(derivative(Vector::new(h, 0.0, 0.0)),
+                                   derivative(Vector::new(0.0, h, 0.0)),
+                                   derivative(Vector::new(0.0, 0.0, h)));
+        if gradient.is_almost_zero() {
+            None
+        } else {
+            Some(gradient.direction())
+        }
+    }
+
+    fn intersection_at(&self, ray: &Ray, t: f64) -> Intersection {
+        let point = ray.along(t);
+        // the gradient vanishes only at creases, which face the ray anyway
+        let normal = self.normal_at(point).unwrap_or(-ray.direction);
+        let u = 0.5 + normal[0].atan2(normal[2]) / (2.0 * PI);
+        let v = 0.5 + normal[1].max(-1.0).min(1.0).asin() / PI;
+        Intersection::new(t, point, [u, v], normal)
+    }
+}
+
+impl Shape for Implicit {
+    fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+        let (mut t, t_far) = match self.bound.ray_range(ray) {
+            Some(range) => range,
+            None => return None,
+        };
+        // a ray leaving the surface first has to get away from it
+        let mut off_surface = false;
+        for _ in 0..MAX_STEPS {
+            if t > t_far {
+                return None;
+            }
+            let d = self.sdf.distance(ray.along(t)).abs();
+            if d < HIT_DISTANCE {
+                if off_surface {
+                    return Some(self.intersection_at(ray, t));
+                }
+            } else {
+                off_surface = true;
+            }
+            t += d.max(HIT_DISTANCE);
+        }
+        None
+    }
+}
+
+impl Bound for Implicit {
+    fn bound(&self) -> BoundBox {
+        self.bound
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {Point, Ray, Vector, Cross, Dot};
+    use shape::{Shape, Sphere};
+    use utils::props::check_prop2;
+
+    #[test]
+    fn hit_on_implicit_sphere() {
+        let mut hits = 0;
+        let mut misses = 0;
+        let center = Point::new(0.5, 0.0, 0.0);
+        let sphere = Sphere::new(center, 0.5);
+        let implicit = Implicit::new(Sdf::Sphere { center: center, radius: 0.5 });
+
+        check_prop2(|from: Point, to: Point| {
+            let from = Point::new(from[0] * 4.0 - 2.0, from[1] * 4.0 - 2.0, -5.0);
+            let ray = Ray::from_to(from, to);
+
+            match (sphere.intersect(&ray), implicit.intersect(&ray)) {
+                (None, None) => misses += 1,
+                (Some(expected), Some(i)) => {
+                    hits += 1;
+                    // sphere tracing stops short of the surface by less than
+                    // `HIT_DISTANCE` along the normal
+                    let cos = -expected.normal.dot(ray.direction);
+                    assert!((i.t - expected.t).abs() * cos < 1e-4);
+                    assert!(ray.direction.cross(i.point - ray.origin).is_almost_zero());
+                    assert!(i.normal.dot(expected.normal) > 1.0 - 1e-4);
+                    // a ray leaving the surface finds the other side
+                    if cos > 0.1 {
+                        let inside = Ray::from_to(i.point, i.point + Vector::from(ray.direction));
+                        let exit = implicit.intersect(&inside).expect("no exit");
+                        assert!(((exit.point - center).length() - 0.5).abs() < 1e-4);
+                        assert!(exit.t > 0.05);
+                    }
+                }
+                // rays grazing the surface may be told apart
+                (Some(expected), None) => assert!(expected.normal.dot(ray.direction) > -1e-2),
+                (None, Some(i)) => assert!(i.normal.dot(ray.direction) > -1e-2),
+            }
+        });
+
+        assert!(hits > 1);
+        assert!(misses > 1);
+    }
+
+    #[test]
+    fn test_distances() {
+        let origin = Point::new(0.0, 0.0, 0.0);
+        let cuboid = Sdf::Cuboid { center: origin, half_size: Vector::new(1.0, 2.0, 3.0) };
+        assert_eq!(cuboid.distance(Point::new(0.0, 0.0, 0.0)), -1.0);
+        assert_eq!(cuboid.distance(Point::new(4.0, 6.0, 0.0)), 5.0);
+
+        let torus = Sdf::Torus { center: origin, major_radius: 2.0, minor_radius: 0.5 };
+        assert_eq!(torus.distance(Point::new(0.0, 0.0, 2.0)), -0.5);
+        assert_eq!(torus.distance(Point::new(0.0, 1.0, -2.0)), 0.5);
+
+        let capsule = Sdf::Capsule { a: origin, b: Point::new(0.0, 2.0, 0.0), radius: 1.0 };
+        assert_eq!(capsule.distance(Point::new(3.0, 1.0, 0.0)), 2.0);
+        assert_eq!(capsule.distance(Point::new(0.0, 5.0, 0.0)), 2.0);
+
+        let repeated = Sdf::Repeat {
+            period: Vector::new(4.0, 0.0, 0.0),
+            copies: [1, 0, 0],
+            sdf: Box::new(Sdf::Sphere { center: origin, radius: 1.0 }),
+        };
+        assert_eq!(repeated.distance(Point::new(4.0, 0.0, 0.0)), -1.0);
+        assert_eq!(repeated.distance(Point::new(-6.0, 0.0, 0.0)), 1.0);
+        assert_eq!(repeated.distance(Point::new(10.0, 0.0, 0.0)), 5.0);
+        let (lo, hi) = repeated.bound().range(0);
+        assert!(lo == -5.0 && hi == 5.0);
+
+        // smoothing only adds material near both shapes
+        let union = Sdf::SmoothUnion {
+            smoothness: 1.0,
+            left: Box::new(Sdf::Sphere { center: Point::new(-1.0, 0.0, 0.0), radius: 1.0 }),
+            right: Box::new(Sdf::Sphere { center: Point::new(1.0, 0.0, 0.0), radius: 1.0 }),
+        };
+        assert!(union.distance(Point::new(0.0, 0.5, 0.0)) < 0.0);
+        assert_eq!(union.distance(Point::new(-3.0, 0.0, 0.0)), 1.0);
+    }
+}
@@ -1,8 +1,13 @@
+use std::f64::consts::PI;
+
 use Point;
 use UnitVector;
+use Vector;
+use Cross;
 use Dot;
 use Ray;
-use super::{Shape, Intersection};
+use super::{Shape, Solid, Surface, SurfaceSample, Intersection, Interval};
+use super::bound_box::{Bound, BoundBox};
 
 pub struct Sphere {
     center: Point,
@@ -21,11 +26,19 @@ impl Sphere {
     fn normal_at(&self, point: Point) -> UnitVector {
         return self.center.direction_to(point)
     }
-}
 
-impl Shape for Sphere {
+    /// Spherical coordinates: `u` is the longitude around the `y` axis with
+    /// the seam behind the sphere, `v` is the latitude from the south pole.
+    fn uv_at(&self, normal: UnitVector) -> [f64; 2] {
+        let u = 0.5 + normal[0].atan2(normal[2]) / (2.0 * PI);
+        let v = 0.5 + normal[1].max(-1.0).min(1.0).asin() / PI;
+        [u, v]
+    }
+}
 
-    fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+impl Sphere {
+    /// Both points where the line of the ray crosses the sphere.
+    fn roots(&self, ray: &Ray) -> Option<(f64, f64)> {
         // (x - self.center)^2 == self.radius
         // x = ray.center + t * ray.direction
         let o = ray.origin - self.center;
@@ -36,24 +49,67 @@ impl Shape for Sphere {
         if disc < 0.0 {
             return None;
         }
+        Some((-k - disc.sqrt(), -k + disc.sqrt()))
+    }
 
-        let t = {
-            let t1 = -k - disc.sqrt();
-            let t2 = -k + disc.sqrt();
-            if t1 > 0.0 {
-                Some(t1)
-            } else if t2 > 0.0 {
-                Some(t2)
-            } else {
-                None
-            }
+    fn intersection_at(&self, ray: &Ray, t: f64) -> Intersection {
+        let point = ray.along(t);
+        let normal = self.normal_at(point);
+        // u grows eastwards around the `y` axis, v grows northwards
+        let east = Vector::new(normal[2], 0.0, -normal[0]);
+        let north = normal.cross(east);
+        Intersection::new(t, point, self.uv_at(normal), normal)
+            .with_tangents(east, north)
+            .with_uv_density(1.0 / (PI * self.radius))
+    }
+}
+
+impl Shape for Sphere {
+
+    fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+        let t = match self.roots(ray) {
+            Some((t1, _)) if t1 > 0.0 => t1,
+            Some((_, t2)) if t2 > 0.0 => t2,
+            _ => return None,
         };
+        Some(self.intersection_at(ray, t))
+    }
+}
 
-        t.map(|t| {
-            let point = ray.along(t);
-            let local_coordinates = [0.0, 0.0];
-            Intersection::new(t, point, local_coordinates, self.normal_at(point))
-        })
+impl Solid for Sphere {
+    fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        match self.roots(ray) {
+            None => Vec::new(),
+            Some((t1, t2)) => vec![Interval {
+                enter: self.intersection_at(ray, t1),
+                exit: self.intersection_at(ray, t2),
+            }],
+        }
+    }
+}
+
+impl Surface for Sphere {
+    fn area(&self) -> f64 {
+        4.0 * PI * self.radius * self.radius
+    }
+
+    fn sample_surface(&self, u: f64, v: f64) -> SurfaceSample {
+        let z = 1.0 - 2.0 * u;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * PI * v;
+        let normal = Vector::new(r * phi.cos(), r * phi.sin(), z);
+        SurfaceSample {
+            point: self.center + normal * self.radius,
+            normal: normal.direction(),
+            pdf: 1.0 / self.area(),
+        }
+    }
+}
+
+impl Bound for Sphere {
+    fn bound(&self) -> BoundBox {
+        let r = Vector::new(self.radius, self.radius, self.radius);
+        BoundBox::new(self.center + -r, self.center + r)
     }
 }
 
@@ -63,6 +119,7 @@ mod tests {
     use super::*;
     use Point;
     use Cross;
+    use Dot;
     use shape::{Shape, Intersection};
     use ray::{Ray};
 
@@ -103,4 +160,23 @@ mod tests {
         assert!(hits > 1);
         assert!(misses > 1);
     }
+
+    #[test]
+    fn sphere_texture_coordinates() {
+        let center = Point::new(1.0, 2.0, 3.0);
+        let sphere = Sphere::new(center, 2.0);
+        check_prop2(|from: Point, to: Point| {
+            let ray = Ray::from_to(center, center + (to - from));
+            let i = sphere.intersect(&ray).unwrap();
+            let (u, v) = (i.local_coordinates[0], i.local_coordinates[1]);
+            assert!(0.0 <= u && u <= 1.0 && 0.0 <= v && v <= 1.0);
+            // v grows from the south pole to the north pole
+            let height = (i.point[1] - center[1]) / 2.0;
+            assert!((((v - 0.5) * PI).sin() - height).abs() < 1e-9);
+            // tangent frame is orthogonal to the normal, v grows upwards
+            assert!(i.tangent.dot(i.normal).abs() < 1e-9);
+            assert!(i.bitangent.dot(i.normal).abs() < 1e-9);
+            assert!(i.bitangent[1] >= -1e-9);
+        });
+    }
 }
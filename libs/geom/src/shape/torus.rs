@@ -0,0 +1,227 @@
+use std::f64::consts::PI;
+
+use Point;
+use UnitVector;
+use Vector;
+use Dot;
+use Ray;
+use super::{Intersection, Interval, Shape, Solid, first_in_front, pair_crossings};
+use super::bound_box::{Bound, BoundBox};
+use super::frame::Frame;
+
+
+/// Torus around the `axis` through its center. `u` texture coordinate goes
+/// around the axis and `v` around the tube, starting from its inner side.
+pub struct Torus {
+    frame: Frame,
+    major_radius: f64,
+    minor_radius: f64,
+}
+
+impl Torus {
+    pub fn new(center: Point, axis: UnitVector, major_radius: f64, minor_radius: f64) -> Torus {
+        assert!(0.0 < minor_radius && minor_radius < major_radius);
+        Torus {
+            frame: Frame::new(center, axis),
+            major_radius: major_radius,
+            minor_radius: minor_radius,
+        }
+    }
+
+    /// Points where the line of the ray crosses the surface, and those where
+    /// it only grazes it.
+    fn crossings(&self, ray: &Ray) -> (Vec<Intersection>, Vec<Intersection>) {
+        let o = self.frame.to_local(ray.origin);
+        let d = self.frame.vector_to_local(ray.direction.into());
+        let (big_r, r) = (self.major_radius, self.minor_radius);
+
+        // move the origin next to the center to keep the coefficients small
+        let t_center = -o.dot(d);
+        let o = o + d * t_center;
+
+        // (|p|^2 + R^2 - r^2)^2 = 4 R^2 (x^2 + y^2) for p = o + s d, |d| = 1
+        let f = o.dot(d);
+        let q = o.dot(o) + big_r * big_r - r * r;
+        let k = 4.0 * big_r * big_r;
+        let coefficients = [
+            q * q - k * (o.x * o.x + o.y * o.y),
+            4.0 * f * q - 2.0 * k * (o.x * d.x + o.y * d.y),
+            4.0 * f * f + 2.0 * q - k * (d.x * d.x + d.y * d.y),
+            4.0 * f,
+            1.0,
+        ];
+        let reach = (big_r + r) * 1.01;
+        let (crossing, touching) = real_roots(&coefficients, -reach, reach);
+        let at = |s: f64| self.intersection_at(ray, t_center + s, o + d * s);
+        (crossing.into_iter().map(&at).collect(), touching.into_iter().map(&at).collect())
+    }
+
+    fn intersection_at(&self, ray: &Ray, t: f64, p: Vector) -> Intersection {
+        let f = &self.frame;
+        let (big_r, r) = (self.major_radius, self.minor_radius);
+        let a = p.dot(p) + big_r * big_r - r * r;
+        let gradient = Vector::new(p.x * (a - 2.0 * big_r * big_r),
+                                   p.y * (a - 2.0 * big_r * big_r),
+                                   p.z * a);
+
+        let theta = p.y.atan2(p.x);
+        let rho = (p.x * p.x + p.y * p.y).sqrt();
+        let phi = p.z.atan2(rho - big_r);
+        let uv = [0.5 + theta / (2.0 * PI), 0.5 + phi / (2.0 * PI)];
+        let dpdu = Vector::new(-p.y, p.x, 0.0) * (2.0 * PI);
+        let dpdv = Vector::new(-r * phi.sin() * theta.cos(),
+                               -r * phi.sin() * theta.sin(),
+                               r * phi.cos()) * (2.0 * PI);
+        Intersection::new(t, ray.along(t), uv, f.vector_to_world(gradient).direction())
+            .with_tangents(f.vector_to_world(dpdu), f.vector_to_world(dpdv))
+            .with_uv_density(1.0 / (2.0 * PI * big_r))
+    }
+}
+
+impl Shape for Torus {
+    fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+        let (mut hits, touching) = self.crossings(ray);
+        hits.extend(touching);
+        hits.sort();
+        first_in_front(hits)
+    }
+}
+
+impl Solid for Torus {
+    /// Grazing rays don't go inside.
+    fn intervals(&self, ray: &Ray) -> Vec<Interval> {
+        pair_crossings(self.crossings(ray).0)
+    }
+}
+
+impl Bound for Torus {
+    fn bound(&self) -> BoundBox {
+        let w = self.frame.w;
+        let extent = |axis| {
+            self.major_radius * (1.0 - w[axis] * w[axis]).max(0.0).sqrt() + self.minor_radius
+        };
+        let e = Vector::new(extent(0), extent(1), extent(2));
+        BoundBox::new(self.frame.origin + -e, self.frame.origin + e)
+    }
+}
+
+
+/// Sorted real roots in `[lo, hi]` of the polynomial with the `coefficients`
+/// from the lowest degree: those where it changes sign, and those where it
+/// only touches zero at a minimum or a maximum. Roots of the derivative
+/// split the range into monotone pieces, in which the crossings are
+/// bisected. The touching roots are the extrema which vanish up to rounding
+/// errors.
+fn real_roots(coefficients: &[f64], lo: f64, hi: f64) -> (Vec<f64>, Vec<f64>) {
+    let degree = coefficients.len() - 1;
+    if degree == 0 {
+        return (Vec::new(), Vec::new());
+    }
+    let value = |x: f64| coefficients.iter().rev().fold(0.0, |acc, &c| acc * x + c);
+    let derivative: Vec<f64> = (1..degree + 1)
+        .map(|i| coefficients[i] * i as f64)
+        .collect();
+    let extrema = real_roots(&derivative, lo, hi).0;
+
+    let mut ends = vec![lo];
+    ends.extend(&extrema);
+    ends.push(hi);
+
+    let mut crossing = Vec::new();
+    for piece in ends.windows(2) {
+        let (mut a, mut b) = (piece[0], piece[1]);
+        let (fa, fb) = (value(a), value(b));
+        let ends_at_root = (fa == 0.0 && a == lo) || (fb == 0.0 && b == hi);
+        if fa * fb > 0.0 || (fa * fb == 0.0 && !ends_at_root) {
+            continue;
+        }
+        let rising = fb > fa;
+        for _ in 0..100 {
+            let mid = 0.5 * (a + b);
+            if mid == a || mid == b {
+                break;
+            }
+            if (value(mid) > 0.0) == rising {
+                b = mid;
+            } else {
+                a = mid;
+            }
+        }
+        crossing.push(0.5 * (a + b));
+    }
+    crossing.dedup();
+
+    let touching = extrema.into_iter()
+        .filter(|&x| {
+            let scale = coefficients.iter().rev().fold(0.0, |acc, &c| acc * x.abs() + c.abs());
+            value(x).abs() <= 1e-10 * scale
+        })
+        .collect();
+    (crossing, touching)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {Point, Ray, Vector};
+    use shape::{Shape, Solid};
+
+    #[test]
+    fn hit_on_torus() {
+        let torus = Torus::new(Point::new(0.0, 0.0, 0.0), Vector::new(0.0, 0.0, 1.0).direction(),
+                               1.0, 0.25);
+        let is_normal = |i: &Intersection, x: f64, y: f64, z: f64| {
+            (Vector::from(i.normal) - Vector::new(x, y, z)).is_almost_zero()
+        };
+
+        // through both sides of the tube, starting from its outer side
+        let ray = Ray::from_to(Point::new(-5.0, 0.0, 0.0), Point::new(0.0, 0.0, 0.0));
+        let i = torus.intersect(&ray).unwrap();
+        assert!((i.t - 3.75).abs() < 1e-9);
+        assert!(is_normal(&i, -1.0, 0.0, 0.0));
+        assert!((i.local_coordinates[1] - 0.5).abs() < 1e-9);
+        let intervals = torus.intervals(&ray);
+        assert_eq!(intervals.len(), 2);
+        let ends = [3.75, 4.25, 5.75, 6.25];
+        for (k, interval) in intervals.iter().enumerate() {
+            assert!((interval.enter.t - ends[2 * k]).abs() < 1e-9);
+            assert!((interval.exit.t - ends[2 * k + 1]).abs() < 1e-9);
+        }
+        // leaving the tube towards the hole
+        assert!(is_normal(&intervals[0].exit, 1.0, 0.0, 0.0));
+
+        // down through the tube
+        let ray = Ray::from_to(Point::new(1.0, 0.0, 5.0), Point::new(1.0, 0.0, 0.0));
+        let i = torus.intersect(&ray).unwrap();
+        assert!((i.t - 4.75).abs() < 1e-9);
+        assert!(is_normal(&i, 0.0, 0.0, 1.0));
+        let intervals = torus.intervals(&ray);
+        assert_eq!(intervals.len(), 1);
+        assert!((intervals[0].exit.t - 5.25).abs() < 1e-9);
+        assert!(is_normal(&intervals[0].exit, 0.0, 0.0, -1.0));
+
+        // through the hole and above the tube
+        let ray = Ray::from_to(Point::new(0.0, 0.0, 5.0), Point::new(0.0, 0.0, 0.0));
+        assert!(torus.intersect(&ray).is_none());
+        let ray = Ray::from_to(Point::new(-5.0, 0.0, 0.3), Point::new(0.0, 0.0, 0.3));
+        assert!(torus.intersect(&ray).is_none());
+    }
+
+    #[test]
+    fn graze_torus() {
+        let torus = Torus::new(Point::new(0.0, 0.0, 0.0), Vector::new(0.0, 0.0, 1.0).direction(),
+                               1.0, 0.25);
+        // along the top of the tube, touching it at both sides of the hole
+        let ray = Ray::from_to(Point::new(-5.0, 0.0, 0.25), Point::new(0.0, 0.0, 0.25));
+        let i = torus.intersect(&ray).unwrap();
+        assert!((i.t - 4.0).abs() < 1e-6);
+        assert!((i.point - Point::new(-1.0, 0.0, 0.25)).length() < 1e-6);
+        assert!(i.normal.dot(Vector::new(0.0, 0.0, 1.0)) > 1.0 - 1e-9);
+        assert!(torus.intervals(&ray).is_empty());
+
+        // just above the tube
+        let ray = Ray::from_to(Point::new(-5.0, 0.0, 0.2501), Point::new(0.0, 0.0, 0.2501));
+        assert!(torus.intersect(&ray).is_none());
+    }
+}
@@ -4,7 +4,7 @@ use Vector;
 use Cross;
 use Dot;
 use Ray;
-use super::{Shape, Intersection};
+use super::{Shape, Surface, SurfaceSample, Intersection};
 use super::bound_box::{Bound, BoundBox};
 
 #[derive(Debug, Clone)]
@@ -13,6 +13,14 @@ pub struct Triangle {
     ab: Vector,
     ac: Vector,
     normals: [UnitVector; 3],
+    /// Whether the normals were given for the vertices, instead of being the
+    /// normal of the face.
+    has_vertex_normals: bool,
+    uvs: [[f64; 2]; 3],
+    uv_density: f64,
+    dpdu: Vector,
+    dpdv: Vector,
+    material_slot: usize,
 }
 
 impl Triangle {
@@ -27,23 +35,64 @@ impl Triangle {
         let ab = b - a;
         let ac = c - a;
         let n = ab.cross(ac).direction();
-        Triangle {
-            a: a,
-            ab: ab,
-            ac: ac,
-            normals: [n; 3],
-        }
+        let mut triangle = Triangle::with_normals(a, b, c, [n; 3]);
+        triangle.has_vertex_normals = false;
+        triangle
     }
 
     pub fn with_normals(a: Point, b: Point, c: Point, normals: [UnitVector; 3]) -> Triangle {
         let ab = b - a;
         let ac = c - a;
-        Triangle {
+        let triangle = Triangle {
             a: a,
             ab: ab,
             ac: ac,
             normals: normals,
+            has_vertex_normals: true,
+            uvs: [[0.0, 0.0]; 3],
+            uv_density: 0.0,
+            dpdu: ab,
+            dpdv: ac,
+            material_slot: 0,
+        };
+        triangle.with_uvs([[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]])
+    }
+
+    /// Sets texture coordinates of the vertices. By default the texture
+    /// coordinates are the barycentric coordinates of the point.
+    pub fn with_uvs(mut self, uvs: [[f64; 2]; 3]) -> Triangle {
+        let uv_ab = [uvs[1][0] - uvs[0][0], uvs[1][1] - uvs[0][1]];
+        let uv_ac = [uvs[2][0] - uvs[0][0], uvs[2][1] - uvs[0][1]];
+        let det = uv_ab[0] * uv_ac[1] - uv_ab[1] * uv_ac[0];
+        self.uvs = uvs;
+        self.uv_density = (det.abs() / self.ab.cross(self.ac).length()).sqrt();
+        if det != 0.0 {
+            self.dpdu = (self.ab * uv_ac[1] - self.ac * uv_ab[1]) * (1.0 / det);
+            self.dpdv = (self.ac * uv_ab[0] - self.ab * uv_ac[0]) * (1.0 / det);
         }
+        self
+    }
+
+    pub fn with_material_slot(mut self, material_slot: usize) -> Triangle {
+        self.material_slot = material_slot;
+        self
+    }
+
+    pub fn vertices(&self) -> [Point; 3] {
+        [self.a, self.a + self.ab, self.a + self.ac]
+    }
+
+    pub fn material_slot(&self) -> usize {
+        self.material_slot
+    }
+
+    pub fn has_vertex_normals(&self) -> bool {
+        self.has_vertex_normals
+    }
+
+    pub fn set_vertex_normals(&mut self, normals: [UnitVector; 3]) {
+        self.normals = normals;
+        self.has_vertex_normals = true;
     }
 
     fn local_coordinates(&self, point: Point) -> (f64, f64, f64) {
@@ -65,6 +114,11 @@ impl Triangle {
                 beta * self.normals[2] +
                 gamma * self.normals[0]).direction()
     }
+
+    fn interpolate_uv(&self, alpha: f64, beta: f64, gamma: f64) -> [f64; 2] {
+        let uv = |i: usize| alpha * self.uvs[1][i] + beta * self.uvs[2][i] + gamma * self.uvs[0][i];
+        [uv(0), uv(1)]
+    }
 }
 
 
@@ -80,8 +134,11 @@ impl Shape for Triangle {
         let f = |x| 0.0 < x && x < 1.0;
         if f(alpha) && f(beta) && f(gamma)  {
             Some(Intersection::new(t, point,
-                                   [alpha, beta],
-                                   self.interpolate_normal(alpha, beta, gamma)))
+                                   self.interpolate_uv(alpha, beta, gamma),
+                                   self.interpolate_normal(alpha, beta, gamma))
+                 .with_tangents(self.dpdu, self.dpdv)
+                 .with_uv_density(self.uv_density)
+                 .with_material_slot(self.material_slot))
         } else {
             None
         }
@@ -89,6 +146,27 @@ impl Shape for Triangle {
 }
 
 
+/// The normal of the samples is the normal of the face, turned to the side
+/// of the interpolated normal.
+impl Surface for Triangle {
+    fn area(&self) -> f64 {
+        self.normal().length() / 2.0
+    }
+
+    fn sample_surface(&self, u: f64, v: f64) -> SurfaceSample {
+        let s = u.sqrt();
+        let (alpha, beta) = (s * (1.0 - v), s * v);
+        let gamma = 1.0 - (alpha + beta);
+        let normal = self.normal().direction();
+        let shading = self.interpolate_normal(alpha, beta, gamma);
+        SurfaceSample {
+            point: self.a + self.ab * alpha + self.ac * beta,
+            normal: if normal.dot(shading) < 0.0 { -normal } else { normal },
+            pdf: 1.0 / self.area(),
+        }
+    }
+}
+
 impl Bound for Triangle {
     fn bound(&self) -> BoundBox {
         self.a.bound()
@@ -181,4 +259,26 @@ mod test {
         assert!(missmisses > 1);
         assert!(misshits > 1);
     }
+
+    #[test]
+    fn triangle_texture_coordinates() {
+        let triangle = Triangle::new(
+            Point::new(0.0, 0.0, 0.0),
+            Point::new(0.0, 2.0, 0.0),
+            Point::new(0.0, 0.0, 2.0))
+            .with_uvs([[0.5, 0.5], [1.0, 0.5], [0.5, 1.0]]);
+        let origin = Point::new(-1.0, 0.0, 0.0);
+
+        check_prop2(|y: f64, z: f64| {
+            let ray = Ray::from_to(origin, Point::new(0.0, y, z));
+            if let Some(i) = triangle.intersect(&ray) {
+                let uv = i.local_coordinates;
+                assert!((uv[0] - (0.5 + i.point[1] / 4.0)).abs() < 1e-9);
+                assert!((uv[1] - (0.5 + i.point[2] / 4.0)).abs() < 1e-9);
+                assert!((i.uv_density - 0.25).abs() < 1e-9);
+                assert!(i.tangent.dot(Vector::new(0.0, 1.0, 0.0)) > 1.0 - 1e-9);
+                assert!(i.bitangent.dot(Vector::new(0.0, 0.0, 1.0)) > 1.0 - 1e-9);
+            }
+        });
+    }
 }
@@ -0,0 +1,245 @@
+use Point;
+use Vector;
+use UnitVector;
+use shape::BoundBox;
+
+
+type Matrix = [[f64; 4]; 4];
+
+const IDENTITY: Matrix = [[1.0, 0.0, 0.0, 0.0],
+                          [0.0, 1.0, 0.0, 0.0],
+                          [0.0, 0.0, 1.0, 0.0],
+                          [0.0, 0.0, 0.0, 1.0]];
+
+
+/// Affine transform of the space, stored together with its inverse.
+#[derive(Debug, Clone, Copy)]
+pub struct Transform {
+    m: Matrix,
+    inv: Matrix,
+}
+
+impl Transform {
+    /// Transform with the matrix `m` acting on column vectors. `None` if the
+    /// matrix is singular.
+    pub fn new(m: [[f64; 4]; 4]) -> Option<Transform> {
+        invert(&m).map(|inv| Transform { m: m, inv: inv })
+    }
+
+    pub fn identity() -> Transform {
+        Transform { m: IDENTITY, inv: IDENTITY }
+    }
+
+    pub fn translation(v: Vector) -> Transform {
+        let mut m = IDENTITY;
+        let mut inv = IDENTITY;
+        for i in 0..3 {
+            m[i][3] = v[i as u8];
+            inv[i][3] = -v[i as u8];
+        }
+        Transform { m: m, inv: inv }
+    }
+
+    /// Scales along the coordinate axes, all factors must be nonzero.
+    pub fn scaling(s: Vector) -> Transform {
+        assert!(s.x != 0.0 && s.y != 0.0 && s.z != 0.0);
+        let mut m = IDENTITY;
+        let mut inv = IDENTITY;
+        for i in 0..3 {
+            m[i][i] = s[i as u8];
+            inv[i][i] = 1.0 / s[i as u8];
+        }
+        Transform { m: m, inv: inv }
+    }
+
+    /// Counterclockwise rotation by `angle` radians when looking against
+    /// the `axis`.
+    pub fn rotation(axis: UnitVector, angle: f64) -> Transform {
+        let (a, s, c) = (axis, angle.sin(), angle.cos());
+        let mut m = IDENTITY;
+        for i in 0..3 {
+            for j in 0..3 {
+                m[i][j] = a[i as u8] * a[j as u8] * (1.0 - c);
+            }
+            m[i][i] += c;
+        }
+        m[0][1] -= a[2] * s;
+        m[0][2] += a[1] * s;
+        m[1][0] += a[2] * s;
+        m[1][2] -= a[0] * s;
+        m[2][0] -= a[1] * s;
+        m[2][1] += a[0] * s;
+        Transform { m: m, inv: transpose(&m) }
+    }
+
+    /// Applies `self` first and then `next`.
+    pub fn then(&self, next: &Transform) -> Transform {
+        Transform {
+            m: multiply(&next.m, &self.m),
+            inv: multiply(&self.inv, &next.inv),
+        }
+    }
+
+    pub fn inverse(&self) -> Transform {
+        Transform { m: self.inv, inv: self.m }
+    }
+
+    pub fn point(&self, p: Point) -> Point {
+        let m = &self.m;
+        let c = |i: usize| m[i][0] * p[0] + m[i][1] * p[1] + m[i][2] * p[2] + m[i][3];
+        let w = c(3);
+        Point::new(c(0) / w, c(1) / w, c(2) / w)
+    }
+
+    pub fn vector(&self, v: Vector) -> Vector {
+        let m = &self.m;
+        let c = |i: usize| m[i][0] * v.x + m[i][1] * v.y + m[i][2] * v.z;
+        Vector::new(c(0), c(1), c(2))
+    }
+
+    /// Normals are transformed with the inverse transpose, so that they stay
+    /// orthogonal to the transformed surface.
+    pub fn normal(&self, n: UnitVector) -> UnitVector {
+        self.inverse_transpose(n).direction()
+    }
+
+    /// Factor by which the area of a small patch of a surface with the
+    /// normal `n` grows.
+    pub fn area_scale(&self, n: UnitVector) -> f64 {
+        self.determinant().abs() * self.inverse_transpose(n).length()
+    }
+
+    /// Bound of the transformed box.
+    pub fn bound(&self, b: &BoundBox) -> BoundBox {
+        if !b.is_finite() {
+            return BoundBox::infinite();
+        }
+        let ranges = [b.range(0), b.range(1), b.range(2)];
+        (0..8)
+            .map(|corner| {
+                let pick = |axis: usize| {
+                    let (lo, hi) = ranges[axis];
+                    if corner & (1 << axis) == 0 { lo } else { hi }
+                };
+                self.point(Point::new(pick(0), pick(1), pick(2)))
+            })
+            .collect()
+    }
+
+    /// Geometric mean of the scale factors along the axes.
+    pub fn scale(&self) -> f64 {
+        self.determinant().abs().cbrt()
+    }
+
+    fn determinant(&self) -> f64 {
+        let m = &self.m;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    fn inverse_transpose(&self, n: UnitVector) -> Vector {
+        let inv = &self.inv;
+        let c = |i: usize| inv[0][i] * n[0] + inv[1][i] * n[1] + inv[2][i] * n[2];
+        Vector::new(c(0), c(1), c(2))
+    }
+}
+
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut result = [[0.0; 4]; 4];
+    for i in 0..4 {
+        for j in 0..4 {
+            result[i][j] = (0..4).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    result
+}
+
+fn transpose(m: &Matrix) -> Matrix {
+    let mut result = [[0.0; 4]; 4];
+    for i in 0..4 {
+        for j in 0..4 {
+            result[i][j] = m[j][i];
+        }
+    }
+    result
+}
+
+/// Gauss-Jordan elimination with partial pivoting.
+fn invert(m: &Matrix) -> Option<Matrix> {
+    let mut a = *m;
+    let mut inv = IDENTITY;
+    for col in 0..4 {
+        let pivot = (col..4)
+            .max_by(|&i, &j| a[i][col].abs().partial_cmp(&a[j][col].abs()).unwrap())
+            .unwrap();
+        if !(a[pivot][col].abs() > 1e-12) {
+            return None;
+        }
+        a.swap(col, pivot);
+        inv.swap(col, pivot);
+        let p = a[col][col];
+        for j in 0..4 {
+            a[col][j] /= p;
+            inv[col][j] /= p;
+        }
+        for i in 0..4 {
+            if i == col {
+                continue;
+            }
+            let f = a[i][col];
+            for j in 0..4 {
+                a[i][j] -= f * a[col][j];
+                inv[i][j] -= f * inv[col][j];
+            }
+        }
+    }
+    Some(inv)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {Point, Vector, Dot, Cross};
+    use utils::props::check_prop2;
+
+    fn random_transform(v: Vector, w: Vector) -> Transform {
+        Transform::scaling(Vector::new(0.5 + v.x, 1.0 + v.y, 0.2 + v.z))
+            .then(&Transform::rotation(w.direction(), v.x * 7.0))
+            .then(&Transform::translation(w * 10.0))
+    }
+
+    #[test]
+    fn test_inverse() {
+        check_prop2(|v: Vector, w: Vector| {
+            let t = random_transform(v, w);
+            let p = Point::new(w.x * 5.0, v.y - 2.0, v.z * 3.0);
+            let q = t.inverse().point(t.point(p));
+            assert!((q - p).is_almost_zero());
+
+            let general = Transform::new(t.m).unwrap();
+            let q = general.inverse().point(t.point(p));
+            assert!((q - p).is_almost_zero());
+        });
+        assert!(Transform::new([[1.0, 2.0, 3.0, 0.0],
+                                [2.0, 4.0, 6.0, 0.0],
+                                [0.0, 0.0, 1.0, 0.0],
+                                [0.0, 0.0, 0.0, 1.0]]).is_none());
+    }
+
+    #[test]
+    fn test_normals_stay_orthogonal() {
+        check_prop2(|v: Vector, w: Vector| {
+            let t = random_transform(v, w);
+            let (a, b) = (v - Vector::new(0.5, 0.5, 0.5), w - Vector::new(0.5, 0.5, 0.5));
+            if a.cross(b).is_almost_zero() {
+                return;
+            }
+            let n = t.normal(a.cross(b).direction());
+            assert!(n.dot(t.vector(a)).abs() < 1e-9);
+            assert!(n.dot(t.vector(b)).abs() < 1e-9);
+        });
+    }
+}
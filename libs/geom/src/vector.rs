@@ -128,8 +128,43 @@ pub struct UnitVector {
 
 
 impl UnitVector {
+    /// Mirror image of `self` by a surface perpendicular to `axis`, that is
+    /// `d - 2 (d . axis) axis`. It doesn't matter which side `axis` faces.
     pub fn reflect(self, axis: UnitVector) -> UnitVector {
-        (self.direction + 2.0f64 * axis).direction()
+        (self.direction - 2.0f64 * self.dot(axis) * axis).direction()
+    }
+
+    /// Direction of the ray refracted by a surface with the given `normal`,
+    /// which should point against `self`. `eta` is the ratio of the
+    /// refractive indices of the media the ray goes from and to.
+    /// Returns `None` in case of the total internal reflection.
+    pub fn refract(self, normal: UnitVector, eta: f64) -> Option<UnitVector> {
+        let cos_i = -self.dot(normal);
+        let sin2_t = eta * eta * (1.0 - cos_i * cos_i);
+        if sin2_t > 1.0 {
+            return None;
+        }
+        let cos_t = (1.0 - sin2_t).sqrt();
+        Some((eta * self + (eta * cos_i - cos_t) * normal).direction())
+    }
+
+    /// Two unit vectors which together with `self` form an orthonormal basis.
+    pub fn orthonormal_basis(self) -> (UnitVector, UnitVector) {
+        let helper = if self.direction.x.abs() > 0.9 {
+            Vector::new(0.0, 1.0, 0.0)
+        } else {
+            Vector::new(1.0, 0.0, 0.0)
+        };
+        let u = helper.cross(self).direction();
+        let v = self.cross(u).direction();
+        (u, v)
+    }
+}
+
+
+impl From<UnitVector> for Vector {
+    fn from(v: UnitVector) -> Vector {
+        v.direction
     }
 }
 
@@ -252,7 +287,7 @@ impl Cross<Vector> for UnitVector {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use Cross;
+    use {Cross, Dot};
     use utils::props::{check_prop, check_prop2};
 
     #[test]
@@ -278,6 +313,56 @@ mod tests {
         })
     }
 
+    #[test]
+    fn orthonormal_basis() {
+        check_prop(|a: Vector| {
+            let n = a.direction();
+            let (u, v) = n.orthonormal_basis();
+            assert!(u.dot(n).abs() < 1e-9);
+            assert!(v.dot(n).abs() < 1e-9);
+            assert!(u.dot(v).abs() < 1e-9);
+            assert!(n.cross(u).is_almost_eq(v * 1.0));
+        })
+    }
+
+    #[test]
+    fn reflection() {
+        let d = Vector::new(1.0, -1.0, 0.0).direction();
+        let n = Vector::new(0.0, 1.0, 0.0).direction();
+        let expected = Vector::new(1.0, 1.0, 0.0).direction();
+        assert!(d.reflect(n).direction.is_almost_eq(expected.direction));
+        assert!(d.reflect(-n).direction.is_almost_eq(expected.direction));
+        // straight at the surface and straight back
+        assert!((-n).reflect(n).direction.is_almost_eq(n.direction));
+    }
+
+    #[test]
+    fn reflection_preserves_angle() {
+        check_prop2(|a: Vector, n: Vector| {
+            let (d, n) = (a.direction(), n.direction());
+            let r = d.reflect(n);
+            assert!((r.dot(n) + d.dot(n)).abs() < 1e-9);
+            assert!(d.reflect(n).reflect(n).direction.is_almost_eq(d.direction));
+        })
+    }
+
+    #[test]
+    fn refraction_obeys_snell_law() {
+        check_prop2(|a: Vector, eta: f64| {
+            let n = Vector::new(0.0, 0.0, 1.0).direction();
+            let d = Vector::new(a.x, a.y, -a.z - 0.01).direction();
+            let eta = 0.5 + eta;
+            let sin_i = d.cross(n).length();
+            match d.refract(n, eta) {
+                None => assert!(eta * sin_i > 1.0),
+                Some(t) => {
+                    assert!(t.dot(n) < 0.0);
+                    assert!((t.cross(n).length() - eta * sin_i).abs() < 1e-9);
+                }
+            }
+        })
+    }
+
     #[test]
     fn mul_inverse_is_div() {
         check_prop2(|a: Vector, c: f64| {
@@ -0,0 +1,225 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use rustraytracer::rendering::{TracerConfig, SamplerConfig};
+
+
+pub const USAGE: &'static str = "\
+Usage:
+    rustraytracer render <scene> [options]
+    rustraytracer validate <scene>
+    rustraytracer info <scene> [options]
+    rustraytracer --help
+
+Options:
+    -o, --output <path>     Where to write the PPM image [default: ./out.ppm]
+    --resolution <WxH>      Override image resolution, e.g. 800x600
+    --samples <n>           Override samples per pixel (per axis)
+    --reflections <n>       Override the maximum number of reflections
+    --threads <n>           Override the number of rendering threads";
+
+
+#[derive(Debug)]
+pub enum Command {
+    Render { scene: String, output: String, overrides: TracerOverrides },
+    Validate { scene: String },
+    Info { scene: String, overrides: TracerOverrides },
+    Help,
+}
+
+
+#[derive(Debug, Default)]
+pub struct TracerOverrides {
+    pub resolution: Option<[u32; 2]>,
+    pub samples_per_pixel: Option<u32>,
+    pub n_reflections: Option<u32>,
+    pub n_threads: Option<u16>,
+}
+
+impl TracerOverrides {
+    pub fn apply(&self, config: &mut TracerConfig) {
+        if let Some(resolution) = self.resolution {
+            config.resolution = resolution;
+        }
+        if let Some(n) = self.samples_per_pixel {
+            match config.sampler {
+                SamplerConfig::Stratified { ref mut samples_per_pixel, .. } =>
+                    *samples_per_pixel = n,
+            }
+        }
+        if let Some(n) = self.n_reflections {
+            config.n_reflections = n;
+        }
+        if let Some(n) = self.n_threads {
+            config.n_threads = Some(n);
+        }
+    }
+}
+
+
+#[derive(Debug)]
+pub struct CliError {
+    description: String
+}
+
+impl CliError {
+    fn new<S: Into<String>>(description: S) -> CliError {
+        CliError { description: description.into() }
+    }
+}
+
+impl Error for CliError {
+    fn description(&self) -> &str {
+        &self.description
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.description().fmt(f)
+    }
+}
+
+
+pub fn parse_args<I>(args: I) -> Result<Command, CliError>
+    where I: IntoIterator<Item=String> {
+
+    let mut args = args.into_iter();
+    let command = match args.next() {
+        None => return Err(CliError::new("no command given")),
+        Some(c) => c,
+    };
+
+    let mut scene = None;
+    let mut output = None;
+    let mut overrides = TracerOverrides::default();
+
+    while let Some(arg) = args.next() {
+        if arg == "-h" || arg == "--help" {
+            return Ok(Command::Help);
+        }
+        if !arg.starts_with('-') {
+            if scene.is_some() {
+                return Err(CliError::new(format!("unexpected argument: {}", arg)));
+            }
+            scene = Some(arg);
+            continue;
+        }
+
+        let (flag, inline_value) = match arg.find('=') {
+            Some(idx) => (arg[..idx].to_string(), Some(arg[idx + 1..].to_string())),
+            None => (arg.clone(), None),
+        };
+        let mut value = || -> Result<String, CliError> {
+            inline_value.clone()
+                .or_else(|| args.next())
+                .ok_or(CliError::new(format!("missing value for {}", flag)))
+        };
+
+        match flag.as_str() {
+            "-o" | "--output" => output = Some(value()?),
+            "--resolution" => overrides.resolution = Some(parse_resolution(&value()?)?),
+            "--samples" => overrides.samples_per_pixel = Some(parse_positive(&flag, &value()?)?),
+            "--reflections" => overrides.n_reflections = Some(parse_number(&flag, &value()?)?),
+            "--threads" => overrides.n_threads = Some(parse_positive(&flag, &value()?)?),
+            _ => return Err(CliError::new(format!("unknown option: {}", flag))),
+        }
+    }
+
+    let required_scene = || scene.clone().ok_or(CliError::new("missing scene file"));
+    let no_render_options = |command: &str| {
+        if output.is_some() || overrides.n_threads.is_some() || overrides.samples_per_pixel.is_some()
+            || overrides.resolution.is_some() || overrides.n_reflections.is_some() {
+            Err(CliError::new(format!("`{}` does not take rendering options", command)))
+        } else {
+            Ok(())
+        }
+    };
+
+    match command.as_str() {
+        "render" => Ok(Command::Render {
+            scene: required_scene()?,
+            output: output.clone().unwrap_or("./out.ppm".to_string()),
+            overrides: overrides,
+        }),
+        "validate" => {
+            no_render_options("validate")?;
+            Ok(Command::Validate { scene: required_scene()? })
+        },
+        "info" => {
+            if output.is_some() {
+                return Err(CliError::new("`info` does not write an image"));
+            }
+            Ok(Command::Info { scene: required_scene()?, overrides: overrides })
+        },
+        "-h" | "--help" | "help" => Ok(Command::Help),
+        _ => Err(CliError::new(format!("unknown command: {}", command))),
+    }
+}
+
+
+fn parse_number<T: FromStr>(flag: &str, s: &str) -> Result<T, CliError> {
+    s.parse::<T>().map_err(|_| CliError::new(format!("invalid value for {}: {}", flag, s)))
+}
+
+fn parse_positive<T: FromStr + PartialEq + Default>(flag: &str, s: &str) -> Result<T, CliError> {
+    let n = parse_number::<T>(flag, s)?;
+    if n == T::default() {
+        return Err(CliError::new(format!("{} should be positive", flag)));
+    }
+    Ok(n)
+}
+
+fn parse_resolution(s: &str) -> Result<[u32; 2], CliError> {
+    let error = || CliError::new(format!("invalid resolution, expected WIDTHxHEIGHT: {}", s));
+    let parts = s.split('x').collect::<Vec<_>>();
+    if parts.len() != 2 {
+        return Err(error());
+    }
+    let width = parts[0].parse::<u32>().map_err(|_| error())?;
+    let height = parts[1].parse::<u32>().map_err(|_| error())?;
+    if width == 0 || height == 0 {
+        return Err(error());
+    }
+    Ok([width, height])
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Command, CliError> {
+        parse_args(args.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn test_render_with_overrides() {
+        let command = parse(&["render", "scene.json", "-o", "x.ppm",
+                              "--resolution=320x240", "--samples", "4", "--threads", "2"]).unwrap();
+        match command {
+            Command::Render { scene, output, overrides } => {
+                assert_eq!(scene, "scene.json");
+                assert_eq!(output, "x.ppm");
+                assert_eq!(overrides.resolution, Some([320, 240]));
+                assert_eq!(overrides.samples_per_pixel, Some(4));
+                assert_eq!(overrides.n_threads, Some(2));
+                assert_eq!(overrides.n_reflections, None);
+            },
+            _ => panic!("expected render command"),
+        }
+    }
+
+    #[test]
+    fn test_bad_arguments() {
+        assert!(parse(&[]).is_err());
+        assert!(parse(&["draw", "scene.json"]).is_err());
+        assert!(parse(&["render"]).is_err());
+        assert!(parse(&["render", "scene.json", "--resolution", "800"]).is_err());
+        assert!(parse(&["render", "scene.json", "--samples", "0"]).is_err());
+        assert!(parse(&["render", "scene.json", "--threads"]).is_err());
+        assert!(parse(&["validate", "scene.json", "--threads", "4"]).is_err());
+        assert!(parse(&["render", "a.json", "b.json"]).is_err());
+    }
+}
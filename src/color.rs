@@ -20,6 +20,15 @@ impl Color {
     pub fn grayscale(&self) -> f64 {
         (self.r + self.g + self.b) / 3.0
     }
+
+    /// Brightness as perceived by the eye, for linear sRGB.
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
+
+    pub fn rgb(&self) -> [f64; 3] {
+        [self.r, self.g, self.b]
+    }
 }
 
 impl Mul for Color {
@@ -3,6 +3,7 @@ extern crate rand;
 extern crate geom;
 extern crate utils;
 extern crate rayon;
+extern crate png;
 
 pub mod color;
 pub mod display;
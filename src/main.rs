@@ -4,7 +4,10 @@ extern crate regex;
 extern crate time;
 extern crate utils;
 
-use std::fs;
+mod cli;
+
+use std::{env, fs, process};
+use std::error::Error;
 use std::io::{self, Read};
 use regex::Regex;
 use rustc_serialize::json;
@@ -12,7 +15,8 @@ use rustc_serialize::json;
 use utils::time_it;
 use rustraytracer::display::{PpmWriter, ImageDisplay};
 use rustraytracer::scene::{Scene, SceneConfig};
-use rustraytracer::rendering::{Tracer, TracerConfig};
+use rustraytracer::rendering::{Tracer, TracerConfig, SamplerConfig};
+use cli::{Command, TracerOverrides};
 
 #[derive(Debug, RustcDecodable)]
 struct Config {
@@ -20,30 +24,101 @@ struct Config {
     rendering: TracerConfig,
 }
 
-fn read_scene_description(path: &str) -> String {
+fn read_scene_description(path: &str) -> io::Result<String> {
     let mut result = String::new();
-    fs::File::open(path).unwrap().read_to_string(&mut result).unwrap();
+    fs::File::open(path)?.read_to_string(&mut result)?;
     let comment = Regex::new(r"(?m)^\s*//.*$").unwrap();
-    comment.replace_all(&result, "\n").into_owned()
+    Ok(comment.replace_all(&result, "\n").into_owned())
 }
 
-fn main() {
+fn read_config(path: &str, overrides: &TracerOverrides) -> Result<Config, Box<Error>> {
+    let description = read_scene_description(path)
+        .map_err(|e| format!("can't read {}: {}", path, e))?;
+    let mut conf: Config = json::decode(&description)
+        .map_err(|e| format!("invalid scene description {}: {}", path, e))?;
+    overrides.apply(&mut conf.rendering);
+    Ok(conf)
+}
+
+fn render(scene_path: &str, output: &str, overrides: &TracerOverrides) -> Result<(), Box<Error>> {
     println!("Start rendering...");
     let start = time::precise_time_s();
-    let ((scene, conf), prep_time) = time_it(|| {
-        let conf: Config = json::decode(&read_scene_description("./scenes/buddha.json")).unwrap();
-        let scene = Scene::new(conf.scene).unwrap();
-        (scene, conf.rendering)
+    let (result, prep_time) = time_it(|| -> Result<_, Box<Error>> {
+        let conf = read_config(scene_path, overrides)?;
+        let scene = Scene::new(conf.scene, conf.rendering.resolution)?;
+        Ok((scene, conf.rendering))
     });
+    let (scene, conf) = result?;
     let tracer = Tracer::new(scene, conf);
 
     let (image, stats) = tracer.render();
-    let path = "./out.ppm";
-    let mut file = io::BufWriter::new(fs::File::create(path).unwrap());
+    let file = fs::File::create(output)
+        .map_err(|e| format!("can't create {}: {}", output, e))?;
+    let mut file = io::BufWriter::new(file);
     let mut display = PpmWriter::new(&mut file);
-    display.draw(&image).unwrap();
+    display.draw(&image)?;
 
     let end = time::precise_time_s();
     println!("\nPreprocess:  {:.2}s\n{}\n\nTotal: {:.2} seconds",
              prep_time, stats, end - start);
+    Ok(())
+}
+
+fn validate(scene_path: &str) -> Result<(), Box<Error>> {
+    let conf = read_config(scene_path, &TracerOverrides::default())?;
+    Scene::new(conf.scene, conf.rendering.resolution)?;
+    println!("{}: ok", scene_path);
+    Ok(())
+}
+
+fn info(scene_path: &str, overrides: &TracerOverrides) -> Result<(), Box<Error>> {
+    let conf = read_config(scene_path, overrides)?;
+    let scene = &conf.scene;
+    let rendering = &conf.rendering;
+    let mut materials = scene.materials.keys().cloned().collect::<Vec<_>>();
+    materials.sort();
+
+    println!("Scene:       {}", scene_path);
+    println!("Camera:      {} -> {}", scene.camera.position(), scene.camera.look_at());
+    println!("Materials:   {} ({})", materials.len(), materials.join(", "));
+    println!("Primitives:  {}", scene.primitives.len());
+    println!("Lights:      {}", scene.lights.len());
+    println!("Resolution:  {}x{}", rendering.resolution[0], rendering.resolution[1]);
+    match rendering.sampler {
+        SamplerConfig::Stratified { samples_per_pixel, jitter } =>
+            println!("Sampler:     stratified, {}x{} samples per pixel{}",
+                     samples_per_pixel, samples_per_pixel,
+                     if jitter { ", jittered" } else { "" }),
+    }
+    println!("Reflections: {}", rendering.n_reflections);
+    match rendering.n_threads {
+        Some(n) => println!("Threads:     {}", n),
+        None => println!("Threads:     default"),
+    }
+    Ok(())
+}
+
+fn main() {
+    let command = match cli::parse_args(env::args().skip(1)) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, cli::USAGE);
+            process::exit(2);
+        }
+    };
+
+    let result = match command {
+        Command::Render { scene, output, overrides } => render(&scene, &output, &overrides),
+        Command::Validate { scene } => validate(&scene),
+        Command::Info { scene, overrides } => info(&scene, &overrides),
+        Command::Help => {
+            println!("{}", cli::USAGE);
+            Ok(())
+        }
+    };
+
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
 }
@@ -7,6 +7,16 @@ pub struct TracerConfig {
     pub filter: FilterConfig,
     pub n_reflections: u32,
     pub n_threads: Option<u16>,
+    pub integrator: Option<IntegratorConfig>,
+}
+
+#[derive(Debug, RustcDecodable)]
+pub enum IntegratorConfig {
+    Whitted,
+    PathTracing {
+        max_depth: u32,
+        roulette_depth: u32,
+    }
 }
 
 #[derive(Debug, RustcDecodable)]
@@ -0,0 +1,100 @@
+mod whitted;
+mod path;
+
+use std::f64;
+use std::f64::consts::PI;
+
+use color::Color;
+use geom::{UnitVector, Dot, Ray};
+use scene::{Bsdf, Intersection, LightSample, Scene};
+use super::config::{TracerConfig, IntegratorConfig};
+
+pub use self::whitted::WhittedIntegrator;
+pub use self::path::PathIntegrator;
+
+
+/// Estimates the radiance arriving along a camera ray.
+pub trait Integrator: Send + Sync {
+    fn radiance(&self, scene: &Scene, ray: &Ray) -> Color;
+}
+
+
+pub fn from_config(config: &TracerConfig) -> Box<Integrator> {
+    match config.integrator {
+        None | Some(IntegratorConfig::Whitted) =>
+            Box::new(WhittedIntegrator::new(config.n_reflections)),
+        Some(IntegratorConfig::PathTracing { max_depth, roulette_depth }) =>
+            Box::new(PathIntegrator::new(max_depth, roulette_depth)),
+    }
+}
+
+
+/// Light arriving directly from the light sources, the environment and the
+/// sun and scattered towards the viewer. Point, spot and directional lights
+/// give their irradiance at normal incidence divided by pi, so that a white
+/// Lambertian surface facing a light reflects its illumination. Area lights
+/// are estimated with several shadow rays towards random points of their
+/// surface, weighted by the geometry term and the pdf of the points, the
+/// environment with rays towards its bright parts.
+fn direct_light(scene: &Scene, view_direction: UnitVector, intersection: &Intersection) -> Color {
+    let material = intersection.material;
+    let mut result = Color::new(0.0, 0.0, 0.0);
+    if material.is_delta() {
+        return result;
+    }
+    let point = intersection.geom.point;
+    let wo = -view_direction;
+    for light in scene.lights.iter() {
+        let n_samples = light.n_samples();
+        for _ in 0..n_samples {
+            let (wi, distance, irradiance) = match light.sample(point) {
+                LightSample::Area { point: q, normal, pdf, radiance } => {
+                    let v = q - point;
+                    let distance = v.length();
+                    let wi = v.direction();
+                    let cos_light = -wi.dot(normal);
+                    if cos_light <= 0.0 {
+                        continue;
+                    }
+                    (wi, distance, radiance * (cos_light / (distance * distance * pdf)))
+                }
+                LightSample::Delta { direction, distance, illumination } =>
+                    (direction, distance, illumination * PI),
+            };
+            if !scene.is_visible(wi, distance, &intersection) {
+                continue;
+            }
+            let cos = wi.dot(intersection.shading_normal).abs();
+            let irradiance = irradiance * (cos / n_samples as f64);
+            result = result + material.eval(intersection, wo, wi) * irradiance;
+        }
+    }
+    if let Some(ref environment) = scene.environment {
+        let n_samples = environment.n_samples();
+        for _ in 0..n_samples {
+            let (wi, pdf) = match environment.sample() {
+                Some(sample) => sample,
+                None => break,
+            };
+            if !scene.is_visible(wi, f64::INFINITY, &intersection) {
+                continue;
+            }
+            let cos = wi.dot(intersection.shading_normal).abs();
+            let illumination = environment.radiance(wi) * (cos / (pdf * n_samples as f64));
+            result = result + material.eval(intersection, wo, wi) * illumination;
+        }
+    }
+    if let Some(ref sun) = scene.sun {
+        let n_samples = sun.n_samples();
+        for _ in 0..n_samples {
+            let wi = sun.sample();
+            if !scene.is_visible(wi, f64::INFINITY, &intersection) {
+                continue;
+            }
+            let cos = wi.dot(intersection.shading_normal).abs();
+            let illumination = sun.irradiance() * (cos / n_samples as f64);
+            result = result + material.eval(intersection, wo, wi) * illumination;
+        }
+    }
+    result
+}
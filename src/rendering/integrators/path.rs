@@ -0,0 +1,76 @@
+use rand;
+
+use color::Color;
+use geom::Ray;
+use scene::{Bsdf, Scene};
+use super::{Integrator, direct_light};
+
+
+/// Unbiased Monte Carlo path tracer.
+///
+/// Every vertex of the path gathers direct light from the light sources and
+/// then continues in a direction sampled from the BSDF of the material.
+/// Emissive surfaces and the environment hit by the path count only when the
+/// direct light couldn't have found them: from the camera and after specular
+/// bounces. After `roulette_depth` bounces paths are terminated with Russian
+/// roulette, `max_depth` is a hard limit on the number of bounces.
+pub struct PathIntegrator {
+    max_depth: u32,
+    roulette_depth: u32,
+}
+
+impl PathIntegrator {
+    pub fn new(max_depth: u32, roulette_depth: u32) -> PathIntegrator {
+        PathIntegrator {
+            max_depth: max_depth,
+            roulette_depth: roulette_depth,
+        }
+    }
+}
+
+impl Integrator for PathIntegrator {
+    fn radiance(&self, scene: &Scene, ray: &Ray) -> Color {
+        let mut result = Color::new(0.0, 0.0, 0.0);
+        let mut throughput = Color::new(1.0, 1.0, 1.0);
+        let mut ray = ray.clone();
+        let mut is_specular = true;
+
+        for depth in 0..self.max_depth + 1 {
+            let intersection = match scene.find_obstacle(&ray) {
+                Some(i) => i,
+                None => {
+                    if is_specular || scene.environment.is_none() {
+                        result = result + throughput * scene.background(ray.direction);
+                    }
+                    break;
+                }
+            };
+            if is_specular {
+                result = result + throughput * intersection.material.emitted(&intersection,
+                                                                            -ray.direction);
+            }
+            result = result + throughput * direct_light(scene, ray.direction, &intersection);
+            if depth == self.max_depth {
+                break;
+            }
+
+            let sample = match intersection.material.sample(&intersection, -ray.direction) {
+                Some(sample) => sample,
+                None => break,
+            };
+            throughput = throughput * sample.weight;
+            is_specular = sample.is_delta;
+
+            if depth >= self.roulette_depth {
+                let survival = throughput.grayscale().min(0.95);
+                if rand::random::<f64>() >= survival {
+                    break;
+                }
+                throughput = throughput / survival;
+            }
+            ray = scene.ray_from(&intersection, sample.direction);
+        }
+
+        result
+    }
+}
@@ -0,0 +1,124 @@
+use color::Color;
+use geom::Ray;
+use scene::{Bsdf, Scene};
+use super::{Integrator, direct_light};
+
+
+/// Classic recursive ray tracing: emitted, ambient and direct light plus all
+/// the directions of perfectly specular lobes, up to a fixed depth.
+pub struct WhittedIntegrator {
+    n_reflections: u32,
+}
+
+impl WhittedIntegrator {
+    pub fn new(n_reflections: u32) -> WhittedIntegrator {
+        WhittedIntegrator {
+            n_reflections: n_reflections
+        }
+    }
+
+    fn trace(&self, scene: &Scene, ray: &Ray, level: u32) -> Color {
+        match scene.find_obstacle(ray) {
+            Some(ref intersection) => {
+                let material = intersection.material;
+                let arrived_light = material.emitted(intersection, -ray.direction)
+                    + material.ambient(intersection, scene.ambient_light)
+                    + direct_light(scene, ray.direction, intersection);
+                if level >= self.n_reflections {
+                    return arrived_light;
+                }
+
+                let mut result = arrived_light;
+                for (direction, weight) in material.delta_directions(intersection, -ray.direction) {
+                    let scattered_ray = scene.ray_from(intersection, direction);
+                    result = result + self.trace(scene, &scattered_ray, level + 1) * weight;
+                }
+                result
+            },
+            None => scene.background(ray.direction)
+        }
+    }
+}
+
+impl Integrator for WhittedIntegrator {
+    fn radiance(&self, scene: &Scene, ray: &Ray) -> Color {
+        self.trace(scene, ray, 0)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustc_serialize::json;
+    use color::Color;
+    use geom::Point;
+    use scene::SceneConfig;
+
+    #[test]
+    fn test_legacy_ambient() {
+        let config = r##"{
+            "camera": {"variant": "Perspective", "fields": [{
+                "position": [0, 0, 5], "look_at": [0, 0, 0], "up": [0, 1, 0], "fov": 40
+            }]},
+            "ambient_light": "#848",
+            "background_color": "#000",
+            "materials": {
+                "legacy": {
+                    "diffuse": 0.5, "specular": 20, "specular_color": "#FFF",
+                    "texture": {"variant": "Color", "fields": ["#C63"]}
+                }
+            },
+            "primitives": [
+                {"kind": {"variant": "Sphere", "fields": [[0, 0, 0], 1]}, "material": "legacy"}
+            ],
+            "lights": []
+        }"##;
+        let config: SceneConfig = json::decode(config).unwrap();
+        let scene = Scene::new(config, [10, 10]).unwrap();
+        let ray = Ray::from_to(Point::new(0.0, 0.0, 5.0), Point::new(0.0, 0.0, 0.0));
+
+        // the texture times the ambient light, as before the lobes
+        let expected = "#C63".parse::<Color>().unwrap().rgb();
+        let ambient = "#848".parse::<Color>().unwrap().rgb();
+        let result = WhittedIntegrator::new(0).radiance(&scene, &ray).rgb();
+        for k in 0..3 {
+            assert!((result[k] - expected[k] * ambient[k]).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_sphere_light_from_afar() {
+        let scene = |light: &str| {
+            let config = format!(r##"{{
+                "camera": {{"variant": "Perspective", "fields": [{{
+                    "position": [0, 0, 5], "look_at": [0, 0, 0], "up": [0, 1, 0], "fov": 40
+                }}]}},
+                "ambient_light": "#000",
+                "background_color": "#000",
+                "materials": {{"white": {{"diffuse": 1}}}},
+                "primitives": [
+                    {{"kind": {{"variant": "Sphere", "fields": [[0, 0, 0], 1]}},
+                      "material": "white"}}
+                ],
+                "lights": [{{"position": [0, 0, 101], "color": "#FFF", {}}}]
+            }}"##, light);
+            let config: SceneConfig = json::decode(&config).unwrap();
+            Scene::new(config, [10, 10]).unwrap()
+        };
+        let sphere = scene(r#""intensity": 1,
+            "kind": {"variant": "Sphere", "fields": [0.5, 16]}"#);
+        // a point light fades with the square root of the distance
+        let point = scene(r#""intensity": 0.001, "kind": {"variant": "PointLight", "fields": []}"#);
+        let ray = Ray::from_to(Point::new(0.0, 0.0, 5.0), Point::new(0.0, 0.0, 0.0));
+
+        // 100 units away the sphere is as bright as a point light of the
+        // same intensity at a unit distance
+        let integrator = WhittedIntegrator::new(0);
+        let expected = integrator.radiance(&point, &ray).rgb()[0];
+        let n = 1000;
+        let result = (0..n).map(|_| integrator.radiance(&sphere, &ray).rgb()[0]).sum::<f64>();
+        assert!(expected > 0.0);
+        assert!((result / (n as f64 * expected) - 1.0).abs() < 0.02);
+    }
+}
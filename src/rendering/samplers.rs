@@ -11,6 +11,8 @@ use super::config::SamplerConfig;
 #[derive(Clone, Copy)]
 pub struct Sample {
     pub pixel: ScreenPoint,
+    /// Uniformly distributed point of `[0, 1)^2` for sampling the camera lens.
+    pub lens: [f64; 2],
 }
 
 
@@ -68,7 +70,8 @@ impl Sampler for StratifiedSampler {
                 };
 
                 result.push(Sample {
-                    pixel: to_uniform(self.resolution, ScreenPoint::from([x, y]) + jitter)
+                    pixel: to_uniform(self.resolution, ScreenPoint::from([x, y]) + jitter),
+                    lens: [rand::random::<f64>(), rand::random::<f64>()],
                 })
             }
         }
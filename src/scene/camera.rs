@@ -1,5 +1,7 @@
-use geom::{Point, Vector, Ray, Cross};
-use super::config::CameraConfig;
+use std::f64::consts::PI;
+
+use geom::{Point, Vector, UnitVector, Ray, Cross, Dot};
+use super::config::{PerspectiveConfig, OrthographicConfig, FisheyeConfig, EquirectangularConfig};
 
 
 #[derive(Clone, Copy, Debug)]
@@ -25,67 +27,282 @@ impl From<[f64; 2]> for ScreenPoint {
 }
 
 
+/// Projects points of the normalized screen `[-1, 1)^2` onto rays. Some
+/// projections don't cover the whole screen, `None` is returned for points
+/// outside of the image.
+pub trait Camera: Send + Sync {
+    /// `lens_point` is a uniformly distributed point of `[0, 1)^2`, which
+    /// selects a point on the lens for cameras which have one.
+    fn cast_ray(&self, screen_point: ScreenPoint, lens_point: [f64; 2]) -> Option<Ray>;
+}
+
+
+/// Orthonormal basis of a camera looking at `look_at`, with `up` pointing
+/// roughly upwards.
+struct Frame {
+    forward: UnitVector,
+    right: UnitVector,
+    up: UnitVector,
+}
+
+impl Frame {
+    fn new(position: Point, look_at: Point, up: UnitVector) -> Frame {
+        let forward = position.direction_to(look_at);
+        let right = forward.cross(up).direction();
+        let up = right.cross(forward).direction();
+        Frame {
+            forward: forward,
+            right: right,
+            up: up,
+        }
+    }
+}
+
+
 struct Screen {
     center: Point,
     basis: [Vector; 2],
 }
 
+impl Screen {
+    fn new(center: Point, frame: &Frame, size: [f64; 2]) -> Screen {
+        Screen {
+            center: center,
+            basis: [frame.right * size[0] / 2.0, -frame.up * size[1] / 2.0],
+        }
+    }
+
+    fn point(&self, screen_point: ScreenPoint) -> Point {
+        self.center
+            + self.basis[0] * screen_point.x
+            + self.basis[1] * screen_point.y
+    }
+}
 
-pub struct Camera {
+
+/// Thin lens centered at the camera position. Rays through the same screen
+/// point converge at the plane which is `focal_distance` away from the
+/// lens, everything else is blurred.
+struct Lens {
+    basis: [Vector; 2],
+    forward: UnitVector,
+    focal_distance: f64,
+    n_blades: Option<u32>,
+}
+
+impl Lens {
+    /// Maps uniform `[0, 1)^2` coordinates to a point of the aperture: the
+    /// unit disk, or a regular polygon inscribed in it for bladed apertures.
+    fn aperture_point(&self, lens_point: [f64; 2]) -> (f64, f64) {
+        match self.n_blades {
+            None => {
+                let r = lens_point[0].sqrt();
+                let phi = 2.0 * PI * lens_point[1];
+                (r * phi.cos(), r * phi.sin())
+            }
+            Some(n) => {
+                let n = n as f64;
+                let sector = (lens_point[0] * n).floor();
+                let u = lens_point[0] * n - sector;
+                let (a0, a1) = (2.0 * PI * sector / n, 2.0 * PI * (sector + 1.0) / n);
+                // uniform point in the triangle formed by the center and
+                // the two vertices of the sector
+                let s = u.sqrt();
+                let (w0, w1) = (s * (1.0 - lens_point[1]), s * lens_point[1]);
+                (w0 * a0.cos() + w1 * a1.cos(), w0 * a0.sin() + w1 * a1.sin())
+            }
+        }
+    }
+}
+
+
+pub struct PerspectiveCamera {
     position: Point,
     screen: Screen,
+    lens: Option<Lens>,
+}
+
+impl PerspectiveCamera {
+    /// The screen of the given `size` is placed `focus_distance` away from
+    /// the camera.
+    pub fn new(config: &PerspectiveConfig, focus_distance: f64, size: [f64; 2]) -> PerspectiveCamera {
+        let frame = Frame::new(config.position, config.look_at, config.up);
+        let screen = Screen::new(config.position + frame.forward * focus_distance, &frame, size);
+        let lens = match config.aperture_radius {
+            Some(radius) if radius > 0.0 => {
+                let focal_distance = config.focal_distance
+                    .unwrap_or((config.look_at - config.position).length());
+                assert!(focal_distance > 0.0);
+                if let Some(n) = config.aperture_blades {
+                    assert!(n >= 3, "aperture should have at least three blades");
+                }
+                Some(Lens {
+                    basis: [frame.right * radius, frame.up * radius],
+                    forward: frame.forward,
+                    focal_distance: focal_distance,
+                    n_blades: config.aperture_blades,
+                })
+            }
+            _ => None
+        };
+        PerspectiveCamera {
+            position: config.position,
+            screen: screen,
+            lens: lens,
+        }
+    }
 }
 
-impl Camera {
-    pub fn cast_ray(&self, screen_point: ScreenPoint) -> Ray {
-        let target = self.screen.center
-            + self.screen.basis[0] * screen_point.x
-            + self.screen.basis[1] * screen_point.y;
+impl Camera for PerspectiveCamera {
+    fn cast_ray(&self, screen_point: ScreenPoint, lens_point: [f64; 2]) -> Option<Ray> {
+        let ray = Ray::from_to(self.position, self.screen.point(screen_point));
+        Some(match self.lens {
+            None => ray,
+            Some(ref lens) => {
+                let t = lens.focal_distance / ray.direction.dot(lens.forward);
+                let focus = ray.along(t);
+                let (x, y) = lens.aperture_point(lens_point);
+                let origin = self.position + lens.basis[0] * x + lens.basis[1] * y;
+                Ray::from_to(origin, focus)
+            }
+        })
+    }
+}
+
+
+/// Parallel projection: all rays share the viewing direction and start on
+/// the screen of the given size centered at the camera position.
+pub struct OrthographicCamera {
+    screen: Screen,
+    direction: UnitVector,
+}
 
-        return Ray::from_to(self.position, target);
+impl From<OrthographicConfig> for OrthographicCamera {
+    fn from(config: OrthographicConfig) -> OrthographicCamera {
+        let frame = Frame::new(config.position, config.look_at, config.up);
+        OrthographicCamera {
+            screen: Screen::new(config.position, &frame, config.size),
+            direction: frame.forward,
+        }
     }
 }
 
-impl From<CameraConfig> for Camera {
-    fn from(config: CameraConfig) -> Camera {
-        let ray_to_scren = Ray::from_to(config.position, config.look_at);
-        let screen_center = ray_to_scren.along(config.focus_distance);
-        let right = ray_to_scren.direction.cross(config.up).direction();
-        let up = right.cross(ray_to_scren.direction).direction();
-        let screen = Screen {
-            center: screen_center,
-            basis: [right * config.size[0] / 2.0, -up * config.size[1] / 2.0],
+impl Camera for OrthographicCamera {
+    fn cast_ray(&self, screen_point: ScreenPoint, _lens_point: [f64; 2]) -> Option<Ray> {
+        Some(Ray {
+            origin: self.screen.point(screen_point),
+            direction: self.direction,
+        })
+    }
+}
+
+
+/// Equidistant fisheye: the angle between a ray and the viewing direction is
+/// proportional to the distance of the screen point from the center. The
+/// image circle covering `fov` fits into the shorter side of the image.
+pub struct FisheyeCamera {
+    position: Point,
+    frame: Frame,
+    half_fov: f64,
+    aspect_ratio: f64,
+}
+
+impl FisheyeCamera {
+    pub fn new(config: FisheyeConfig, aspect_ratio: f64) -> FisheyeCamera {
+        FisheyeCamera {
+            position: config.position,
+            frame: Frame::new(config.position, config.look_at, config.up),
+            half_fov: config.fov.to_radians() / 2.0,
+            aspect_ratio: aspect_ratio,
+        }
+    }
+}
+
+impl Camera for FisheyeCamera {
+    fn cast_ray(&self, screen_point: ScreenPoint, _lens_point: [f64; 2]) -> Option<Ray> {
+        let (x, y) = if self.aspect_ratio > 1.0 {
+            (screen_point.x * self.aspect_ratio, screen_point.y)
+        } else {
+            (screen_point.x, screen_point.y / self.aspect_ratio)
         };
-        Camera {
+        let r = (x * x + y * y).sqrt();
+        if r > 1.0 {
+            return None;
+        }
+        let theta = r * self.half_fov;
+        let (x, y) = if r > 0.0 { (x / r, y / r) } else { (0.0, 0.0) };
+        let direction = self.frame.forward * theta.cos()
+            + (self.frame.right * x - self.frame.up * y) * theta.sin();
+        Some(Ray {
+            origin: self.position,
+            direction: direction.direction(),
+        })
+    }
+}
+
+
+/// 360 degree panorama: the horizontal axis of the image spans longitudes
+/// and the vertical axis spans latitudes, with the viewing direction in the
+/// center of the image.
+pub struct EquirectangularCamera {
+    position: Point,
+    frame: Frame,
+}
+
+impl From<EquirectangularConfig> for EquirectangularCamera {
+    fn from(config: EquirectangularConfig) -> EquirectangularCamera {
+        EquirectangularCamera {
             position: config.position,
-            screen: screen
+            frame: Frame::new(config.position, config.look_at, config.up),
         }
     }
 }
 
+impl Camera for EquirectangularCamera {
+    fn cast_ray(&self, screen_point: ScreenPoint, _lens_point: [f64; 2]) -> Option<Ray> {
+        let longitude = screen_point.x * PI;
+        let latitude = -screen_point.y * PI / 2.0;
+        let direction = self.frame.forward * (latitude.cos() * longitude.cos())
+            + self.frame.right * (latitude.cos() * longitude.sin())
+            + self.frame.up * latitude.sin();
+        Some(Ray {
+            origin: self.position,
+            direction: direction.direction(),
+        })
+    }
+}
+
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use scene::config::CameraConfig;
-    use geom::{Vector, Point};
+    use scene::config::{PerspectiveConfig, FisheyeConfig, EquirectangularConfig};
+    use geom::{Vector, Point, Dot};
     use utils::props::{check_prop2};
 
 
-    #[test]
-    fn test_ray_casting() {
-        let config = CameraConfig {
+    fn perspective(aperture_radius: Option<f64>, aperture_blades: Option<u32>) -> PerspectiveCamera {
+        let config = PerspectiveConfig {
             position: Point::new(-10.0, 0.0, 0.0),
             look_at: Point::new(0.0, 0.0, 0.0),
-            focus_distance: 10.0,
             up: Vector::new(0.0, 0.0, 1.0).direction(),
-            size: [6.4, 4.8],
+            focus_distance: Some(10.0),
+            size: Some([6.4, 4.8]),
+            fov: None,
+            aperture_radius: aperture_radius,
+            focal_distance: Some(20.0),
+            aperture_blades: aperture_blades,
         };
-        let cam = Camera::from(config);
+        PerspectiveCamera::new(&config, 10.0, [6.4, 4.8])
+    }
+
+    #[test]
+    fn test_ray_casting() {
+        let cam = perspective(None, None);
         check_prop2(|x: f64, y: f64| {
             let x = x % 1.0;
             let y = y % 1.0;
-            let ray = cam.cast_ray(ScreenPoint::new(x, y));
+            let ray = cam.cast_ray(ScreenPoint::new(x, y), [0.5, 0.5]).unwrap();
             let p = ray.along(10.0);
             assert!(-1.0 < p[0] && p[0] < 0.0);
 
@@ -95,4 +312,50 @@ mod tests {
             assert!(-2.4 < y && y < 2.4);
         })
     }
+
+    #[test]
+    fn test_depth_of_field() {
+        for &blades in [None, Some(6)].iter() {
+            let cam = perspective(Some(0.5), blades);
+            check_prop2(|(x, y): (f64, f64), lens_point: (f64, f64)| {
+                let screen_point = ScreenPoint::new(x % 1.0, y % 1.0);
+                let lens_point = [lens_point.0 % 1.0, lens_point.1 % 1.0];
+                let ray = cam.cast_ray(screen_point, lens_point).unwrap();
+                assert!((ray.origin - Point::new(-10.0, 0.0, 0.0)).length() <= 0.5 + 1e-9);
+                assert!((ray.origin[0] + 10.0).abs() < 1e-9);
+
+                let pinhole = cam.cast_ray(screen_point, [0.0, 0.0]).unwrap();
+                let t = (10.0 - ray.origin[0]) / ray.direction[0];
+                let t_pinhole = (10.0 - pinhole.origin[0]) / pinhole.direction[0];
+                assert!((ray.along(t) - pinhole.along(t_pinhole)).is_almost_zero());
+            })
+        }
+    }
+
+    #[test]
+    fn test_panoramic_cameras() {
+        let position = Point::new(1.0, 2.0, 3.0);
+        let look_at = Point::new(1.0, 5.0, 3.0);
+        let up = Vector::new(0.0, 0.0, 1.0).direction();
+        let forward = position.direction_to(look_at);
+        let center = ScreenPoint::new(0.0, 0.0);
+
+        let fisheye = FisheyeCamera::new(FisheyeConfig {
+            position: position, look_at: look_at, up: up, fov: 180.0
+        }, 2.0);
+        assert!(fisheye.cast_ray(center, [0.0, 0.0]).unwrap().direction.dot(forward) > 0.999);
+        let edge = fisheye.cast_ray(ScreenPoint::new(0.0, 0.999), [0.0, 0.0]).unwrap();
+        assert!(edge.direction.dot(forward).abs() < 0.01);
+        assert!(edge.direction.dot(up) < -0.999);
+        assert!(fisheye.cast_ray(ScreenPoint::new(0.9, 0.0), [0.0, 0.0]).is_none());
+
+        let panorama = EquirectangularCamera::from(EquirectangularConfig {
+            position: position, look_at: look_at, up: up
+        });
+        assert!(panorama.cast_ray(center, [0.0, 0.0]).unwrap().direction.dot(forward) > 0.999);
+        let back = panorama.cast_ray(ScreenPoint::new(-0.999, 0.0), [0.0, 0.0]).unwrap();
+        assert!(back.direction.dot(forward) < -0.999);
+        let top = panorama.cast_ray(ScreenPoint::new(0.3, -0.999), [0.0, 0.0]).unwrap();
+        assert!(top.direction.dot(up) > 0.999);
+    }
 }
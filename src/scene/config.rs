@@ -1,10 +1,19 @@
 use std::collections::HashMap;
 use std::error::Error;
 use std::{fmt, fs, io};
+use std::path::Path;
+use std::sync::Arc;
 
 use color::Color;
-use geom::{Point, UnitVector};
-use geom::shape::{Mesh, Plane, Sphere};
+use geom::{Point, UnitVector, Vector, Cross, Transform};
+use geom::shape::{Shape, Solid, Surface, Mesh, MeshPart, Plane, Sphere, Cuboid, Cylinder, Cone,
+                  Disk, Quad, Torus, Instance, Csg, CsgOperation, Sdf, Implicit, ObjParser,
+                  BvhOptions, SplitMethod, read_ply, read_stl, smooth_normals};
+use super::camera::{Camera, PerspectiveCamera, OrthographicCamera, FisheyeCamera,
+                    EquirectangularCamera};
+use super::light::LightSource;
+use super::material::Material;
+use super::mtl::read_mtl;
 use super::primitive::Primitive;
 
 
@@ -15,41 +24,337 @@ pub struct SceneConfig {
     pub background_color: Color,
     pub materials: HashMap<String, MaterialConfig>,
     pub primitives: Vec<PrimitiveConfig>,
-    pub lights: Vec<LightConfig>
+    pub lights: Vec<LightConfig>,
+    pub bvh: Option<BvhConfig>,
+    /// Replaces the `background_color` and lights the scene.
+    pub environment: Option<EnvironmentConfig>,
+    /// Daylight instead of the `environment`.
+    pub sky: Option<SkyConfig>,
 }
 
 
+/// Equirectangular Radiance `.hdr` or `.pfm` image of the light arriving
+/// from afar, with the center of the image towards `-z`. The `rotation`
+/// turns it counterclockwise around `y` by degrees, the `intensity` scales
+/// it. `n_samples` shadow rays, one by default, are cast towards it.
 #[derive(Debug, RustcDecodable)]
-pub struct CameraConfig {
+pub struct EnvironmentConfig {
+    pub location: String,
+    pub rotation: Option<f64>,
+    pub intensity: Option<f64>,
+    pub n_samples: Option<u32>,
+}
+
+
+/// Clear or hazy sky lit by the sun. The sun is `sun_elevation` degrees
+/// above the horizon and `sun_azimuth` degrees counterclockwise around `y`
+/// from `-z`. `turbidity` goes from 2 for a clear sky to 10 for haze. The
+/// `intensity` scales both the sky and the sun, `n_samples` shadow rays are
+/// cast towards the sky.
+#[derive(Debug, RustcDecodable)]
+pub struct SkyConfig {
+    pub sun_elevation: f64,
+    pub sun_azimuth: f64,
+    pub turbidity: f64,
+    pub intensity: Option<f64>,
+    pub n_samples: Option<u32>,
+    /// The sky has no sun disk without it.
+    pub sun: Option<SunConfig>,
+}
+
+/// The disk of the sun is `angular_diameter` degrees wide, 0.53 by
+/// default. `n_samples` shadow rays towards it soften the shadows.
+#[derive(Debug, RustcDecodable)]
+pub struct SunConfig {
+    pub angular_diameter: Option<f64>,
+    pub n_samples: Option<u32>,
+}
+
+
+/// Parameters of the bounding volume hierarchies built for meshes. The
+/// surface area heuristic is used by default.
+#[derive(Debug, RustcDecodable)]
+pub struct BvhConfig {
+    pub split: Option<BvhSplit>,
+    pub max_leaf_size: Option<usize>,
+    pub traversal_cost: Option<f64>,
+    pub intersection_cost: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, RustcDecodable)]
+pub enum BvhSplit {
+    Median,
+    Sah,
+}
+
+impl BvhConfig {
+    pub fn options(&self) -> Result<BvhOptions, Box<Error>> {
+        let default = BvhOptions::default();
+        let max_leaf_size = self.max_leaf_size.unwrap_or(default.max_leaf_size);
+        // leaves store their sizes in 16 bits
+        if max_leaf_size == 0 || max_leaf_size > u16::max_value() as usize {
+            return Err(Box::new(ParseSceneError {
+                description: "bvh max_leaf_size should be between 1 and 65535".to_string()
+            }));
+        }
+        Ok(BvhOptions {
+            split: match self.split {
+                None => default.split,
+                Some(BvhSplit::Median) => SplitMethod::Median,
+                Some(BvhSplit::Sah) => SplitMethod::Sah,
+            },
+            max_leaf_size: max_leaf_size,
+            traversal_cost: self.traversal_cost.unwrap_or(default.traversal_cost),
+            intersection_cost: self.intersection_cost.unwrap_or(default.intersection_cost),
+        })
+    }
+}
+
+
+#[derive(Debug, RustcDecodable)]
+pub enum CameraConfig {
+    Perspective(PerspectiveConfig),
+    Orthographic(OrthographicConfig),
+    Fisheye(FisheyeConfig),
+    Equirectangular(EquirectangularConfig),
+}
+
+impl CameraConfig {
+    pub fn position(&self) -> Point {
+        match *self {
+            CameraConfig::Perspective(ref c) => c.position,
+            CameraConfig::Orthographic(ref c) => c.position,
+            CameraConfig::Fisheye(ref c) => c.position,
+            CameraConfig::Equirectangular(ref c) => c.position,
+        }
+    }
+
+    pub fn look_at(&self) -> Point {
+        match *self {
+            CameraConfig::Perspective(ref c) => c.look_at,
+            CameraConfig::Orthographic(ref c) => c.look_at,
+            CameraConfig::Fisheye(ref c) => c.look_at,
+            CameraConfig::Equirectangular(ref c) => c.look_at,
+        }
+    }
+}
+
+
+/// The screen is specified either by its `size` together with the
+/// `focus_distance` to it, or by the horizontal `fov` in degrees, in which
+/// case the aspect ratio of the screen matches the image resolution.
+#[derive(Debug, RustcDecodable)]
+pub struct PerspectiveConfig {
     pub position: Point,
     pub look_at: Point,
-    pub focus_distance: f64,
     pub up: UnitVector,
 
+    pub focus_distance: Option<f64>,
+    pub size: Option<[f64; 2]>,
+    pub fov: Option<f64>,
+
+    /// Radius of the thin lens, a pinhole camera is used if it's absent.
+    pub aperture_radius: Option<f64>,
+    /// Distance to the plane in focus, defaults to the distance to `look_at`.
+    pub focal_distance: Option<f64>,
+    /// Number of aperture blades, which gives polygonal bokeh. The aperture
+    /// is round by default.
+    pub aperture_blades: Option<u32>,
+}
+
+
+#[derive(Debug, RustcDecodable)]
+pub struct OrthographicConfig {
+    pub position: Point,
+    pub look_at: Point,
+    pub up: UnitVector,
     pub size: [f64; 2],
 }
 
 
+#[derive(Debug, RustcDecodable)]
+pub struct FisheyeConfig {
+    pub position: Point,
+    pub look_at: Point,
+    pub up: UnitVector,
+    /// Field of view of the image circle in degrees.
+    pub fov: f64,
+}
+
+
+#[derive(Debug, RustcDecodable)]
+pub struct EquirectangularConfig {
+    pub position: Point,
+    pub look_at: Point,
+    pub up: UnitVector,
+}
+
+
+/// A material is either the sum of the `lobes` of its BSDF, or is given by
+/// the legacy Phong-like fields: the `texture` scaled by `diffuse`,
+/// highlights with the `specular` exponent, mirror `reflectance` and
+/// `transmittance` which refracts with the `refractive_index`. These are
+/// mapped onto equivalent lobes.
 #[derive(Debug, RustcDecodable)]
 pub struct MaterialConfig {
-    pub specular: f64,
-    pub diffuse: f64,
+    pub specular: Option<f64>,
+    pub diffuse: Option<f64>,
+    /// White by default.
+    pub texture: Option<TextureConfig>,
+    pub reflectance: Option<f64>,
+    pub transmittance: Option<f64>,
+    pub refractive_index: Option<f64>,
+    pub bump: Option<BumpConfig>,
+    pub normal_map: Option<TextureConfig>,
+    /// Color of the highlights, white by default.
+    pub specular_color: Option<Color>,
+    pub lobes: Option<Vec<LobeConfig>>,
+    /// Makes the primitives of the material light sources.
+    pub emission: Option<EmissionConfig>,
+}
+
+
+/// Radiance of the `color` times the `intensity`. `n_samples` shadow rays,
+/// one by default, are cast towards each emissive primitive.
+#[derive(Debug, RustcDecodable)]
+pub struct EmissionConfig {
+    pub color: Color,
+    pub intensity: f64,
+    pub n_samples: Option<u32>,
+}
+
+
+/// Lobe of a BSDF. `roughness` goes from 0, a perfectly smooth surface, to
+/// 1, the width of the GGX distribution of microfacets is its square.
+#[derive(Debug, RustcDecodable)]
+pub enum LobeConfig {
+    Lambertian(TextureConfig),
+    /// Rough diffuse surface, `sigma` is the standard deviation of the
+    /// slopes of its facets in degrees.
+    OrenNayar {
+        color: TextureConfig,
+        sigma: f64,
+    },
+    /// Energy conserving Blinn-Phong highlights.
+    BlinnPhong {
+        color: TextureConfig,
+        exponent: f64,
+    },
+    /// Metal with the `color` as the reflectance at normal incidence.
+    Conductor {
+        color: TextureConfig,
+        roughness: f64,
+    },
+    /// Glass-like boundary, which reflects and refracts light according to
+    /// the Fresnel equations. The `color` scales the scattered light.
+    Dielectric {
+        color: Color,
+        refractive_index: f64,
+        roughness: f64,
+    },
+    /// Perfect mirror which reflects `color` of the light.
+    Mirror(Color),
+}
+
+
+/// Bump map with the brightness of `texture` as the height, multiplied by
+/// `height` in the scene units.
+#[derive(Debug, RustcDecodable)]
+pub struct BumpConfig {
     pub texture: TextureConfig,
-    pub reflectance: f64,
+    pub height: f64,
 }
 
 
 #[derive(Debug, RustcDecodable)]
 pub enum TextureConfig {
     Checkboard3d(Color, Color),
-    Color(Color)
+    Color(Color),
+    /// PNG or PPM image mapped with the texture coordinates of the shape,
+    /// multiplied by `scale`. Bilinear filtering and repeating are used by
+    /// default.
+    Image {
+        location: String,
+        filtering: Option<TextureFiltering>,
+        wrap: Option<TextureWrap>,
+        scale: Option<[f64; 2]>,
+    },
+    Perlin(NoiseConfig),
+    Fbm(NoiseConfig),
+    Turbulence(NoiseConfig),
+    Marble(NoiseConfig),
+    Wood(NoiseConfig),
+    Worley(NoiseConfig),
 }
 
 
+/// Procedural noise texture, which blends `low` and `high` colors. `scale`
+/// is the size of the noise features, fractal patterns sum 6 `octaves` of
+/// noise by default. The same `seed` always gives the same texture.
+#[derive(Debug, RustcDecodable)]
+pub struct NoiseConfig {
+    pub low: Color,
+    pub high: Color,
+    pub scale: f64,
+    pub octaves: Option<u32>,
+    pub seed: Option<u32>,
+}
+
+
+#[derive(Debug, Clone, Copy, RustcDecodable)]
+pub enum TextureFiltering {
+    Nearest,
+    Bilinear,
+    /// Trilinear filtering, the size of the area seen through a pixel
+    /// selects a level of the mip-map.
+    MipMap,
+}
+
+
+#[derive(Debug, Clone, Copy, RustcDecodable)]
+pub enum TextureWrap {
+    Repeat,
+    Clamp,
+}
+
+
+/// A `transform` places the primitive into the scene. Meshes are loaded
+/// once per file, so transformed copies of a mesh share its memory.
+///
+/// Faces of a mesh which name a material of its .mtl libraries get that
+/// material, unless `material_overrides` maps the name to a scene material.
+/// The other faces, and the other primitives, get `material`.
+///
+/// `mesh_options` only apply to meshes. They are kept out of
+/// `PrimitiveKind::Mesh` so that scenes can leave them out.
 #[derive(Debug, RustcDecodable)]
 pub struct PrimitiveConfig {
     material: String,
     kind: PrimitiveKind,
+    transform: Option<Vec<TransformConfig>>,
+    material_overrides: Option<HashMap<String, String>>,
+    mesh_options: Option<MeshOptions>,
+}
+
+
+#[derive(Debug, Clone, Default, RustcDecodable)]
+pub struct MeshOptions {
+    /// Format of the file, found from its extension by default.
+    pub format: Option<MeshFormat>,
+    /// Faces without normals in the file get smooth normals, averaged over
+    /// the faces at most this many degrees apart. They are flat by default.
+    pub crease_angle: Option<f64>,
+    /// Discards the normals of the file and computes them for all the
+    /// faces, with `crease_angle` or flat.
+    pub recompute_normals: Option<bool>,
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, RustcDecodable)]
+pub enum MeshFormat {
+    Obj,
+    Ply,
+    Stl,
 }
 
 
@@ -66,6 +371,134 @@ pub enum PrimitiveKind {
         position: Point,
         radius: f64
     },
+    /// Axis aligned box between its corners.
+    Cuboid {
+        min: Point,
+        max: Point,
+    },
+    /// Cylinder between the centers of its end disks, which close it if it is
+    /// `capped`.
+    Cylinder {
+        base: Point,
+        top: Point,
+        radius: f64,
+        capped: bool,
+    },
+    /// Cone with the base disk around `base` and the tip at `apex`.
+    Cone {
+        base: Point,
+        apex: Point,
+        radius: f64,
+        capped: bool,
+    },
+    Disk {
+        position: Point,
+        normal: UnitVector,
+        radius: f64,
+    },
+    /// Parallelogram spanned by the edges `u` and `v` from the `corner`.
+    Quad {
+        corner: Point,
+        u: Vector,
+        v: Vector,
+    },
+    /// Torus around the `axis` at its center `position`, the minor radius is
+    /// the radius of the tube.
+    Torus {
+        position: Point,
+        axis: UnitVector,
+        major_radius: f64,
+        minor_radius: f64,
+    },
+    /// Surface given by a signed distance function.
+    Sdf {
+        distance: SdfConfig,
+    },
+    /// Boolean combination of two closed primitives: spheres, cuboids, capped
+    /// cylinders and cones, tori, meshes or other CSG primitives.
+    Csg {
+        operation: BooleanOperation,
+        left: Box<SolidConfig>,
+        right: Box<SolidConfig>,
+    },
+}
+
+#[derive(Debug, RustcDecodable)]
+pub struct SolidConfig {
+    kind: PrimitiveKind,
+    transform: Option<Vec<TransformConfig>>,
+    mesh_options: Option<MeshOptions>,
+}
+
+/// Signed distance function built from primitives and their combinations.
+#[derive(Debug, RustcDecodable)]
+pub enum SdfConfig {
+    Sphere {
+        center: Point,
+        radius: f64,
+    },
+    Cuboid {
+        center: Point,
+        size: Vector,
+    },
+    /// Cuboid of the `size` with the edges rounded by the `radius`.
+    RoundedCuboid {
+        center: Point,
+        size: Vector,
+        radius: f64,
+    },
+    /// Torus around the `y` axis.
+    Torus {
+        center: Point,
+        major_radius: f64,
+        minor_radius: f64,
+    },
+    Capsule {
+        a: Point,
+        b: Point,
+        radius: f64,
+    },
+    SmoothUnion {
+        smoothness: f64,
+        left: Box<SdfConfig>,
+        right: Box<SdfConfig>,
+    },
+    /// `left` minus `right`.
+    SmoothSubtraction {
+        smoothness: f64,
+        left: Box<SdfConfig>,
+        right: Box<SdfConfig>,
+    },
+    /// `copies` more copies on each side along each axis, zero period
+    /// components mean no repetition along that axis.
+    Repeat {
+        period: Vector,
+        copies: [u32; 3],
+        distance: Box<SdfConfig>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, RustcDecodable)]
+pub enum BooleanOperation {
+    Union,
+    Intersection,
+    Difference,
+}
+
+/// Steps of a transform, applied in the order in which they are listed.
+#[derive(Debug, RustcDecodable)]
+pub enum TransformConfig {
+    Translate(Vector),
+    /// Scale factors along the axes.
+    Scale(Vector),
+    /// Rotation about the `axis` through the origin by `angle` degrees,
+    /// counterclockwise when looking against the axis.
+    Rotate {
+        axis: Vector,
+        angle: f64,
+    },
+    /// Rows of a matrix which acts on column vectors.
+    Matrix([[f64; 4]; 4]),
 }
 
 #[derive(Debug)]
@@ -85,24 +518,470 @@ impl fmt::Display for ParseSceneError {
     }
 }
 
-pub fn read_primitive<'a>(conf: PrimitiveConfig, materials: &HashMap<String, usize>)
+pub fn read_camera(conf: CameraConfig, aspect_ratio: f64) -> Result<Box<Camera>, Box<Error>> {
+    let error = |description: &str| ParseSceneError { description: description.to_string() };
+    let camera: Box<Camera> = match conf {
+        CameraConfig::Perspective(conf) => {
+            let (focus_distance, size) = match (conf.fov, conf.size) {
+                (Some(fov), None) => {
+                    if !(0.0 < fov && fov < 180.0) {
+                        return Err(Box::new(error("fov should be between 0 and 180 degrees")));
+                    }
+                    let focus_distance = conf.focus_distance.unwrap_or(1.0);
+                    let width = 2.0 * focus_distance * (fov.to_radians() / 2.0).tan();
+                    (focus_distance, [width, width / aspect_ratio])
+                }
+                (None, Some(size)) => match conf.focus_distance {
+                    Some(focus_distance) => (focus_distance, size),
+                    None => return Err(Box::new(error("focus_distance is required with size"))),
+                },
+                _ => return Err(Box::new(error("camera needs exactly one of fov and size"))),
+            };
+            Box::new(PerspectiveCamera::new(&conf, focus_distance, size))
+        }
+        CameraConfig::Orthographic(conf) => Box::new(OrthographicCamera::from(conf)),
+        CameraConfig::Fisheye(conf) => {
+            if !(0.0 < conf.fov && conf.fov <= 360.0) {
+                return Err(Box::new(error("fov should be between 0 and 360 degrees")));
+            }
+            Box::new(FisheyeCamera::new(conf, aspect_ratio))
+        }
+        CameraConfig::Equirectangular(conf) => Box::new(EquirectangularCamera::from(conf)),
+    };
+    Ok(camera)
+}
+
+/// Mesh loaded from a file, with the materials of its slots: the names
+/// given by the file and the materials found in its .mtl libraries.
+#[derive(Clone)]
+pub struct LoadedMesh {
+    mesh: Arc<Mesh>,
+    slots: Vec<(String, Option<usize>)>,
+}
+
+/// Location, format, the bits of the crease angle if the normals are
+/// smoothed and whether the normals of the file are recomputed: options
+/// which load the same mesh give the same key.
+pub type MeshKey = (String, MeshFormat, Option<u64>, bool);
+
+/// `names` are the indices of the scene `materials` by their names, the
+/// materials of .mtl libraries are added to them. `meshes` caches the meshes
+/// loaded so far by their location and options. Primitives with emissive
+/// materials add their `lights`.
+pub fn read_primitive(conf: PrimitiveConfig,
+                      names: &HashMap<String, usize>,
+                      materials: &mut Vec<Material>,
+                      bvh_options: &BvhOptions,
+                      meshes: &mut HashMap<MeshKey, LoadedMesh>,
+                      lights: &mut Vec<LightSource>)
     -> Result<Primitive, Box<Error>> {
-    let material = materials.get(&conf.material).ok_or(ParseSceneError {
-        description: format!("No such material: {}", conf.material)
-    })?;
-    let material = material.clone();
+    let material = find_material(names, &conf.material)?;
+    let overrides = conf.material_overrides.unwrap_or_default();
 
-    match conf.kind {
+    check_dimensions(&conf.kind)?;
+    let mut slot_materials = Vec::new();
+    // the shapes which can emit light
+    let mut surfaces: Vec<Arc<Surface>> = Vec::new();
+    let mut mesh = None;
+    let shape: Arc<Shape> = match conf.kind {
         PrimitiveKind::Mesh { location } => {
-            let mut file = fs::File::open(&location).map(io::BufReader::new)?;
-            let mesh = Mesh::from_obj(&mut file)?;
-            Ok(Primitive::new(mesh, material))
-        },
+            let options = conf.mesh_options.unwrap_or_default();
+            let loaded = read_mesh(location, &options, materials, bvh_options, meshes)?;
+            for (name, _) in &overrides {
+                if !loaded.slots.iter().any(|slot| &slot.0 == name) {
+                    return Err(Box::new(ParseSceneError {
+                        description: format!("The mesh has no material {}", name)
+                    }));
+                }
+            }
+            for (name, library_material) in loaded.slots {
+                slot_materials.push(match overrides.get(&name) {
+                    Some(replacement) => find_material(names, replacement)?,
+                    None => library_material.unwrap_or(material),
+                });
+            }
+            mesh = Some(loaded.mesh.clone());
+            loaded.mesh
+        }
+        _ if !overrides.is_empty() => return Err(Box::new(ParseSceneError {
+            description: "Only materials of meshes can be overridden".to_string()
+        })),
+        _ if conf.mesh_options.is_some() => return Err(mesh_options_error()),
         PrimitiveKind::Plane { position, normal } =>
-            Ok(Primitive::new(Plane::new(position, normal), material)),
+            Arc::new(Plane::new(position, normal)),
+        PrimitiveKind::Sphere { position, radius } =>
+            surface(Sphere::new(position, radius), &mut surfaces),
+        PrimitiveKind::Cuboid { min, max } => Arc::new(Cuboid::new(min, max)),
+        PrimitiveKind::Cylinder { base, top, radius, capped } =>
+            Arc::new(Cylinder::new(base, top, radius, capped)),
+        PrimitiveKind::Cone { base, apex, radius, capped } =>
+            Arc::new(Cone::new(base, apex, radius, capped)),
+        PrimitiveKind::Disk { position, normal, radius } =>
+            surface(Disk::new(position, normal, radius), &mut surfaces),
+        PrimitiveKind::Quad { corner, u, v } => surface(Quad::new(corner, u, v), &mut surfaces),
+        PrimitiveKind::Torus { position, axis, major_radius, minor_radius } =>
+            Arc::new(Torus::new(position, axis, major_radius, minor_radius)),
+        PrimitiveKind::Sdf { distance } => Arc::new(Implicit::new(read_sdf(distance)?)),
+        PrimitiveKind::Csg { operation, left, right } =>
+            Arc::new(read_csg(operation, *left, *right, materials, bvh_options, meshes)?),
+    };
+    let transform = match conf.transform {
+        None => None,
+        Some(steps) => Some(read_transform(steps)?),
+    };
+    let shape = match transform {
+        None => shape,
+        Some(transform) => Arc::new(Instance::new(shape, transform)),
+    };
+    let primitive = Primitive::new(shape, material).with_slot_materials(slot_materials);
+
+    // emissive surfaces grouped by their materials
+    let mut emitters: Vec<(usize, Vec<Arc<Surface>>)> = Vec::new();
+    match mesh {
+        Some(mesh) => {
+            // the indices of the emissive triangles, which stay in the mesh
+            let mut parts: Vec<(usize, Vec<usize>)> = Vec::new();
+            for (k, triangle) in mesh.triangles().iter().enumerate() {
+                let index = primitive.material_at(triangle.material_slot());
+                if materials[index].emission.is_none() {
+                    continue;
+                }
+                match parts.iter_mut().find(|p| p.0 == index) {
+                    Some(part) => part.1.push(k),
+                    None => parts.push((index, vec![k])),
+                }
+            }
+            for (index, triangles) in parts {
+                let part: Arc<Surface> = Arc::new(MeshPart::new(mesh.clone(), triangles));
+                emitters.push((index, vec![part]));
+            }
+        }
+        None if materials[material].emission.is_some() => {
+            if surfaces.is_empty() {
+                return Err(Box::new(ParseSceneError {
+                    description: "Only spheres, quads, disks and meshes can emit light"
+                        .to_string()
+                }));
+            }
+            emitters.push((material, surfaces));
+        }
+        None => {}
+    }
+    for (index, surfaces) in emitters {
+        let surfaces = match transform {
+            None => surfaces,
+            Some(transform) => surfaces.into_iter()
+                .map(|s| Arc::new(Instance::new(s, transform)) as Arc<Surface>)
+                .collect(),
+        };
+        let emission = materials[index].emission.as_ref().unwrap();
+        lights.push(LightSource::emissive(emission, surfaces));
+    }
+    Ok(primitive)
+}
+
+/// Shares the `shape` with the `surfaces`, in case it emits light.
+fn surface<S: Surface + 'static>(shape: S, surfaces: &mut Vec<Arc<Surface>>) -> Arc<Shape> {
+    let shape = Arc::new(shape);
+    surfaces.push(shape.clone());
+    shape
+}
+
+fn find_material(names: &HashMap<String, usize>, name: &str) -> Result<usize, Box<Error>> {
+    match names.get(name) {
+        Some(&index) => Ok(index),
+        None => Err(Box::new(ParseSceneError {
+            description: format!("No such material: {}", name)
+        })),
+    }
+}
+
+fn mesh_options_error() -> Box<Error> {
+    Box::new(ParseSceneError {
+        description: "Mesh options are only allowed for meshes".to_string()
+    })
+}
+
+fn read_mesh(location: String,
+             options: &MeshOptions,
+             materials: &mut Vec<Material>,
+             bvh_options: &BvhOptions,
+             meshes: &mut HashMap<MeshKey, LoadedMesh>)
+    -> Result<LoadedMesh, Box<Error>> {
+    let format = match options.format {
+        Some(format) => format,
+        None => guess_mesh_format(&location)?,
+    };
+    let crease_angle = options.crease_angle.unwrap_or(0.0);
+    if crease_angle < 0.0 || crease_angle > 180.0 {
+        return Err(Box::new(ParseSceneError {
+            description: "crease_angle should be between 0 and 180 degrees".to_string()
+        }));
+    }
+    let recompute = options.recompute_normals.unwrap_or(false);
+    let smoothing = if options.crease_angle.is_some() || recompute {
+        Some(crease_angle)
+    } else {
+        None
+    };
+    let key = (location.clone(), format, smoothing.map(f64::to_bits), recompute);
+    if !meshes.contains_key(&key) {
+        let mut file = fs::File::open(&location)?;
+        let (mut triangles, slots) = match format {
+            MeshFormat::Obj => {
+                let mut parser = ObjParser::new();
+                parser.read(&mut file)?;
+                let directory = Path::new(&location).parent().unwrap_or(Path::new(""));
+                let library = read_material_libraries(directory, &parser.material_libraries,
+                                                      materials)?;
+                let slots = parser.materials.iter()
+                    .map(|name| (name.clone(), library.get(name).cloned()))
+                    .collect();
+                (parser.faces, slots)
+            }
+            MeshFormat::Ply => (read_ply(&mut file)?, Vec::new()),
+            MeshFormat::Stl => (read_stl(&mut file)?, Vec::new()),
+        };
+        if let Some(crease_angle) = smoothing {
+            smooth_normals(&mut triangles, crease_angle.to_radians(), recompute);
+        }
+        meshes.insert(key.clone(), LoadedMesh {
+            mesh: Arc::new(Mesh::new(triangles, bvh_options)),
+            slots: slots,
+        });
+    }
+    Ok(meshes[&key].clone())
+}
+
+fn guess_mesh_format(location: &str) -> Result<MeshFormat, Box<Error>> {
+    let extension = Path::new(location).extension()
+        .map(|e| e.to_string_lossy().to_lowercase());
+    match extension.as_ref().map(|e| &e[..]) {
+        Some("obj") => Ok(MeshFormat::Obj),
+        Some("ply") => Ok(MeshFormat::Ply),
+        Some("stl") => Ok(MeshFormat::Stl),
+        _ => Err(Box::new(ParseSceneError {
+            description: format!("Unknown mesh format of {}, set it in the mesh options",
+                                 location)
+        })),
+    }
+}
+
+/// Adds the materials of the .mtl libraries in the `directory` and gives
+/// their indices by names. Missing libraries are skipped: models often
+/// name libraries which don't come with them.
+fn read_material_libraries(directory: &Path,
+                           libraries: &[String],
+                           materials: &mut Vec<Material>)
+    -> Result<HashMap<String, usize>, Box<Error>> {
+    let mut result = HashMap::new();
+    for library in libraries {
+        let path = directory.join(library);
+        let mut file = match fs::File::open(&path) {
+            Ok(file) => file,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(Box::new(e)),
+        };
+        let library_directory = path.parent().unwrap_or(directory);
+        for (name, config) in read_mtl(&mut file, library_directory)? {
+            result.insert(name, materials.len());
+            materials.push(Material::new(config)?);
+        }
+    }
+    Ok(result)
+}
+
+fn read_csg(operation: BooleanOperation,
+            left: SolidConfig,
+            right: SolidConfig,
+            materials: &mut Vec<Material>,
+            bvh_options: &BvhOptions,
+            meshes: &mut HashMap<MeshKey, LoadedMesh>)
+    -> Result<Csg, Box<Error>> {
+    let operation = match operation {
+        BooleanOperation::Union => CsgOperation::Union,
+        BooleanOperation::Intersection => CsgOperation::Intersection,
+        BooleanOperation::Difference => CsgOperation::Difference,
+    };
+    let left = read_solid(left, materials, bvh_options, meshes)?;
+    let right = read_solid(right, materials, bvh_options, meshes)?;
+    Ok(Csg::new(operation, left, right))
+}
+
+fn read_solid(conf: SolidConfig,
+              materials: &mut Vec<Material>,
+              bvh_options: &BvhOptions,
+              meshes: &mut HashMap<MeshKey, LoadedMesh>)
+    -> Result<Arc<Solid>, Box<Error>> {
+    check_dimensions(&conf.kind)?;
+    let solid: Arc<Solid> = match conf.kind {
+        PrimitiveKind::Mesh { location } => {
+            let options = conf.mesh_options.unwrap_or_default();
+            read_mesh(location, &options, materials, bvh_options, meshes)?.mesh
+        }
+        _ if conf.mesh_options.is_some() => return Err(mesh_options_error()),
         PrimitiveKind::Sphere { position, radius } =>
-            Ok(Primitive::new(Sphere::new(position, radius), material))
+            Arc::new(Sphere::new(position, radius)),
+        PrimitiveKind::Cuboid { min, max } => Arc::new(Cuboid::new(min, max)),
+        PrimitiveKind::Cylinder { base, top, radius, capped: true } =>
+            Arc::new(Cylinder::new(base, top, radius, true)),
+        PrimitiveKind::Cone { base, apex, radius, capped: true } =>
+            Arc::new(Cone::new(base, apex, radius, true)),
+        PrimitiveKind::Torus { position, axis, major_radius, minor_radius } =>
+            Arc::new(Torus::new(position, axis, major_radius, minor_radius)),
+        PrimitiveKind::Csg { operation, left, right } =>
+            Arc::new(read_csg(operation, *left, *right, materials, bvh_options, meshes)?),
+        PrimitiveKind::Plane { .. } |
+        PrimitiveKind::Cylinder { .. } |
+        PrimitiveKind::Cone { .. } |
+        PrimitiveKind::Disk { .. } |
+        PrimitiveKind::Quad { .. } |
+        PrimitiveKind::Sdf { .. } => return Err(Box::new(ParseSceneError {
+            description: "Only closed primitives can be combined".to_string()
+        })),
+    };
+    Ok(match conf.transform {
+        None => solid,
+        Some(steps) => Arc::new(Instance::new(solid, read_transform(steps)?)),
+    })
+}
+
+fn read_sdf(conf: SdfConfig) -> Result<Sdf, Box<Error>> {
+    let error = |description: &str| Err(Box::new(ParseSceneError {
+        description: description.to_string()
+    }) as Box<Error>);
+    let positive = |v: Vector| v.x > 0.0 && v.y > 0.0 && v.z > 0.0;
+    let sdf = match conf {
+        SdfConfig::Sphere { center, radius } => {
+            if !(radius > 0.0) {
+                return error("radius should be positive");
+            }
+            Sdf::Sphere { center: center, radius: radius }
+        }
+        SdfConfig::Cuboid { center, size } => {
+            if !positive(size) {
+                return error("cuboid size should be positive");
+            }
+            Sdf::Cuboid { center: center, half_size: size / 2.0 }
+        }
+        SdfConfig::RoundedCuboid { center, size, radius } => {
+            if !positive(size) {
+                return error("cuboid size should be positive");
+            }
+            if !(0.0 <= radius && 2.0 * radius <= size.x.min(size.y).min(size.z)) {
+                return error("rounding radius should be at most half of the cuboid size");
+            }
+            Sdf::RoundedCuboid { center: center, half_size: size / 2.0, radius: radius }
+        }
+        SdfConfig::Torus { center, major_radius, minor_radius } => {
+            if !(0.0 < minor_radius && minor_radius < major_radius) {
+                return error("torus minor radius should be positive and below the major one");
+            }
+            Sdf::Torus { center: center, major_radius: major_radius, minor_radius: minor_radius }
+        }
+        SdfConfig::Capsule { a, b, radius } => {
+            if !(radius > 0.0) {
+                return error("radius should be positive");
+            }
+            Sdf::Capsule { a: a, b: b, radius: radius }
+        }
+        SdfConfig::SmoothUnion { smoothness, left, right } => {
+            if !(smoothness >= 0.0) {
+                return error("smoothness should not be negative");
+            }
+            Sdf::SmoothUnion {
+                smoothness: smoothness,
+                left: Box::new(read_sdf(*left)?),
+                right: Box::new(read_sdf(*right)?),
+            }
+        }
+        SdfConfig::SmoothSubtraction { smoothness, left, right } => {
+            if !(smoothness >= 0.0) {
+                return error("smoothness should not be negative");
+            }
+            Sdf::SmoothSubtraction {
+                smoothness: smoothness,
+                left: Box::new(read_sdf(*left)?),
+                right: Box::new(read_sdf(*right)?),
+            }
+        }
+        SdfConfig::Repeat { period, copies, distance } => {
+            if period.x < 0.0 || period.y < 0.0 || period.z < 0.0 {
+                return error("repetition period should not be negative");
+            }
+            Sdf::Repeat { period: period, copies: copies, sdf: Box::new(read_sdf(*distance)?) }
+        }
+    };
+    Ok(sdf)
+}
+
+/// Rejects the degenerate sizes which the shapes don't accept.
+fn check_dimensions(kind: &PrimitiveKind) -> Result<(), Box<Error>> {
+    let error = |description: &str| Err(Box::new(ParseSceneError {
+        description: description.to_string()
+    }) as Box<Error>);
+    match *kind {
+        PrimitiveKind::Cuboid { min, max } => {
+            if !(0..3).all(|axis| min[axis] < max[axis]) {
+                return error("cuboid min should be below max on every axis");
+            }
+        }
+        PrimitiveKind::Cylinder { base, top: end, radius, .. } |
+        PrimitiveKind::Cone { base, apex: end, radius, .. } => {
+            if !(radius > 0.0) {
+                return error("radius should be positive");
+            }
+            if (end - base).is_almost_zero() {
+                return error("height should be positive");
+            }
+        }
+        PrimitiveKind::Sphere { radius, .. } |
+        PrimitiveKind::Disk { radius, .. } => {
+            if !(radius > 0.0) {
+                return error("radius should be positive");
+            }
+        }
+        PrimitiveKind::Quad { u, v, .. } => {
+            if u.cross(v).is_almost_zero() {
+                return error("quad edges should not be parallel");
+            }
+        }
+        PrimitiveKind::Torus { major_radius, minor_radius, .. } => {
+            if !(0.0 < minor_radius && minor_radius < major_radius) {
+                return error("torus minor radius should be positive and below the major one");
+            }
+        }
+        _ => {}
     }
+    Ok(())
+}
+
+fn read_transform(steps: Vec<TransformConfig>) -> Result<Transform, Box<Error>> {
+    let error = |description: &str| ParseSceneError { description: description.to_string() };
+    let mut result = Transform::identity();
+    for step in steps {
+        let transform = match step {
+            TransformConfig::Translate(v) => Transform::translation(v),
+            TransformConfig::Scale(s) => {
+                if s.x == 0.0 || s.y == 0.0 || s.z == 0.0 {
+                    return Err(Box::new(error("scale factors should be nonzero")));
+                }
+                Transform::scaling(s)
+            }
+            TransformConfig::Rotate { axis, angle } => {
+                if axis.is_almost_zero() {
+                    return Err(Box::new(error("rotation axis should be nonzero")));
+                }
+                Transform::rotation(axis.direction(), angle.to_radians())
+            }
+            TransformConfig::Matrix(m) => match Transform::new(m) {
+                Some(transform) => transform,
+                None => return Err(Box::new(error("transform matrix should be invertible"))),
+            },
+        };
+        result = result.then(&transform);
+    }
+    Ok(result)
 }
 
 #[derive(Debug, RustcDecodable)]
@@ -116,9 +995,58 @@ pub struct LightConfig {
 #[derive(Debug, RustcDecodable)]
 pub enum LightKind {
     PointLight,
+    /// Parallel rays from infinitely far away, going the way from the light
+    /// position to `look_at`.
+    Directional {
+        look_at: Point,
+    },
     SpotLight {
         look_at: Point,
         inner_angle: f64,
         outer_angle: f64,
     },
+    /// Parallelogram centered at the light position, spanned by `u` and
+    /// `v`, which shines towards `u x v`.
+    Rectangle {
+        u: Vector,
+        v: Vector,
+        n_samples: u32,
+    },
+    Disk {
+        normal: UnitVector,
+        radius: f64,
+        n_samples: u32,
+    },
+    Sphere {
+        radius: f64,
+        n_samples: u32,
+    },
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustc_serialize::json;
+
+    #[test]
+    fn test_sphere_radius() {
+        let sphere = |radius: f64| PrimitiveKind::Sphere {
+            position: Point::new(0.0, 0.0, 0.0),
+            radius: radius,
+        };
+        assert!(check_dimensions(&sphere(1.0)).is_ok());
+        assert!(check_dimensions(&sphere(0.0)).is_err());
+        assert!(check_dimensions(&sphere(-1.0)).is_err());
+
+        // the solids of CSG primitives are checked as well
+        let csg = r#"{"variant": "Csg", "fields": ["Difference",
+            {"kind": {"variant": "Cuboid", "fields": [[-1, -1, -1], [1, 1, 1]]}},
+            {"kind": {"variant": "Sphere", "fields": [[0, 0, 0], -1.2]}}
+        ]}"#;
+        let csg: PrimitiveKind = json::decode(csg).unwrap();
+        let result = read_solid(SolidConfig { kind: csg, transform: None, mesh_options: None },
+                                &mut Vec::new(), &BvhOptions::default(), &mut HashMap::new());
+        assert!(result.is_err());
+    }
 }
@@ -0,0 +1,260 @@
+use std::error::Error;
+use std::f64::consts::PI;
+
+use rand;
+use utils::datastructures::Matrix;
+
+use color::Color;
+use geom::{UnitVector, Vector};
+use super::config::EnvironmentConfig;
+use super::texture::read_image;
+
+
+/// Light arriving from infinitely far away, given by an equirectangular
+/// image: the longitude goes along its width and the latitude along its
+/// height, with `y` up at the top. The center of the image is seen looking
+/// towards `-z`.
+pub struct Environment {
+    image: Matrix<Color>,
+    intensity: f64,
+    /// Counterclockwise around `y` when looking down, in radians.
+    rotation: f64,
+    n_samples: u32,
+    /// Directions are sampled proportionally to the luminance: a row is
+    /// picked by its total, then a pixel of the row. A pixel weighs as much
+    /// as the brightest of its neighbours, which bleed into it when the
+    /// image is interpolated, so that no light has a zero probability.
+    rows: Distribution,
+    columns: Vec<Distribution>,
+}
+
+impl Environment {
+    pub fn new(config: EnvironmentConfig) -> Result<Environment, Box<Error>> {
+        let intensity = config.intensity.unwrap_or(1.0);
+        if intensity < 0.0 {
+            return Err(From::from("environment intensity should not be negative"));
+        }
+        let n_samples = config.n_samples.unwrap_or(1);
+        if n_samples == 0 {
+            return Err(From::from("environment should have at least one sample"));
+        }
+        let image = read_image(&config.location)
+            .map_err(|e| format!("can't read environment {}: {}", config.location, e))?;
+        let rotation = config.rotation.unwrap_or(0.0).to_radians();
+        Ok(Environment::from_image(image, intensity, rotation, n_samples))
+    }
+
+    pub fn from_image(image: Matrix<Color>, intensity: f64, rotation: f64, n_samples: u32)
+        -> Environment {
+        let (w, h) = (image.width(), image.height());
+        let neighbourhood = |x: u32, y: u32| {
+            let mut luminance = 0.0f64;
+            for ny in y.saturating_sub(1)..(y + 2).min(h) {
+                for nx in &[(x + w - 1) % w, x, (x + 1) % w] {
+                    luminance = luminance.max(image[[*nx, ny]].luminance());
+                }
+            }
+            luminance
+        };
+        let columns = (0..h)
+            .map(|y| {
+                // the rows near the poles cover less of the sphere
+                let sin = (PI * (y as f64 + 0.5) / h as f64).sin();
+                Distribution::new((0..w).map(|x| neighbourhood(x, y) * sin).collect())
+            })
+            .collect::<Vec<_>>();
+        let rows = Distribution::new(columns.iter().map(|c| c.total).collect());
+        Environment {
+            image: image,
+            intensity: intensity,
+            rotation: rotation,
+            n_samples: n_samples,
+            rows: rows,
+            columns: columns,
+        }
+    }
+
+    /// Number of shadow rays which should be used to estimate the light
+    /// arriving at a point.
+    pub fn n_samples(&self) -> u32 {
+        self.n_samples
+    }
+
+    /// Light arriving along a ray going in the `direction`.
+    pub fn radiance(&self, direction: UnitVector) -> Color {
+        let (u, t) = self.image_coordinates(direction);
+        let (w, h) = (self.image.width() as i64, self.image.height() as i64);
+        // bilinear interpolation between the pixel centers, wrapping around
+        // horizontally
+        let x = u * w as f64 - 0.5;
+        let y = t * h as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let texel = |x: i64, y: i64| {
+            let x = ((x % w) + w) % w;
+            let y = y.max(0).min(h - 1);
+            self.image[[x as u32, y as u32]]
+        };
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let top = texel(x0, y0) * (1.0 - fx) + texel(x0 + 1, y0) * fx;
+        let bottom = texel(x0, y0 + 1) * (1.0 - fx) + texel(x0 + 1, y0 + 1) * fx;
+        (top * (1.0 - fy) + bottom * fy) * self.intensity
+    }
+
+    /// Random direction towards the environment with its probability
+    /// density with respect to the solid angle. Bright pixels are chosen
+    /// more often, nothing is chosen if the image is black.
+    pub fn sample(&self) -> Option<(UnitVector, f64)> {
+        if self.rows.total <= 0.0 {
+            return None;
+        }
+        let (y, dy) = self.rows.sample(rand::random::<f64>());
+        let (x, dx) = self.columns[y].sample(rand::random::<f64>());
+        let u = (x as f64 + dx) / self.image.width() as f64;
+        let t = (y as f64 + dy) / self.image.height() as f64;
+        let direction = self.rotated(direction_at(u, t));
+        let pdf = self.pdf(direction);
+        if pdf > 0.0 { Some((direction, pdf)) } else { None }
+    }
+
+    /// Probability density of `sample` choosing the `direction`.
+    pub fn pdf(&self, direction: UnitVector) -> f64 {
+        if self.rows.total <= 0.0 {
+            return 0.0;
+        }
+        let (u, t) = self.image_coordinates(direction);
+        let (w, h) = (self.image.width(), self.image.height());
+        let x = ((u * w as f64) as usize).min(w as usize - 1);
+        let y = ((t * h as f64) as usize).min(h as usize - 1);
+        let sin = (PI * t).sin();
+        if sin <= 0.0 {
+            return 0.0;
+        }
+        // density over the image, which spans 2 pi by pi radians
+        let density = self.rows.probability(y) * self.columns[y].probability(x) * (w * h) as f64;
+        density / (2.0 * PI * PI * sin)
+    }
+
+    /// Horizontal position in the image and the position from its top, both
+    /// in `[0, 1]`.
+    fn image_coordinates(&self, direction: UnitVector) -> (f64, f64) {
+        let (sin, cos) = self.rotation.sin_cos();
+        let d = Vector::from(direction);
+        let x = d.x * cos - d.z * sin;
+        let z = d.x * sin + d.z * cos;
+        let u = 0.5 + x.atan2(-z) / (2.0 * PI);
+        let t = d.y.max(-1.0).min(1.0).acos() / PI;
+        (u, t)
+    }
+
+    fn rotated(&self, d: UnitVector) -> UnitVector {
+        let (sin, cos) = self.rotation.sin_cos();
+        Vector::new(d[0] * cos + d[2] * sin, d[1], -d[0] * sin + d[2] * cos).direction()
+    }
+}
+
+
+/// Direction of the point of an equirectangular image which is `u` of its
+/// width from the left and `t` of its height from the top.
+pub fn direction_at(u: f64, t: f64) -> UnitVector {
+    let phi = (u - 0.5) * 2.0 * PI;
+    let theta = t * PI;
+    Vector::new(theta.sin() * phi.sin(), theta.cos(), -theta.sin() * phi.cos()).direction()
+}
+
+
+/// Piecewise constant distribution of a value in `[0, 1)` split into slots
+/// of the given weights.
+struct Distribution {
+    /// Running totals of the weights.
+    cdf: Vec<f64>,
+    total: f64,
+}
+
+impl Distribution {
+    fn new(weights: Vec<f64>) -> Distribution {
+        let mut total = 0.0;
+        let cdf = weights.iter()
+            .map(|&w| { total += w; total })
+            .collect();
+        Distribution {
+            cdf: cdf,
+            total: total,
+        }
+    }
+
+    /// The slot where `u` of a uniform random value in `[0, 1)` falls, and
+    /// the position within the slot.
+    fn sample(&self, u: f64) -> (usize, f64) {
+        let x = u * self.total;
+        // the first slot whose running total exceeds `x`
+        let k = match self.cdf.binary_search_by(|total| total.partial_cmp(&x).unwrap()) {
+            Ok(k) => k + 1,
+            Err(k) => k,
+        };
+        let k = k.min(self.cdf.len() - 1);
+        let start = if k == 0 { 0.0 } else { self.cdf[k - 1] };
+        let width = self.cdf[k] - start;
+        let offset = if width > 0.0 { (x - start) / width } else { 0.5 };
+        (k, offset.max(0.0).min(1.0 - 1e-12))
+    }
+
+    fn probability(&self, k: usize) -> f64 {
+        let start = if k == 0 { 0.0 } else { self.cdf[k - 1] };
+        (self.cdf[k] - start) / self.total
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use utils::datastructures::Matrix;
+    use color::Color;
+    use geom::Vector;
+
+    #[test]
+    fn test_environment_sampling() {
+        let mut image = Matrix::fill([8, 4], Color::new(0.1, 0.1, 0.1));
+        image[[6, 1]] = Color::new(100.0, 100.0, 100.0);
+        let environment = Environment::from_image(image, 1.0, 0.5, 1);
+
+        // estimate the integral of the radiance over the sphere, most
+        // samples go towards the bright pixel and its neighbours
+        let n = 20000;
+        let mut bright = 0;
+        let mut estimate = 0.0;
+        for _ in 0..n {
+            let (direction, pdf) = environment.sample().unwrap();
+            assert!((environment.pdf(direction) - pdf).abs() < 1e-6 * pdf);
+            let (u, t) = environment.image_coordinates(direction);
+            if 5.0 / 8.0 <= u && t <= 3.0 / 4.0 {
+                bright += 1;
+            }
+            estimate += environment.radiance(direction).grayscale() / pdf / n as f64;
+        }
+        assert!(bright > n / 2);
+        let (w, h) = (512, 256);
+        let mut exact = 0.0;
+        for y in 0..h {
+            for x in 0..w {
+                let t = (y as f64 + 0.5) / h as f64;
+                let direction = direction_at((x as f64 + 0.5) / w as f64, t);
+                let solid_angle = 2.0 * PI * PI * (PI * t).sin() / (w * h) as f64;
+                exact += environment.radiance(direction).grayscale() * solid_angle;
+            }
+        }
+        assert!((estimate / exact - 1.0).abs() < 0.05);
+
+        let up = Vector::new(0.0, 1.0, 0.0).direction();
+        assert!(environment.radiance(up).grayscale() < 1.0);
+
+        // a black pixel next to a bright one is lit by the interpolation
+        let mut image = Matrix::fill([8, 4], Color::new(0.0, 0.0, 0.0));
+        image[[2, 2]] = Color::new(100.0, 100.0, 100.0);
+        let environment = Environment::from_image(image, 1.0, 0.0, 1);
+        let direction = direction_at(3.2 / 8.0, 2.5 / 4.0);
+        assert!(environment.radiance(direction).grayscale() > 10.0);
+        assert!(environment.pdf(direction) > 0.0);
+    }
+}
@@ -1,83 +1,235 @@
+use std::error::Error;
+use std::f64::consts::PI;
+use std::f64;
+use std::sync::Arc;
+
+use rand;
+
 use color::Color;
-use geom::{Point, UnitVector, Dot};
+use geom::{Point, UnitVector, Vector, Dot, Cross};
+use geom::shape::Surface;
 use super::config::{LightConfig, LightKind};
+use super::material::Emission;
+
+
+/// A part of a light source chosen to illuminate a shading point, ignoring
+/// obstacles.
+#[derive(Debug, Clone, Copy)]
+pub enum LightSample {
+    /// A point on the surface of an area light. `pdf` is the probability
+    /// density of choosing it with respect to the surface area, and
+    /// `radiance` is what the point emits to the side of its `normal`. The
+    /// integrator applies the geometry term between the two points.
+    Area {
+        point: Point,
+        normal: UnitVector,
+        pdf: f64,
+        radiance: Color,
+    },
+    /// Light which comes from a single point or direction. `illumination` is
+    /// the irradiance at normal incidence divided by pi, `distance` is
+    /// infinite for directional lights.
+    Delta {
+        direction: UnitVector,
+        distance: f64,
+        illumination: Color,
+    },
+}
 
 
 pub struct LightSource {
     color: Color,
     intensity: f64,
-    position: Point,
+    n_samples: u32,
     source: Box<LightSourceImpl>,
 }
 
 impl LightSource {
-    pub fn position(&self) -> Point {
-        self.position
+    /// Number of shadow rays which should be used to estimate the light
+    /// arriving at a point.
+    pub fn n_samples(&self) -> u32 {
+        self.n_samples
+    }
+
+    /// A random part of the light which may illuminate `p`.
+    pub fn sample(&self, p: Point) -> LightSample {
+        let color = self.color * self.intensity;
+        match self.source.sample(p) {
+            LightSample::Area { point, normal, pdf, radiance } => LightSample::Area {
+                point: point,
+                normal: normal,
+                pdf: pdf,
+                radiance: radiance * color,
+            },
+            LightSample::Delta { direction, distance, illumination } => LightSample::Delta {
+                direction: direction,
+                distance: distance,
+                illumination: illumination * color,
+            },
+        }
     }
 
-    pub fn illuminate(&self, p: Point) -> Color {
-        let v = p - self.position();
-        let distance = v.length();
-        let direction = v.direction();
-        let coef = self.intensity * self.source.intensity_at(direction) / distance.sqrt();
-        return self.color * coef
+    /// Light of emissive `surfaces`, whose material has the `emission`.
+    /// Points are sampled uniformly over their total area.
+    pub fn emissive(emission: &Emission, surfaces: Vec<Arc<Surface>>) -> LightSource {
+        assert!(!surfaces.is_empty(), "emissive light without surfaces");
+        let mut area = 0.0;
+        let mut cdf = Vec::with_capacity(surfaces.len());
+        for surface in &surfaces {
+            area += surface.area();
+            cdf.push(area);
+        }
+        LightSource {
+            color: emission.radiance,
+            intensity: 1.0,
+            n_samples: emission.n_samples,
+            source: Box::new(SurfaceLight {
+                surfaces: surfaces,
+                cdf: cdf,
+                area: area,
+            }),
+        }
     }
 }
 
 
-impl From<LightConfig> for LightSource {
-    fn from(config: LightConfig) -> LightSource {
-        let source: Box<LightSourceImpl> = match config.kind {
-            LightKind::PointLight => Box::new(PointLight),
+impl LightSource {
+    pub fn new(config: LightConfig) -> Result<LightSource, Box<Error>> {
+        if !(config.intensity >= 0.0) {
+            return Err(From::from("light intensity should not be negative"));
+        }
+        let position = config.position;
+        let (source, n_samples): (Box<LightSourceImpl>, u32) = match config.kind {
+            LightKind::PointLight => (Box::new(PointLight { position: position }), 1),
+            LightKind::Directional { look_at } => {
+                let direction = look_at - position;
+                if direction.is_almost_zero() {
+                    return Err(From::from("directional light should look away from its position"));
+                }
+                (Box::new(DirectionalLight { direction: direction.direction() }), 1)
+            }
             LightKind::SpotLight { look_at, inner_angle, outer_angle } => {
-                assert!(inner_angle <= outer_angle);
-                let direction = (look_at - config.position).direction();
-                Box::new(SpotLight {
-                    direction: direction,
+                let direction = look_at - position;
+                if direction.is_almost_zero() {
+                    return Err(From::from("spot light should look away from its position"));
+                }
+                if !(inner_angle <= outer_angle) {
+                    return Err(From::from("inner_angle should not exceed outer_angle"));
+                }
+                (Box::new(SpotLight {
+                    position: position,
+                    direction: direction.direction(),
                     inner_cos: inner_angle.cos(),
                     outer_cos: outer_angle.cos(),
-                })
+                }), 1)
+            }
+            LightKind::Rectangle { u, v, n_samples } => {
+                let normal = u.cross(v);
+                if normal.is_almost_zero() {
+                    return Err(From::from("edges of a rectangle light should not be parallel"));
+                }
+                (Box::new(RectangleLight {
+                    corner: position + u * -0.5 + v * -0.5,
+                    u: u,
+                    v: v,
+                    normal: normal.direction(),
+                    area: normal.length(),
+                }), n_samples)
+            }
+            LightKind::Disk { normal, radius, n_samples } => {
+                if !(radius > 0.0) {
+                    return Err(From::from("radius should be positive"));
+                }
+                (Box::new(DiskLight {
+                    center: position,
+                    normal: normal,
+                    basis: normal.orthonormal_basis(),
+                    radius: radius,
+                }), n_samples)
+            }
+            LightKind::Sphere { radius, n_samples } => {
+                if !(radius > 0.0) {
+                    return Err(From::from("radius should be positive"));
+                }
+                (Box::new(SphereLight {
+                    center: position,
+                    radius: radius,
+                }), n_samples)
             }
         };
-        LightSource {
+        if n_samples == 0 {
+            return Err(From::from("light should have at least one sample"));
+        }
+        Ok(LightSource {
             color: config.color,
             intensity: config.intensity,
-            position: config.position,
+            n_samples: n_samples,
             source: source,
-        }
+        })
     }
 }
 
 
 trait LightSourceImpl: Send + Sync {
-    fn intensity_at(&self, d: UnitVector) -> f64;
+    /// A random part of the light which may illuminate `p`, for a white
+    /// light of unit intensity.
+    fn sample(&self, p: Point) -> LightSample;
 }
 
 
-struct PointLight;
+/// Parallel rays from infinitely far away, which don't fade.
+struct DirectionalLight {
+    /// Where the light goes.
+    direction: UnitVector,
+}
+
+impl LightSourceImpl for DirectionalLight {
+    fn sample(&self, _p: Point) -> LightSample {
+        LightSample::Delta {
+            direction: -self.direction,
+            distance: f64::INFINITY,
+            illumination: Color::new(1.0, 1.0, 1.0),
+        }
+    }
+}
 
 
+/// Point and spot lights fade with the square root of the distance, as they
+/// always did, so that existing scenes keep their look.
+fn delta_sample(position: Point, p: Point, coef: f64) -> LightSample {
+    let v = position - p;
+    let distance = v.length();
+    let coef = coef / distance.sqrt();
+    LightSample::Delta {
+        direction: v.direction(),
+        distance: distance,
+        illumination: Color::new(coef, coef, coef),
+    }
+}
+
+
+struct PointLight {
+    position: Point,
+}
+
 impl LightSourceImpl for PointLight {
-    fn intensity_at(&self, _d: UnitVector) -> f64 {
-        1.0
+    fn sample(&self, p: Point) -> LightSample {
+        delta_sample(self.position, p, 1.0)
     }
 }
 
+
 struct SpotLight {
+    position: Point,
     direction: UnitVector,
     outer_cos: f64,
     inner_cos: f64,
 }
 
 impl SpotLight {
-    fn cos(&self, d: UnitVector) -> f64 {
-        return self.direction.dot(d)
-    }
-}
-
-impl LightSourceImpl for SpotLight {
-    fn intensity_at(&self, d: UnitVector) -> f64 {
-        let cos = self.cos(d);
+    /// Fraction of the light intensity emitted in direction `d`.
+    fn emission(&self, d: UnitVector) -> f64 {
+        let cos = self.direction.dot(d);
         if cos < self.outer_cos {
             return 0.0;
         }
@@ -90,3 +242,248 @@ impl LightSourceImpl for SpotLight {
         t
     }
 }
+
+impl LightSourceImpl for SpotLight {
+    fn sample(&self, p: Point) -> LightSample {
+        let coef = self.emission(self.position.direction_to(p));
+        delta_sample(self.position, p, coef)
+    }
+}
+
+
+/// Area lights are Lambertian emitters which fade with the square of the
+/// distance. Their radiance is normalized so that from afar a spherical
+/// light is as bright as a point light of the same intensity at a unit
+/// distance. Rectangles and disks emit only to the side of their normal,
+/// so head-on they are four times brighter.
+fn area_sample(point: Point, normal: UnitVector, pdf: f64, area: f64) -> LightSample {
+    let radiance = 4.0 * PI / area;
+    LightSample::Area {
+        point: point,
+        normal: normal,
+        pdf: pdf,
+        radiance: Color::new(radiance, radiance, radiance),
+    }
+}
+
+
+struct RectangleLight {
+    corner: Point,
+    u: Vector,
+    v: Vector,
+    normal: UnitVector,
+    area: f64,
+}
+
+impl LightSourceImpl for RectangleLight {
+    fn sample(&self, _p: Point) -> LightSample {
+        let point = self.corner + self.u * rand::random::<f64>() + self.v * rand::random::<f64>();
+        area_sample(point, self.normal, 1.0 / self.area, self.area)
+    }
+}
+
+
+struct DiskLight {
+    center: Point,
+    normal: UnitVector,
+    basis: (UnitVector, UnitVector),
+    radius: f64,
+}
+
+impl DiskLight {
+    fn area(&self) -> f64 {
+        PI * self.radius * self.radius
+    }
+}
+
+impl LightSourceImpl for DiskLight {
+    fn sample(&self, _p: Point) -> LightSample {
+        let r = self.radius * rand::random::<f64>().sqrt();
+        let phi = 2.0 * PI * rand::random::<f64>();
+        let (u, v) = self.basis;
+        let point = self.center + u * (r * phi.cos()) + v * (r * phi.sin());
+        area_sample(point, self.normal, 1.0 / self.area(), self.area())
+    }
+}
+
+
+/// Only the hemisphere facing the shading point is sampled, the other half
+/// of the sphere can't illuminate it anyway.
+struct SphereLight {
+    center: Point,
+    radius: f64,
+}
+
+impl SphereLight {
+    fn area(&self) -> f64 {
+        4.0 * PI * self.radius * self.radius
+    }
+}
+
+impl LightSourceImpl for SphereLight {
+    fn sample(&self, p: Point) -> LightSample {
+        let axis = self.center.direction_to(p);
+        let (u, v) = axis.orthonormal_basis();
+        let z = rand::random::<f64>();
+        let r = (1.0 - z * z).sqrt();
+        let phi = 2.0 * PI * rand::random::<f64>();
+        let normal = (u * (r * phi.cos()) + v * (r * phi.sin()) + axis * z).direction();
+        let point = self.center + normal * self.radius;
+        area_sample(point, normal, 2.0 / self.area(), self.area())
+    }
+}
+
+
+/// Emissive primitives, whose radiance is the color of the light.
+struct SurfaceLight {
+    surfaces: Vec<Arc<Surface>>,
+    /// Running totals of the areas of the surfaces.
+    cdf: Vec<f64>,
+    area: f64,
+}
+
+impl LightSourceImpl for SurfaceLight {
+    fn sample(&self, _p: Point) -> LightSample {
+        let x = rand::random::<f64>() * self.area;
+        // the first surface whose running total exceeds `x`
+        let k = match self.cdf.binary_search_by(|total| total.partial_cmp(&x).unwrap()) {
+            Ok(k) => k + 1,
+            Err(k) => k,
+        };
+        let k = k.min(self.cdf.len() - 1);
+        let surface = &self.surfaces[k];
+        let s = surface.sample_surface(rand::random::<f64>(), rand::random::<f64>());
+        LightSample::Area {
+            point: s.point,
+            normal: s.normal,
+            pdf: s.pdf * surface.area() / self.area,
+            radiance: Color::new(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use color::Color;
+    use scene::config::{LightConfig, LightKind};
+    use geom::{Point, Vector};
+    use geom::shape::Quad;
+    use scene::material::Emission;
+
+    fn light(position: Point, kind: LightKind) -> LightSource {
+        LightSource::new(LightConfig {
+            color: Color::new(1.0, 1.0, 1.0),
+            intensity: 1.0,
+            position: position,
+            kind: kind,
+        }).unwrap()
+    }
+
+    #[test]
+    fn test_area_light_samples() {
+        let center = Point::new(0.0, 10.0, 0.0);
+        let target = Point::new(3.0, 0.0, 1.0);
+        let rectangle = light(center, LightKind::Rectangle {
+            u: Vector::new(2.0, 0.0, 0.0),
+            v: Vector::new(0.0, 0.0, 4.0),
+            n_samples: 4,
+        });
+        let disk = light(center, LightKind::Disk {
+            normal: Vector::new(0.0, -1.0, 0.0).direction(),
+            radius: 1.5,
+            n_samples: 4,
+        });
+        let sphere = light(center, LightKind::Sphere { radius: 2.0, n_samples: 4 });
+
+        let down = Vector::new(0.0, -1.0, 0.0);
+        for _ in 0..1000 {
+            // the points are uniform over the rectangle, whose normal faces
+            // down
+            match rectangle.sample(target) {
+                LightSample::Area { point, normal, pdf, radiance } => {
+                    let d = point - center;
+                    assert!(d.x.abs() <= 1.0 && d.y.abs() < 1e-9 && d.z.abs() <= 2.0);
+                    assert!((Vector::from(normal) - down).is_almost_zero());
+                    assert!((pdf - 1.0 / 8.0).abs() < 1e-9);
+                    assert!((radiance.rgb()[0] - 4.0 * PI / 8.0).abs() < 1e-9);
+                }
+                s => panic!("unexpected sample {:?}", s),
+            }
+
+            match disk.sample(target) {
+                LightSample::Area { point, pdf, .. } => {
+                    let d = point - center;
+                    assert!(d.length() <= 1.5 + 1e-9 && d.y.abs() < 1e-9);
+                    assert!((pdf - 1.0 / (PI * 1.5 * 1.5)).abs() < 1e-9);
+                }
+                s => panic!("unexpected sample {:?}", s),
+            }
+
+            match sphere.sample(target) {
+                LightSample::Area { point, normal, pdf, .. } => {
+                    let d = point - center;
+                    assert!((d.length() - 2.0).abs() < 1e-9);
+                    assert!((Vector::from(normal) - d / 2.0).is_almost_zero());
+                    assert!(d.dot(target - center) >= 0.0);
+                    assert!((pdf - 2.0 / (16.0 * PI)).abs() < 1e-9);
+                }
+                s => panic!("unexpected sample {:?}", s),
+            }
+        }
+    }
+
+    #[test]
+    fn test_directional_light() {
+        let light = light(Point::new(0.0, 10.0, 0.0), LightKind::Directional {
+            look_at: Point::new(10.0, 0.0, 0.0),
+        });
+        let expected = Vector::new(-1.0, 1.0, 0.0).direction();
+        for &p in &[Point::new(0.0, 0.0, 0.0), Point::new(-100.0, 3.0, 7.0)] {
+            match light.sample(p) {
+                LightSample::Delta { direction, distance, illumination } => {
+                    assert!((Vector::from(direction) - Vector::from(expected)).is_almost_zero());
+                    assert!(distance.is_infinite());
+                    assert_eq!(illumination.rgb(), [1.0, 1.0, 1.0]);
+                }
+                s => panic!("unexpected sample {:?}", s),
+            }
+        }
+
+        // a light without a direction is an error of the scene
+        let nowhere = LightSource::new(LightConfig {
+            color: Color::new(1.0, 1.0, 1.0),
+            intensity: 1.0,
+            position: Point::new(1.0, 2.0, 3.0),
+            kind: LightKind::Directional { look_at: Point::new(1.0, 2.0, 3.0) },
+        });
+        assert!(nowhere.is_err());
+    }
+
+    #[test]
+    fn test_emissive_surfaces() {
+        let emission = Emission { radiance: Color::new(2.0, 2.0, 2.0), n_samples: 1 };
+        let square = |x: f64| -> Arc<Surface> {
+            Arc::new(Quad::new(Point::new(x, 10.0, 0.0),
+                               Vector::new(0.1, 0.0, 0.0),
+                               Vector::new(0.0, 0.0, 0.1)))
+        };
+        let light = LightSource::emissive(&emission, vec![square(0.0), square(-0.1)]);
+
+        // the points are uniform over both squares
+        let target = Point::new(0.0, 0.0, 0.0);
+        for _ in 0..100 {
+            match light.sample(target) {
+                LightSample::Area { point, normal, pdf, radiance } => {
+                    assert!(-0.1 <= point[0] && point[0] <= 0.1);
+                    assert!((point[1] - 10.0).abs() < 1e-9);
+                    assert!(normal.dot(Vector::new(0.0, 1.0, 0.0)).abs() > 1.0 - 1e-9);
+                    assert!((pdf - 1.0 / 0.02).abs() < 1e-6);
+                    assert_eq!(radiance.rgb(), [2.0, 2.0, 2.0]);
+                }
+                s => panic!("unexpected sample {:?}", s),
+            }
+        }
+    }
+}
@@ -1,23 +1,56 @@
-use geom::shape;
+use std::error::Error;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use rand;
+
+use geom::{Dot, UnitVector, Vector};
 use color::Color;
-use super::config::{MaterialConfig, TextureConfig};
+use super::config::{MaterialConfig, LobeConfig, TextureConfig, TextureFiltering, TextureWrap,
+                    NoiseConfig};
+use super::primitive::Intersection;
+use super::texture::{ImageTexture, NoiseTexture, NoisePattern, Grayscale};
 
+/// Sum of the lobes of a BSDF, with the maps which perturb the normal.
 pub struct Material {
-    pub color: Box<Texture<Color>>,
-    pub diffuse: f64,
-    pub specular: f64,
-    pub reflectance: f64,
+    lobes: Vec<Box<Bsdf>>,
+    /// Materials made of the legacy fields reflect the ambient light by
+    /// their texture alone, as they always did.
+    legacy_ambient: Option<Arc<Texture<Color>>>,
+    pub bump: Option<BumpMap>,
+    /// Color texture interpreted as a normal in the tangent frame of the
+    /// surface, with channels mapped from `[0, 1]` to `[-1, 1]`.
+    pub normal_map: Option<Box<Texture<Color>>>,
+    pub emission: Option<Emission>,
+}
+
+
+/// Light emitted by a surface, the same in all the directions on the side
+/// of its normal.
+#[derive(Debug, Clone, Copy)]
+pub struct Emission {
+    pub radiance: Color,
+    /// Number of shadow rays towards the surfaces of the material.
+    pub n_samples: u32,
+}
+
+
+/// Height of the surface relative to the geometry, which changes only the
+/// shading normal.
+pub struct BumpMap {
+    height: Box<Texture<f64>>,
+    scale: f64,
 }
 
 
 pub trait Texture<T: Copy + Send + Sync>: Send + Sync {
-    fn at(&self, intersection: &shape::Intersection) -> T;
+    fn at(&self, intersection: &Intersection) -> T;
 }
 
 pub struct ConstTextute<T: Copy>(T);
 
 impl<T: Copy + Send + Sync> Texture<T> for ConstTextute<T> {
-    fn at(&self, _: &shape::Intersection) -> T {
+    fn at(&self, _: &Intersection) -> T {
         return self.0
     }
 }
@@ -28,30 +61,1018 @@ pub struct Checkboard3d<T: Copy> {
 }
 
 impl<T: Copy + Send + Sync> Texture<T> for Checkboard3d<T> {
-    fn at(&self, i: &shape::Intersection) -> T {
-        let p = i.point;
+    fn at(&self, i: &Intersection) -> T {
+        let p = i.geom.point;
         let is_odd = |f| if (f % 2.0 + 2.0) % 2.0 > 1.0 { 1 } else { 0 };
         [self.black, self.white][(is_odd(p[0]) ^ is_odd(p[1]) ^ is_odd(p[2])) as usize]
     }
 }
 
+struct ScaledTexture(Arc<Texture<Color>>, f64);
+
+impl Texture<Color> for ScaledTexture {
+    fn at(&self, i: &Intersection) -> Color {
+        self.0.at(i) * self.1
+    }
+}
+
+
+/// Scattering of light at a surface. Directions point away from the
+/// surface: `wo` towards the viewer and `wi` towards the light. The shading
+/// normal of the intersection tells the sides of the surface apart.
+pub trait Bsdf: Send + Sync {
+    /// Fraction of the light arriving from `wi` which is scattered towards
+    /// `wo`, per unit of solid angle. Perfectly specular lobes give black.
+    fn eval(&self, i: &Intersection, wo: UnitVector, wi: UnitVector) -> Color;
+
+    /// Random direction of the light scattered towards `wo`, if any.
+    fn sample(&self, i: &Intersection, wo: UnitVector) -> Option<BsdfSample>;
+
+    /// Probability density of `sample` choosing `wi` with respect to the
+    /// solid angle. Zero for perfectly specular lobes.
+    fn pdf(&self, i: &Intersection, wo: UnitVector, wi: UnitVector) -> f64;
+
+    /// Rough fraction of the light which is scattered, it decides how often
+    /// a material samples the lobe.
+    fn albedo(&self, i: &Intersection) -> Color;
+
+    /// Whether light is scattered only in a few discrete directions.
+    fn is_delta(&self) -> bool {
+        false
+    }
+
+    /// The discrete directions of a perfectly specular lobe, with the
+    /// fractions of light scattered along them.
+    fn delta_directions(&self, _i: &Intersection, _wo: UnitVector) -> Vec<(UnitVector, Color)> {
+        Vec::new()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BsdfSample {
+    /// Direction towards the light, `wi`.
+    pub direction: UnitVector,
+    /// The BSDF times the cosine of the direction divided by the `pdf`,
+    /// which is the factor of the throughput of a path.
+    pub weight: Color,
+    /// Probability of the direction for perfectly specular samples.
+    pub pdf: f64,
+    pub is_delta: bool,
+}
+
 
-impl From<MaterialConfig> for Material {
-    fn from(config: MaterialConfig) -> Material {
-        let color: Box<Texture<Color>> = match config.texture {
-            TextureConfig::Checkboard3d(black, white) => Box::new(
-                Checkboard3d {
-                    black: black,
-                    white: white
-                }),
-            TextureConfig::Color(c) => Box::new(ConstTextute(c))
+impl Material {
+    pub fn new(config: MaterialConfig) -> Result<Material, Box<Error>> {
+        let bump = match config.bump {
+            None => None,
+            Some(bump) => Some(BumpMap {
+                height: Box::new(Grayscale(read_texture(bump.texture)?)),
+                scale: bump.height,
+            }),
         };
+        let normal_map = match config.normal_map {
+            None => None,
+            Some(texture) => Some(read_texture(texture)?),
+        };
+        let (lobes, legacy_ambient) = match config.lobes {
+            Some(lobes) => {
+                let has_legacy_fields = config.specular.is_some()
+                    || config.diffuse.is_some()
+                    || config.texture.is_some()
+                    || config.reflectance.is_some()
+                    || config.transmittance.is_some()
+                    || config.refractive_index.is_some()
+                    || config.specular_color.is_some();
+                if has_legacy_fields {
+                    return Err(From::from("material with lobes can't have the legacy fields"));
+                }
+                (lobes.into_iter().map(read_lobe).collect::<Result<Vec<_>, _>>()?, None)
+            }
+            None => {
+                let (lobes, ambient) = legacy_lobes(
+                    config.specular,
+                    config.diffuse.unwrap_or(0.0),
+                    config.texture,
+                    config.specular_color.unwrap_or(Color::new(1.0, 1.0, 1.0)),
+                    config.reflectance.unwrap_or(0.0),
+                    config.transmittance.unwrap_or(0.0),
+                    config.refractive_index.unwrap_or(1.0))?;
+                (lobes, Some(ambient))
+            }
+        };
+
+        let emission = match config.emission {
+            None => None,
+            Some(emission) => {
+                if emission.intensity < 0.0 {
+                    return Err(From::from("emission intensity should not be negative"));
+                }
+                let n_samples = emission.n_samples.unwrap_or(1);
+                if n_samples == 0 {
+                    return Err(From::from("emission should have at least one sample"));
+                }
+                Some(Emission {
+                    radiance: emission.color * emission.intensity,
+                    n_samples: n_samples,
+                })
+            }
+        };
+
+        Ok(Material {
+            lobes: lobes,
+            legacy_ambient: legacy_ambient,
+            bump: bump,
+            normal_map: normal_map,
+            emission: emission,
+        })
+    }
+
+    /// Radiance emitted towards `wo`, only emissive surfaces seen from the
+    /// side of the normal give any.
+    pub fn emitted(&self, i: &Intersection, wo: UnitVector) -> Color {
+        match self.emission {
+            Some(ref emission) if wo.dot(i.geom.normal) > 0.0 => emission.radiance,
+            _ => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    /// Light reflected from uniform ambient `illumination`, ignoring the
+    /// perfectly specular lobes.
+    pub fn ambient(&self, i: &Intersection, illumination: Color) -> Color {
+        if let Some(ref color) = self.legacy_ambient {
+            return color.at(i) * illumination;
+        }
+        self.lobes.iter()
+            .filter(|lobe| !lobe.is_delta())
+            .fold(Color::new(0.0, 0.0, 0.0), |sum, lobe| sum + lobe.albedo(i) * illumination)
+    }
+
+    /// Normal used for shading, which accounts for the bump and normal maps.
+    pub fn shading_normal(&self, i: &Intersection) -> UnitVector {
+        let (tangent, bitangent) = (i.geom.tangent, i.geom.bitangent);
+        let mut normal = i.geom.normal;
+        if let Some(ref map) = self.normal_map {
+            let c = map.at(i).rgb();
+            let n = tangent * (2.0 * c[0] - 1.0)
+                + bitangent * (2.0 * c[1] - 1.0)
+                + normal * (2.0 * c[2] - 1.0);
+            if !n.is_almost_zero() {
+                normal = n.direction();
+            }
+        }
+        if let Some(ref bump) = self.bump {
+            // finite differences of the height along the tangent frame
+            let delta = (0.5 * i.footprint).max(1e-4);
+            let shifted = |du: f64, dv: f64| {
+                let mut j = *i;
+                j.geom.point = i.geom.point + tangent * du + bitangent * dv;
+                let uv = i.geom.local_coordinates;
+                let density = i.geom.uv_density;
+                j.geom.local_coordinates = [uv[0] + du * density, uv[1] + dv * density];
+                bump.height.at(&j)
+            };
+            let h = bump.height.at(i);
+            let dhdu = bump.scale * (shifted(delta, 0.0) - h) / delta;
+            let dhdv = bump.scale * (shifted(0.0, delta) - h) / delta;
+            let n = Vector::from(normal) - tangent * dhdu - bitangent * dhdv;
+            if !n.is_almost_zero() {
+                normal = n.direction();
+            }
+        }
+        normal
+    }
+
+    /// Probabilities of sampling the lobes.
+    fn lobe_probabilities(&self, i: &Intersection) -> Vec<f64> {
+        let weights = self.lobes.iter()
+            .map(|lobe| lobe.albedo(i).grayscale().max(0.0))
+            .collect::<Vec<_>>();
+        let total: f64 = weights.iter().sum();
+        if total <= 0.0 {
+            return vec![0.0; weights.len()];
+        }
+        weights.into_iter().map(|w| w / total).collect()
+    }
+
+    fn pdf_with(&self, probabilities: &[f64], i: &Intersection, wo: UnitVector, wi: UnitVector)
+        -> f64 {
+        self.lobes.iter()
+            .zip(probabilities)
+            .filter(|&(lobe, &p)| !lobe.is_delta() && p > 0.0)
+            .map(|(lobe, &p)| p * lobe.pdf(i, wo, wi))
+            .sum()
+    }
+}
+
+/// A lobe is picked proportionally to its albedo. Directions of the other
+/// lobes are weighted by the combined density, unless the lobe is perfectly
+/// specular.
+impl Bsdf for Material {
+    fn eval(&self, i: &Intersection, wo: UnitVector, wi: UnitVector) -> Color {
+        self.lobes.iter()
+            .filter(|lobe| !lobe.is_delta())
+            .fold(Color::new(0.0, 0.0, 0.0), |sum, lobe| sum + lobe.eval(i, wo, wi))
+    }
+
+    fn sample(&self, i: &Intersection, wo: UnitVector) -> Option<BsdfSample> {
+        let probabilities = self.lobe_probabilities(i);
+        let mut choice = rand::random::<f64>();
+        let mut picked = None;
+        for (k, &p) in probabilities.iter().enumerate() {
+            if p > 0.0 {
+                picked = Some(k);
+                if choice < p {
+                    break;
+                }
+                choice -= p;
+            }
+        }
+        let k = picked?;
+        let sample = self.lobes[k].sample(i, wo)?;
+        if sample.is_delta {
+            return Some(BsdfSample {
+                weight: sample.weight / probabilities[k],
+                pdf: sample.pdf * probabilities[k],
+                ..sample
+            });
+        }
+
+        let wi = sample.direction;
+        let pdf = self.pdf_with(&probabilities, i, wo, wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        let cos = wi.dot(i.shading_normal).abs();
+        Some(BsdfSample {
+            direction: wi,
+            weight: self.eval(i, wo, wi) * (cos / pdf),
+            pdf: pdf,
+            is_delta: false,
+        })
+    }
+
+    fn pdf(&self, i: &Intersection, wo: UnitVector, wi: UnitVector) -> f64 {
+        self.pdf_with(&self.lobe_probabilities(i), i, wo, wi)
+    }
+
+    fn albedo(&self, i: &Intersection) -> Color {
+        self.lobes.iter().fold(Color::new(0.0, 0.0, 0.0), |sum, lobe| sum + lobe.albedo(i))
+    }
+
+    fn is_delta(&self) -> bool {
+        self.lobes.iter().all(|lobe| lobe.is_delta())
+    }
+
+    fn delta_directions(&self, i: &Intersection, wo: UnitVector) -> Vec<(UnitVector, Color)> {
+        self.lobes.iter().flat_map(|lobe| lobe.delta_directions(i, wo)).collect()
+    }
+}
+
 
-        Material {
+/// The shading normal turned to the side of the surface where `w` is.
+fn facing(i: &Intersection, w: UnitVector) -> UnitVector {
+    let n = i.shading_normal;
+    if w.dot(i.geom.normal) < 0.0 { -n } else { n }
+}
+
+/// Whether `wo` and `wi` are on the same side of the surface.
+fn is_reflection(i: &Intersection, wo: UnitVector, wi: UnitVector) -> bool {
+    let n = i.geom.normal;
+    wo.dot(n) * wi.dot(n) > 0.0
+}
+
+/// Random direction in the hemisphere around `normal`, distributed
+/// proportionally to the cosine of the angle with the normal.
+fn sample_cosine_hemisphere(normal: UnitVector) -> UnitVector {
+    let (u, v) = normal.orthonormal_basis();
+    let phi = 2.0 * PI * rand::random::<f64>();
+    let r2 = rand::random::<f64>();
+    let r = r2.sqrt();
+    (u * (r * phi.cos()) + v * (r * phi.sin()) + normal * (1.0 - r2).sqrt()).direction()
+}
+
+/// Direction around `axis` with the given cosine of the angle to it and a
+/// random azimuth.
+pub fn around(axis: UnitVector, cos: f64) -> UnitVector {
+    let (u, v) = axis.orthonormal_basis();
+    let sin = (1.0 - cos * cos).max(0.0).sqrt();
+    let phi = 2.0 * PI * rand::random::<f64>();
+    (u * (sin * phi.cos()) + v * (sin * phi.sin()) + axis * cos).direction()
+}
+
+/// Fraction of unpolarized light reflected by the boundary of two
+/// dielectrics, `eta` is the ratio of their refractive indices.
+fn fresnel_dielectric(cos_i: f64, cos_t: f64, eta: f64) -> f64 {
+    let r_s = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_p = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    ((r_s * r_s + r_p * r_p) / 2.0).min(1.0)
+}
+
+/// Schlick's approximation of the reflectance of a conductor which
+/// reflects `f0` at normal incidence.
+fn fresnel_schlick(f0: Color, cos: f64) -> Color {
+    let k = (1.0 - cos).max(0.0).min(1.0).powi(5);
+    let c = f0.rgb();
+    Color::new(c[0] + (1.0 - c[0]) * k, c[1] + (1.0 - c[1]) * k, c[2] + (1.0 - c[2]) * k)
+}
+
+
+struct Lambertian {
+    color: Box<Texture<Color>>,
+}
+
+impl Bsdf for Lambertian {
+    fn eval(&self, i: &Intersection, wo: UnitVector, wi: UnitVector) -> Color {
+        if !is_reflection(i, wo, wi) {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        self.color.at(i) / PI
+    }
+
+    fn sample(&self, i: &Intersection, wo: UnitVector) -> Option<BsdfSample> {
+        let wi = sample_cosine_hemisphere(facing(i, wo));
+        // the cosine and the density cancel out
+        Some(BsdfSample {
+            direction: wi,
+            weight: self.color.at(i),
+            pdf: self.pdf(i, wo, wi),
+            is_delta: false,
+        })
+    }
+
+    fn pdf(&self, i: &Intersection, wo: UnitVector, wi: UnitVector) -> f64 {
+        if is_reflection(i, wo, wi) { wi.dot(i.shading_normal).abs() / PI } else { 0.0 }
+    }
+
+    fn albedo(&self, i: &Intersection) -> Color {
+        self.color.at(i)
+    }
+}
+
+
+/// Qualitative Oren-Nayar model of a surface made of Lambertian facets.
+struct OrenNayar {
+    color: Box<Texture<Color>>,
+    a: f64,
+    b: f64,
+}
+
+impl OrenNayar {
+    fn new(color: Box<Texture<Color>>, sigma: f64) -> OrenNayar {
+        let s2 = sigma * sigma;
+        OrenNayar {
             color: color,
-            diffuse: config.diffuse,
-            specular: config.specular,
-            reflectance: config.reflectance,
+            a: 1.0 - s2 / (2.0 * (s2 + 0.33)),
+            b: 0.45 * s2 / (s2 + 0.09),
+        }
+    }
+}
+
+impl Bsdf for OrenNayar {
+    fn eval(&self, i: &Intersection, wo: UnitVector, wi: UnitVector) -> Color {
+        if !is_reflection(i, wo, wi) {
+            return Color::new(0.0, 0.0, 0.0);
         }
+        let n = facing(i, wo);
+        let (cos_o, cos_i) = (wo.dot(n), wi.dot(n));
+        let (sin_o, sin_i) = ((1.0 - cos_o * cos_o).max(0.0).sqrt(),
+                              (1.0 - cos_i * cos_i).max(0.0).sqrt());
+        // cosine of the difference of the azimuths
+        let cos_phi = if sin_o > 1e-6 && sin_i > 1e-6 {
+            let to = Vector::from(wo) - n * cos_o;
+            let ti = Vector::from(wi) - n * cos_i;
+            (to.dot(ti) / (sin_o * sin_i)).max(0.0)
+        } else {
+            0.0
+        };
+        let sin_alpha_tan_beta = if cos_i > cos_o {
+            sin_o * sin_i / cos_i
+        } else {
+            sin_i * sin_o / cos_o
+        };
+        self.color.at(i) * ((self.a + self.b * cos_phi * sin_alpha_tan_beta) / PI)
+    }
+
+    fn sample(&self, i: &Intersection, wo: UnitVector) -> Option<BsdfSample> {
+        let wi = sample_cosine_hemisphere(facing(i, wo));
+        Some(BsdfSample {
+            direction: wi,
+            weight: self.eval(i, wo, wi) * PI,
+            pdf: self.pdf(i, wo, wi),
+            is_delta: false,
+        })
+    }
+
+    fn pdf(&self, i: &Intersection, wo: UnitVector, wi: UnitVector) -> f64 {
+        if is_reflection(i, wo, wi) { wi.dot(i.shading_normal).abs() / PI } else { 0.0 }
+    }
+
+    fn albedo(&self, i: &Intersection) -> Color {
+        self.color.at(i)
+    }
+}
+
+
+/// Blinn-Phong highlights normalized to keep the energy.
+struct BlinnPhong {
+    color: Box<Texture<Color>>,
+    exponent: f64,
+}
+
+impl BlinnPhong {
+    /// Density of the half vector with the cosine `cos_h` to the normal.
+    fn half_vector_pdf(&self, cos_h: f64) -> f64 {
+        (self.exponent + 1.0) / (2.0 * PI) * cos_h.max(0.0).powf(self.exponent)
+    }
+}
+
+impl Bsdf for BlinnPhong {
+    fn eval(&self, i: &Intersection, wo: UnitVector, wi: UnitVector) -> Color {
+        if !is_reflection(i, wo, wi) {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        let h = (Vector::from(wo) + Vector::from(wi)).direction();
+        let cos_h = h.dot(facing(i, wo));
+        let k = (self.exponent + 8.0) / (8.0 * PI) * cos_h.max(0.0).powf(self.exponent);
+        self.color.at(i) * k
+    }
+
+    fn sample(&self, i: &Intersection, wo: UnitVector) -> Option<BsdfSample> {
+        let n = facing(i, wo);
+        let cos_h = rand::random::<f64>().powf(1.0 / (self.exponent + 1.0));
+        let h = around(n, cos_h);
+        let wi = (-wo).reflect(h);
+        let pdf = self.pdf(i, wo, wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(BsdfSample {
+            direction: wi,
+            weight: self.eval(i, wo, wi) * (wi.dot(n) / pdf),
+            pdf: pdf,
+            is_delta: false,
+        })
+    }
+
+    fn pdf(&self, i: &Intersection, wo: UnitVector, wi: UnitVector) -> f64 {
+        if !is_reflection(i, wo, wi) {
+            return 0.0;
+        }
+        let h = (Vector::from(wo) + Vector::from(wi)).direction();
+        self.half_vector_pdf(h.dot(facing(i, wo))) / (4.0 * wo.dot(h))
+    }
+
+    fn albedo(&self, i: &Intersection) -> Color {
+        self.color.at(i)
+    }
+}
+
+
+/// Trowbridge-Reitz (GGX) distribution of the normals of microfacets with
+/// Smith's shadowing.
+#[derive(Clone, Copy)]
+struct Ggx {
+    alpha: f64,
+}
+
+impl Ggx {
+    fn d(&self, cos_h: f64) -> f64 {
+        if cos_h <= 0.0 {
+            return 0.0;
+        }
+        let a2 = self.alpha * self.alpha;
+        let t = cos_h * cos_h * (a2 - 1.0) + 1.0;
+        a2 / (PI * t * t)
+    }
+
+    fn g1(&self, cos: f64) -> f64 {
+        let a2 = self.alpha * self.alpha;
+        let c = cos.abs();
+        2.0 * c / (c + (a2 + (1.0 - a2) * c * c).sqrt())
+    }
+
+    fn g(&self, cos_o: f64, cos_i: f64) -> f64 {
+        self.g1(cos_o) * self.g1(cos_i)
+    }
+
+    /// Microfacet normal around `n` with the density `d(cos_h) * cos_h`.
+    fn sample(&self, n: UnitVector) -> UnitVector {
+        let u = rand::random::<f64>();
+        let tan2 = self.alpha * self.alpha * u / (1.0 - u);
+        around(n, 1.0 / (1.0 + tan2).sqrt())
+    }
+
+    fn pdf(&self, cos_h: f64) -> f64 {
+        self.d(cos_h) * cos_h
+    }
+}
+
+fn ggx_alpha(roughness: f64) -> Option<Ggx> {
+    if roughness > 0.0 {
+        Some(Ggx { alpha: (roughness * roughness).max(1e-4) })
+    } else {
+        None
+    }
+}
+
+
+/// Cook-Torrance model of a metal, a perfect mirror without roughness.
+struct Conductor {
+    color: Box<Texture<Color>>,
+    distribution: Option<Ggx>,
+}
+
+impl Bsdf for Conductor {
+    fn eval(&self, i: &Intersection, wo: UnitVector, wi: UnitVector) -> Color {
+        let ggx = match self.distribution {
+            Some(ggx) if is_reflection(i, wo, wi) => ggx,
+            _ => return Color::new(0.0, 0.0, 0.0),
+        };
+        let n = facing(i, wo);
+        let (cos_o, cos_i) = (wo.dot(n), wi.dot(n));
+        let h = (Vector::from(wo) + Vector::from(wi)).direction();
+        let f = fresnel_schlick(self.color.at(i), wi.dot(h));
+        f * (ggx.d(h.dot(n)) * ggx.g(cos_o, cos_i) / (4.0 * cos_o * cos_i))
+    }
+
+    fn sample(&self, i: &Intersection, wo: UnitVector) -> Option<BsdfSample> {
+        let n = facing(i, wo);
+        let ggx = match self.distribution {
+            Some(ggx) => ggx,
+            None => {
+                let wi = (-wo).reflect(n);
+                return Some(BsdfSample {
+                    direction: wi,
+                    weight: fresnel_schlick(self.color.at(i), wo.dot(n)),
+                    pdf: 1.0,
+                    is_delta: true,
+                });
+            }
+        };
+        let h = ggx.sample(n);
+        let wi = (-wo).reflect(h);
+        let pdf = self.pdf(i, wo, wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(BsdfSample {
+            direction: wi,
+            weight: self.eval(i, wo, wi) * (wi.dot(n) / pdf),
+            pdf: pdf,
+            is_delta: false,
+        })
+    }
+
+    fn pdf(&self, i: &Intersection, wo: UnitVector, wi: UnitVector) -> f64 {
+        let ggx = match self.distribution {
+            Some(ggx) if is_reflection(i, wo, wi) => ggx,
+            _ => return 0.0,
+        };
+        let h = (Vector::from(wo) + Vector::from(wi)).direction();
+        ggx.pdf(h.dot(facing(i, wo))) / (4.0 * wo.dot(h))
+    }
+
+    fn albedo(&self, i: &Intersection) -> Color {
+        self.color.at(i)
+    }
+
+    fn is_delta(&self) -> bool {
+        self.distribution.is_none()
+    }
+
+    fn delta_directions(&self, i: &Intersection, wo: UnitVector) -> Vec<(UnitVector, Color)> {
+        if self.distribution.is_some() {
+            return Vec::new();
+        }
+        let n = facing(i, wo);
+        vec![((-wo).reflect(n), fresnel_schlick(self.color.at(i), wo.dot(n)))]
+    }
+}
+
+
+/// Boundary of a transparent medium, whose side the normal points out of.
+/// Rough boundaries follow Walter et al., "Microfacet Models for Refraction
+/// through Rough Surfaces". The radiance isn't scaled by the square of the
+/// ratio of the refractive indices, as in the smooth case.
+struct Dielectric {
+    color: Color,
+    refractive_index: f64,
+    distribution: Option<Ggx>,
+}
+
+impl Dielectric {
+    /// The refractive indices on the side of `wo` and on the other side.
+    fn indices(&self, i: &Intersection, wo: UnitVector) -> (f64, f64) {
+        if wo.dot(i.geom.normal) >= 0.0 {
+            (1.0, self.refractive_index)
+        } else {
+            (self.refractive_index, 1.0)
+        }
+    }
+
+    /// Reflectance of the facet with the normal `h` facing `wo`, and the
+    /// refracted direction unless the reflection is total.
+    fn fresnel(&self, i: &Intersection, wo: UnitVector, h: UnitVector)
+        -> (f64, Option<UnitVector>) {
+        let (eta_o, eta_t) = self.indices(i, wo);
+        let eta = eta_o / eta_t;
+        match (-wo).refract(h, eta) {
+            None => (1.0, None),
+            Some(refracted) => {
+                let f = fresnel_dielectric(wo.dot(h), -refracted.dot(h), eta);
+                (f, Some(refracted))
+            }
+        }
+    }
+
+    /// Normal of the microfacet which scatters `wo` into `wi`, facing `wo`.
+    fn half_vector(&self, i: &Intersection, wo: UnitVector, wi: UnitVector) -> UnitVector {
+        let h = if is_reflection(i, wo, wi) {
+            Vector::from(wo) + Vector::from(wi)
+        } else {
+            let (eta_o, eta_t) = self.indices(i, wo);
+            -(Vector::from(wo) * eta_o + Vector::from(wi) * eta_t)
+        };
+        let h = h.direction();
+        if h.dot(facing(i, wo)) < 0.0 { -h } else { h }
+    }
+}
+
+impl Bsdf for Dielectric {
+    fn eval(&self, i: &Intersection, wo: UnitVector, wi: UnitVector) -> Color {
+        let ggx = match self.distribution {
+            Some(ggx) => ggx,
+            None => return Color::new(0.0, 0.0, 0.0),
+        };
+        let n = i.shading_normal;
+        let (cos_o, cos_i) = (wo.dot(n), wi.dot(n));
+        let h = self.half_vector(i, wo, wi);
+        let (oh, ih) = (wo.dot(h), wi.dot(h));
+        let cos_h = h.dot(facing(i, wo));
+        let (f, _) = self.fresnel(i, wo, h);
+        let dg = ggx.d(cos_h) * ggx.g(cos_o, cos_i);
+        if is_reflection(i, wo, wi) {
+            return self.color * (f * dg / (4.0 * (cos_o * cos_i).abs()));
+        }
+        if oh <= 0.0 || ih >= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        let (eta_o, eta_t) = self.indices(i, wo);
+        let denominator = eta_o * oh + eta_t * ih;
+        let k = (ih * oh / (cos_i * cos_o)).abs() * eta_t * eta_t * (1.0 - f) * dg
+            / (denominator * denominator);
+        self.color * k
+    }
+
+    fn sample(&self, i: &Intersection, wo: UnitVector) -> Option<BsdfSample> {
+        let n = facing(i, wo);
+        let h = match self.distribution {
+            Some(ggx) => ggx.sample(n),
+            None => n,
+        };
+        if wo.dot(h) <= 0.0 {
+            return None;
+        }
+        let (f, refracted) = self.fresnel(i, wo, h);
+        let (wi, is_reflected, probability) = match refracted {
+            Some(refracted) if rand::random::<f64>() >= f => (refracted, false, 1.0 - f),
+            _ => ((-wo).reflect(h), true, f),
+        };
+        let ggx = match self.distribution {
+            Some(ggx) => ggx,
+            None => return Some(BsdfSample {
+                direction: wi,
+                weight: self.color,
+                pdf: probability,
+                is_delta: true,
+            }),
+        };
+        // the facet can scatter the light to the wrong side of the surface
+        if wi.dot(n) == 0.0 || (wi.dot(n) > 0.0) != is_reflected {
+            return None;
+        }
+        // the Fresnel term and the probability of the choice cancel out
+        let cos_o = wo.dot(n);
+        let weight = wo.dot(h) * ggx.g(cos_o, wi.dot(n)) / (cos_o * h.dot(n));
+        Some(BsdfSample {
+            direction: wi,
+            weight: self.color * weight,
+            pdf: self.pdf(i, wo, wi),
+            is_delta: false,
+        })
+    }
+
+    fn pdf(&self, i: &Intersection, wo: UnitVector, wi: UnitVector) -> f64 {
+        let ggx = match self.distribution {
+            Some(ggx) => ggx,
+            None => return 0.0,
+        };
+        let h = self.half_vector(i, wo, wi);
+        let (oh, ih) = (wo.dot(h), wi.dot(h));
+        let (f, _) = self.fresnel(i, wo, h);
+        let pdf_h = ggx.pdf(h.dot(facing(i, wo)));
+        if is_reflection(i, wo, wi) {
+            return f * pdf_h / (4.0 * oh.abs());
+        }
+        if oh <= 0.0 || ih >= 0.0 {
+            return 0.0;
+        }
+        let (eta_o, eta_t) = self.indices(i, wo);
+        let denominator = eta_o * oh + eta_t * ih;
+        (1.0 - f) * pdf_h * eta_t * eta_t * ih.abs() / (denominator * denominator)
+    }
+
+    fn albedo(&self, _i: &Intersection) -> Color {
+        self.color
+    }
+
+    fn is_delta(&self) -> bool {
+        self.distribution.is_none()
+    }
+
+    fn delta_directions(&self, i: &Intersection, wo: UnitVector) -> Vec<(UnitVector, Color)> {
+        if self.distribution.is_some() {
+            return Vec::new();
+        }
+        let n = facing(i, wo);
+        let (f, refracted) = self.fresnel(i, wo, n);
+        let mut result = vec![((-wo).reflect(n), self.color * f)];
+        if let Some(refracted) = refracted {
+            result.push((refracted, self.color * (1.0 - f)));
+        }
+        result
+    }
+}
+
+
+/// Perfect mirror which reflects the same fraction of light at all angles.
+struct Mirror {
+    color: Color,
+}
+
+impl Bsdf for Mirror {
+    fn eval(&self, _i: &Intersection, _wo: UnitVector, _wi: UnitVector) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    fn sample(&self, i: &Intersection, wo: UnitVector) -> Option<BsdfSample> {
+        Some(BsdfSample {
+            direction: (-wo).reflect(facing(i, wo)),
+            weight: self.color,
+            pdf: 1.0,
+            is_delta: true,
+        })
+    }
+
+    fn pdf(&self, _i: &Intersection, _wo: UnitVector, _wi: UnitVector) -> f64 {
+        0.0
+    }
+
+    fn albedo(&self, _i: &Intersection) -> Color {
+        self.color
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+
+    fn delta_directions(&self, i: &Intersection, wo: UnitVector) -> Vec<(UnitVector, Color)> {
+        vec![((-wo).reflect(facing(i, wo)), self.color)]
+    }
+}
+
+
+fn read_lobe(config: LobeConfig) -> Result<Box<Bsdf>, Box<Error>> {
+    let check_roughness = |roughness: f64| if 0.0 <= roughness && roughness <= 1.0 {
+        Ok(())
+    } else {
+        Err(Box::<Error>::from("roughness should be between 0 and 1"))
+    };
+    let lobe: Box<Bsdf> = match config {
+        LobeConfig::Lambertian(color) => Box::new(Lambertian { color: read_texture(color)? }),
+        LobeConfig::OrenNayar { color, sigma } => {
+            if sigma < 0.0 || sigma > 90.0 {
+                return Err(From::from("sigma should be between 0 and 90 degrees"));
+            }
+            Box::new(OrenNayar::new(read_texture(color)?, sigma.to_radians()))
+        }
+        LobeConfig::BlinnPhong { color, exponent } => {
+            if exponent < 0.0 {
+                return Err(From::from("exponent should not be negative"));
+            }
+            Box::new(BlinnPhong { color: read_texture(color)?, exponent: exponent })
+        }
+        LobeConfig::Conductor { color, roughness } => {
+            check_roughness(roughness)?;
+            Box::new(Conductor {
+                color: read_texture(color)?,
+                distribution: ggx_alpha(roughness),
+            })
+        }
+        LobeConfig::Dielectric { color, refractive_index, roughness } => {
+            check_roughness(roughness)?;
+            if refractive_index <= 0.0 {
+                return Err(From::from("refractive_index should be positive"));
+            }
+            Box::new(Dielectric {
+                color: color,
+                refractive_index: refractive_index,
+                distribution: ggx_alpha(roughness),
+            })
+        }
+        LobeConfig::Mirror(color) => Box::new(Mirror { color: color }),
+    };
+    Ok(lobe)
+}
+
+/// Lobes equivalent to the legacy fields of materials, and the texture which
+/// reflects the ambient light. The highlights had the peak `specular_color`
+/// and the Phong `specular` exponent, which is about a quarter of the
+/// Blinn-Phong one. Mirror reflection and refraction didn't depend on the
+/// colors.
+fn legacy_lobes(specular: Option<f64>,
+                diffuse: f64,
+                texture: Option<TextureConfig>,
+                specular_color: Color,
+                reflectance: f64,
+                transmittance: f64,
+                refractive_index: f64)
+    -> Result<(Vec<Box<Bsdf>>, Arc<Texture<Color>>), Box<Error>> {
+    if transmittance < 0.0 || transmittance > 1.0 {
+        return Err(From::from("transmittance should be between 0 and 1"));
+    }
+    if refractive_index <= 0.0 {
+        return Err(From::from("refractive_index should be positive"));
+    }
+    let texture: Arc<Texture<Color>> = match texture {
+        Some(texture) => Arc::from(read_texture(texture)?),
+        None => Arc::new(ConstTextute(Color::new(1.0, 1.0, 1.0))),
+    };
+    let mut lobes: Vec<Box<Bsdf>> = Vec::new();
+    if diffuse > 0.0 {
+        let color = Box::new(ScaledTexture(texture.clone(), diffuse));
+        lobes.push(Box::new(Lambertian { color: color }));
+    }
+    if let Some(exponent) = specular {
+        if exponent < 0.0 {
+            return Err(From::from("specular should not be negative"));
+        }
+        lobes.push(Box::new(BlinnPhong {
+            color: Box::new(ConstTextute(specular_color * (2.0 / (exponent + 2.0)))),
+            exponent: 4.0 * exponent,
+        }));
+    }
+    if reflectance > 0.0 {
+        let gray = Color::new(reflectance, reflectance, reflectance);
+        lobes.push(Box::new(Mirror { color: gray }));
+    }
+    if transmittance > 0.0 {
+        lobes.push(Box::new(Dielectric {
+            color: Color::new(transmittance, transmittance, transmittance),
+            refractive_index: refractive_index,
+            distribution: None,
+        }));
+    }
+    Ok((lobes, texture))
+}
+
+
+fn read_texture(config: TextureConfig) -> Result<Box<Texture<Color>>, Box<Error>> {
+    let texture: Box<Texture<Color>> = match config {
+        TextureConfig::Checkboard3d(black, white) => Box::new(
+            Checkboard3d {
+                black: black,
+                white: white
+            }),
+        TextureConfig::Color(c) => Box::new(ConstTextute(c)),
+        TextureConfig::Image { location, filtering, wrap, scale } => Box::new(
+            ImageTexture::open(&location,
+                               filtering.unwrap_or(TextureFiltering::Bilinear),
+                               wrap.unwrap_or(TextureWrap::Repeat),
+                               scale.unwrap_or([1.0, 1.0]))?),
+        TextureConfig::Perlin(c) => noise_texture(NoisePattern::Perlin, c),
+        TextureConfig::Fbm(c) => noise_texture(NoisePattern::Fbm, c),
+        TextureConfig::Turbulence(c) => noise_texture(NoisePattern::Turbulence, c),
+        TextureConfig::Marble(c) => noise_texture(NoisePattern::Marble, c),
+        TextureConfig::Wood(c) => noise_texture(NoisePattern::Wood, c),
+        TextureConfig::Worley(c) => noise_texture(NoisePattern::Worley, c),
+    };
+    Ok(texture)
+}
+
+fn noise_texture(pattern: NoisePattern, config: NoiseConfig) -> Box<Texture<Color>> {
+    Box::new(NoiseTexture::new(pattern, config.low, config.high, config.scale,
+                               config.octaves.unwrap_or(6), config.seed.unwrap_or(0)))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geom::{Point, Vector};
+    use geom::shape;
+    use scene::config::LobeConfig;
+
+    fn material(lobes: Vec<LobeConfig>) -> Material {
+        Material::new(MaterialConfig {
+            specular: None,
+            diffuse: None,
+            texture: None,
+            reflectance: None,
+            transmittance: None,
+            refractive_index: None,
+            bump: None,
+            normal_map: None,
+            specular_color: None,
+            lobes: Some(lobes),
+            emission: None,
+        }).unwrap()
+    }
+
+    #[test]
+    fn test_fresnel_normal_incidence() {
+        let f = fresnel_dielectric(1.0, 1.0, 1.0 / 1.5);
+        assert!((f - 0.04).abs() < 1e-9);
+        assert!(fresnel_dielectric(1.0, 1.0, 1.0) < 1e-9);
+        assert!(fresnel_dielectric(0.0, 0.5, 1.0 / 1.5) > 0.999);
+    }
+
+    /// The weights of the samples should agree with the BSDF and its
+    /// density, and average to at most the albedo.
+    #[test]
+    fn test_samples_match_eval() {
+        let white = || TextureConfig::Color(Color::new(1.0, 1.0, 1.0));
+        let materials = vec![
+            material(vec![LobeConfig::Lambertian(white())]),
+            material(vec![LobeConfig::OrenNayar { color: white(), sigma: 20.0 }]),
+            material(vec![LobeConfig::BlinnPhong { color: white(), exponent: 30.0 }]),
+            material(vec![LobeConfig::Conductor { color: white(), roughness: 0.4 }]),
+            material(vec![LobeConfig::Dielectric {
+                color: Color::new(1.0, 1.0, 1.0),
+                refractive_index: 1.5,
+                roughness: 0.3,
+            }]),
+            material(vec![LobeConfig::Lambertian(white()),
+                          LobeConfig::Mirror(Color::new(0.5, 0.5, 0.5))]),
+        ];
+        let normal = Vector::new(0.0, 0.0, 1.0).direction();
+        let geom = shape::Intersection::new(1.0, Point::new(0.0, 0.0, 0.0), [0.0, 0.0], normal);
+        for material in materials {
+            let i = Intersection {
+                geom: geom,
+                shading_normal: normal,
+                material: &material,
+                footprint: 0.0,
+            };
+            for &wo in &[Vector::new(0.0, 0.3, 1.0), Vector::new(0.8, 0.0, 0.5),
+                         Vector::new(0.0, 0.5, -1.0)] {
+                let wo = wo.direction();
+                let n = 20000;
+                let mut total = 0.0;
+                for _ in 0..n {
+                    let s = match material.sample(&i, wo) {
+                        Some(s) => s,
+                        None => continue,
+                    };
+                    total += s.weight.grayscale();
+                    if s.is_delta {
+                        continue;
+                    }
+                    assert!(s.pdf > 0.0);
+                    assert!((material.pdf(&i, wo, s.direction) - s.pdf).abs() < 1e-6 * s.pdf);
+                    let cos = s.direction.dot(normal).abs();
+                    let expected = material.eval(&i, wo, s.direction) * (cos / s.pdf);
+                    assert!((expected.grayscale() - s.weight.grayscale()).abs() < 1e-6);
+                }
+                assert!(total / n as f64 <= material.albedo(&i).grayscale() * 1.02);
+            }
+        }
+    }
+
+    #[test]
+    fn test_shading_normal_keeps_sides() {
+        // a normal map tilts the shading normal far from the geometric one
+        let normal = Vector::new(0.0, 0.0, 1.0).direction();
+        let geom = shape::Intersection::new(1.0, Point::new(0.0, 0.0, 0.0), [0.0, 0.0], normal);
+        let white = TextureConfig::Color(Color::new(1.0, 1.0, 1.0));
+        let lambertian = material(vec![LobeConfig::Lambertian(white)]);
+        let i = Intersection {
+            geom: geom,
+            shading_normal: Vector::new(0.9, 0.0, 0.44).direction(),
+            material: &lambertian,
+            footprint: 0.0,
+        };
+
+        // the light doesn't leak through the surface
+        let (above, below) = (Vector::new(0.0, 0.0, 1.0), Vector::new(1.0, 0.0, -0.2));
+        assert!(lambertian.eval(&i, above.direction(), below.direction()).grayscale() == 0.0);
+
+        // a grazing ray is still outside of a transparent medium
+        let dielectric = Dielectric {
+            color: Color::new(1.0, 1.0, 1.0),
+            refractive_index: 1.5,
+            distribution: None,
+        };
+        assert_eq!(dielectric.indices(&i, Vector::new(-1.0, 0.0, 0.2).direction()), (1.0, 1.5));
     }
 }
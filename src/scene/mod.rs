@@ -1,71 +1,131 @@
 mod camera;
 mod config;
+mod environment;
 mod light;
 // FIXME: https://github.com/rust-lang/rust/issues/16264
 pub mod material;
+mod mtl;
+mod noise;
 mod primitive;
+mod sky;
+mod texture;
 
+use std::f64;
 use std::error::Error;
 use std::collections::HashMap;
 
-use geom::{Point, UnitVector, Ray};
+use geom::{UnitVector, Ray, Dot};
+use geom::shape::{Bound, Bvh, BvhOptions};
 use color::Color;
-use self::camera::Camera;
 use self::primitive::Primitive;
-use self::config::read_primitive;
+use self::config::{read_camera, read_primitive};
+use self::sky::read_sky;
 
-pub use self::light::LightSource;
+pub use self::environment::Environment;
+pub use self::light::{LightSource, LightSample};
+pub use self::sky::Sun;
 pub use self::primitive::Intersection;
-pub use self::camera::ScreenPoint;
-pub use self::material::{Texture, Material};
+pub use self::camera::{Camera, ScreenPoint};
+pub use self::material::{Texture, Material, Bsdf, BsdfSample};
 pub use self::config::SceneConfig;
 
 
 pub struct Scene {
-    pub camera: Camera,
+    pub camera: Box<Camera>,
     pub ambient_light: Color,
     pub background_color: Color,
+    pub environment: Option<Environment>,
+    pub sun: Option<Sun>,
     pub lights: Vec<LightSource>,
-    primitives: Vec<Primitive>,
+    /// Bounded primitives are kept in a BVH, infinite ones are intersected
+    /// one by one.
+    index: Option<Bvh<Primitive>>,
+    unbounded: Vec<Primitive>,
     materials: Vec<Material>,
+    pixel_cone: PixelCone,
 }
 
 
 impl Scene {
-    pub fn new(config: SceneConfig) -> Result<Scene, Box<Error>> {
+    /// `resolution` of the image is needed by the cameras whose screen is
+    /// not given explicitly and to estimate how blurry textures should be.
+    pub fn new(config: SceneConfig, resolution: [u32; 2]) -> Result<Scene, Box<Error>> {
         let mut materials = Vec::new();
         let mut material_index_map = HashMap::new();
         for (k, v) in config.materials {
             material_index_map.insert(k, materials.len());
-            materials.push(Material::from(v));
+            materials.push(Material::new(v)?);
         }
 
+        let bvh_options = match config.bvh {
+            Some(ref bvh) => bvh.options()?,
+            None => BvhOptions::default(),
+        };
+        let mut lights = config.lights.into_iter()
+            .map(LightSource::new)
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut meshes = HashMap::new();
         let primitives = config.primitives.into_iter()
-            .map(|p| read_primitive(p, &material_index_map))
+            .map(|p| read_primitive(p, &material_index_map, &mut materials, &bvh_options,
+                                    &mut meshes, &mut lights))
             .collect::<Result<Vec<Primitive>, _>>()?;
-        let lights = config.lights.into_iter()
-                                  .map(LightSource::from)
-                                  .collect();
+        let (bounded, unbounded): (Vec<_>, Vec<_>) = primitives.into_iter()
+            .partition(|p| p.bound().is_finite());
+        let index = if bounded.is_empty() {
+            None
+        } else {
+            Some(Bvh::new(bounded, &bvh_options))
+        };
+        let (environment, sun) = match (config.environment, config.sky) {
+            (None, None) => (None, None),
+            (Some(environment), None) => (Some(Environment::new(environment)?), None),
+            (None, Some(sky)) => {
+                let (environment, sun) = read_sky(sky)?;
+                (Some(environment), sun)
+            }
+            (Some(_), Some(_)) => {
+                return Err(From::from("scene can't have both an environment and a sky"));
+            }
+        };
 
+        let aspect_ratio = resolution[0] as f64 / resolution[1] as f64;
+        let camera = read_camera(config.camera, aspect_ratio)?;
+        let pixel_cone = PixelCone::new(&*camera, resolution);
         Ok(Scene {
-            camera: Camera::from(config.camera),
+            camera: camera,
             ambient_light: config.ambient_light,
             background_color: config.background_color,
+            environment: environment,
+            sun: sun,
             lights: lights,
-            primitives: primitives,
+            index: index,
+            unbounded: unbounded,
             materials: materials,
+            pixel_cone: pixel_cone,
         })
     }
 
-    pub fn is_visible(&self, what: Point, from: &Intersection) -> bool {
-        let ray = Ray::from_to(from.geom.point, what);
-        let ray = Ray::from_to(ray.along(1e-6), what);
+    /// Whether nothing is in the way from the point of the intersection up
+    /// to the `distance` in the `direction`, which may be infinite. Points
+    /// on the surfaces of emissive primitives are visible even if rounding
+    /// errors make the shadow ray hit their surface before them.
+    pub fn is_visible(&self, direction: UnitVector, distance: f64, from: &Intersection) -> bool {
         // FIXME: what if obstacle is behind a light source?
-        match self.find_obstacle(&ray) {
+        match self.closest_hit(&self.ray_from(from, direction)) {
             None => true,
-            Some(i) => {
-                i.geom.t > (from.geom.point - what).length()
-            }
+            Some(i) => i.geom.t > (distance - 1e-6) * (1.0 - 1e-6),
+        }
+    }
+
+    /// Light arriving along a ray which misses the scene.
+    pub fn background(&self, direction: UnitVector) -> Color {
+        let sky = match self.environment {
+            Some(ref environment) => environment.radiance(direction),
+            None => self.background_color,
+        };
+        match self.sun {
+            Some(ref sun) => sky + sun.radiance(direction),
+            None => sky,
         }
     }
 
@@ -76,14 +136,70 @@ impl Scene {
         }
     }
 
+    /// The nearest intersection with its shading normal.
     pub fn find_obstacle(&self, ray: &Ray) -> Option<Intersection> {
-        self.primitives
-            .iter()
-            .filter_map(|obj| {
-                let material = &self.materials[obj.material_idx];
-                obj.shape.intersect(&ray)
-                         .map(|g| Intersection { geom: g, material: material })
-            })
-            .min()
+        self.closest_hit(ray).map(|mut i| {
+            i.shading_normal = i.material.shading_normal(&i);
+            i
+        })
+    }
+
+    fn closest_hit(&self, ray: &Ray) -> Option<Intersection> {
+        let mut result: Option<Intersection> = None;
+        {
+            let mut hit = |obj: &Primitive| {
+                obj.shape.intersect(ray).map(|g| {
+                    let i = Intersection {
+                        geom: g,
+                        shading_normal: g.normal,
+                        material: &self.materials[obj.material_at(g.material_slot)],
+                        footprint: self.pixel_cone.width_at(g.t),
+                    };
+                    if result.map_or(true, |r| i < r) {
+                        result = Some(i);
+                    }
+                    g.t
+                })
+            };
+            let mut max_t = f64::INFINITY;
+            for obj in self.unbounded.iter() {
+                if let Some(t) = hit(obj) {
+                    max_t = max_t.min(t);
+                }
+            }
+            if let Some(ref index) = self.index {
+                index.traverse(ray, max_t, hit);
+            }
+        }
+        result
+    }
+}
+
+
+/// Cone around a camera ray which covers a single pixel. Secondary rays are
+/// assumed to spread in the same way, which is good enough to pick a level
+/// of texture detail.
+struct PixelCone {
+    width: f64,
+    spread: f64,
+}
+
+impl PixelCone {
+    fn new(camera: &Camera, resolution: [u32; 2]) -> PixelCone {
+        let step = 2.0 / resolution[0] as f64;
+        let lens_center = [0.0, 0.0];
+        let rays = (camera.cast_ray(ScreenPoint::new(0.0, 0.0), lens_center),
+                    camera.cast_ray(ScreenPoint::new(step, 0.0), lens_center));
+        match rays {
+            (Some(a), Some(b)) => PixelCone {
+                width: (a.origin - b.origin).length(),
+                spread: a.direction.dot(b.direction).min(1.0).acos(),
+            },
+            _ => PixelCone { width: 0.0, spread: 0.0 },
+        }
+    }
+
+    fn width_at(&self, distance: f64) -> f64 {
+        self.width + self.spread * distance
     }
 }
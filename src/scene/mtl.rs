@@ -0,0 +1,220 @@
+use std::{fmt, io};
+use std::error::Error;
+use std::io::BufRead;
+use std::path::Path;
+
+use color::Color;
+use super::config::{MaterialConfig, TextureConfig};
+
+
+#[derive(Debug)]
+pub struct ParseMtlError {
+    /// Counted from 1.
+    pub line: usize,
+    pub description: String,
+}
+
+impl Error for ParseMtlError {
+    fn description(&self) -> &str {
+        &self.description
+    }
+}
+
+impl fmt::Display for ParseMtlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at line {} of .mtl file", self.description, self.line)
+    }
+}
+
+
+/// Statements of a material in a .mtl library which the renderer uses.
+struct MtlMaterial {
+    name: String,
+    /// `Kd`
+    diffuse: Color,
+    /// `Ks`
+    specular: Color,
+    /// `Ns`, the exponent of the highlights.
+    shininess: f64,
+    /// `Ni`
+    refractive_index: f64,
+    /// `d`, or one minus `Tr`: the opacity.
+    dissolve: f64,
+    /// `illum`, the illumination model.
+    illumination: u32,
+    /// `map_Kd`
+    diffuse_map: Option<String>,
+}
+
+impl MtlMaterial {
+    fn new(name: String) -> MtlMaterial {
+        MtlMaterial {
+            name: name,
+            diffuse: Color::new(0.8, 0.8, 0.8),
+            specular: Color::new(0.0, 0.0, 0.0),
+            shininess: 1.0,
+            refractive_index: 1.0,
+            dissolve: 1.0,
+            illumination: 2,
+            diffuse_map: None,
+        }
+    }
+
+    /// Models without highlights (0 and 1) lose `Ks`, the ones with ray
+    /// traced reflections (3 and above) turn it into the reflectance. The
+    /// transparent part refracts with `Ni`.
+    fn config(self, directory: &Path) -> MaterialConfig {
+        let texture = match self.diffuse_map {
+            None => TextureConfig::Color(self.diffuse),
+            Some(map) => TextureConfig::Image {
+                location: directory.join(map).to_string_lossy().into_owned(),
+                filtering: None,
+                wrap: None,
+                scale: None,
+            },
+        };
+        let black = Color::new(0.0, 0.0, 0.0);
+        let specular = if self.illumination >= 2 { self.specular } else { black };
+        let reflectance = if self.illumination >= 3 { self.specular.grayscale() } else { 0.0 };
+        let opacity = self.dissolve.max(0.0).min(1.0);
+        let refractive_index = if self.refractive_index > 0.0 { self.refractive_index } else { 1.0 };
+        MaterialConfig {
+            specular: Some(self.shininess),
+            diffuse: Some(opacity),
+            texture: Some(texture),
+            reflectance: Some(reflectance.min(opacity)),
+            transmittance: Some(1.0 - opacity),
+            refractive_index: Some(refractive_index),
+            bump: None,
+            normal_map: None,
+            specular_color: Some(specular),
+            lobes: None,
+            emission: None,
+        }
+    }
+}
+
+
+/// Materials of the .mtl library in the `source` by their names. Texture
+/// maps are looked up relative to the `directory`.
+pub fn read_mtl(source: &mut io::Read, directory: &Path)
+    -> Result<Vec<(String, MaterialConfig)>, ParseMtlError> {
+    let mut materials: Vec<MtlMaterial> = Vec::new();
+    for (i, line) in io::BufReader::new(source).lines().enumerate() {
+        let error = |description: String| ParseMtlError { line: i + 1, description: description };
+        let line = line.map_err(|e| error(e.to_string()))?;
+        let line = match line.find('#') {
+            Some(comment) => &line[..comment],
+            None => &line[..],
+        };
+        let mut words = line.split_whitespace();
+        let statement = match words.next() {
+            Some(statement) => statement,
+            None => continue,
+        };
+        let args = words.collect::<Vec<_>>();
+        if statement == "newmtl" {
+            materials.push(MtlMaterial::new(args.join(" ")));
+            continue;
+        }
+        let material = match materials.last_mut() {
+            Some(material) => material,
+            // statements which don't describe materials are ignored below
+            None if !is_material_statement(statement) => continue,
+            None => return Err(error(format!("{} before newmtl", statement))),
+        };
+        let number = |s: &str| {
+            s.parse::<f64>().map_err(|_| error(format!("invalid number {:?}", s)))
+        };
+        let single = || match args.len() {
+            1 => number(args[0]),
+            _ => Err(error(format!("{} should have one value", statement))),
+        };
+        match statement {
+            "Kd" | "Ks" => {
+                let color = match args.len() {
+                    1 => { let v = number(args[0])?; Color::new(v, v, v) }
+                    3 => Color::new(number(args[0])?, number(args[1])?, number(args[2])?),
+                    _ => return Err(error(format!("{} should be given as r g b", statement))),
+                };
+                if statement == "Kd" {
+                    material.diffuse = color;
+                } else {
+                    material.specular = color;
+                }
+            }
+            "Ns" => material.shininess = single()?,
+            "Ni" => material.refractive_index = single()?,
+            "d" => material.dissolve = single()?,
+            "Tr" => material.dissolve = 1.0 - single()?,
+            "illum" => material.illumination = single()? as u32,
+            // the file name follows the options
+            "map_Kd" => match args.last() {
+                Some(file) => material.diffuse_map = Some(file.to_string()),
+                None => return Err(error("map_Kd without a file".to_string())),
+            },
+            _ => {}
+        }
+    }
+    Ok(materials.into_iter()
+       .map(|m| (m.name.clone(), m.config(directory)))
+       .collect())
+}
+
+fn is_material_statement(statement: &str) -> bool {
+    match statement {
+        "Kd" | "Ks" | "Ns" | "Ni" | "d" | "Tr" | "illum" | "map_Kd" => true,
+        _ => false,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use scene::config::TextureConfig;
+
+    #[test]
+    fn test_read_mtl() {
+        let source = "# two materials
+            newmtl wood
+            Kd 0.5 0.25 0.125
+            Ks 0.1
+            Ns 40
+            illum 2
+            map_Kd -s 2 2 1 textures/wood.png
+
+            newmtl glass
+            Kd 0 0 0
+            Ks 1 1 1
+            Ni 1.5
+            d 0.1
+            illum 7
+        ";
+        let materials = read_mtl(&mut source.as_bytes(), Path::new("models")).unwrap();
+        assert_eq!(materials.len(), 2);
+
+        let (ref name, ref wood) = materials[0];
+        assert_eq!(name, "wood");
+        assert_eq!(wood.specular, Some(40.0));
+        assert_eq!(wood.specular_color.unwrap().rgb(), [0.1, 0.1, 0.1]);
+        assert!(wood.reflectance == Some(0.0) && wood.transmittance == Some(0.0));
+        match wood.texture {
+            Some(TextureConfig::Image { ref location, .. }) =>
+                assert_eq!(Path::new(location), Path::new("models/textures/wood.png")),
+            _ => panic!("map_Kd should be an image texture"),
+        }
+
+        let (ref name, ref glass) = materials[1];
+        assert_eq!(name, "glass");
+        assert!((glass.transmittance.unwrap() - 0.9).abs() < 1e-9);
+        assert!((glass.reflectance.unwrap() - 0.1).abs() < 1e-9);
+        assert_eq!(glass.refractive_index, Some(1.5));
+
+        let e = read_mtl(&mut "newmtl a\nKd 1 x 1\n".as_bytes(), Path::new(".")).unwrap_err();
+        assert_eq!(e.line, 2);
+        let e = read_mtl(&mut "Kd 1 1 1\n".as_bytes(), Path::new(".")).unwrap_err();
+        assert_eq!(e.line, 1);
+    }
+}
@@ -0,0 +1,196 @@
+use geom::Point;
+
+
+/// Deterministic pseudo random generator, so that the noise depends only on
+/// the seed and not on the version of `rand`.
+struct XorShift(u64);
+
+impl XorShift {
+    fn new(seed: u32) -> XorShift {
+        XorShift((seed as u64) << 32 ^ 0x9E37_79B9_7F4A_7C15)
+    }
+
+    fn next(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        x
+    }
+}
+
+
+/// Ken Perlin's gradient noise. Values are in `[-1, 1]` and change on the
+/// scale of one unit.
+pub struct Perlin {
+    permutation: [u8; 512],
+}
+
+impl Perlin {
+    pub fn new(seed: u32) -> Perlin {
+        let mut rng = XorShift::new(seed);
+        let mut table = [0u8; 256];
+        for i in 0..256 {
+            table[i] = i as u8;
+        }
+        for i in (1..256).rev() {
+            let j = (rng.next() % (i as u64 + 1)) as usize;
+            table.swap(i, j);
+        }
+        let mut permutation = [0u8; 512];
+        for i in 0..512 {
+            permutation[i] = table[i % 256];
+        }
+        Perlin { permutation: permutation }
+    }
+
+    pub fn noise(&self, p: Point) -> f64 {
+        let (xi, yi, zi) = (p[0].floor(), p[1].floor(), p[2].floor());
+        let (x, y, z) = (p[0] - xi, p[1] - yi, p[2] - zi);
+        let (xi, yi, zi) = (wrap(xi), wrap(yi), wrap(zi));
+        let (u, v, w) = (fade(x), fade(y), fade(z));
+
+        let perm = |i: usize| self.permutation[i] as usize;
+        let a = perm(xi) + yi;
+        let (aa, ab) = (perm(a) + zi, perm(a + 1) + zi);
+        let b = perm(xi + 1) + yi;
+        let (ba, bb) = (perm(b) + zi, perm(b + 1) + zi);
+
+        let n = lerp(w,
+                     lerp(v,
+                          lerp(u, grad(perm(aa), x, y, z), grad(perm(ba), x - 1.0, y, z)),
+                          lerp(u, grad(perm(ab), x, y - 1.0, z),
+                               grad(perm(bb), x - 1.0, y - 1.0, z))),
+                     lerp(v,
+                          lerp(u, grad(perm(aa + 1), x, y, z - 1.0),
+                               grad(perm(ba + 1), x - 1.0, y, z - 1.0)),
+                          lerp(u, grad(perm(ab + 1), x, y - 1.0, z - 1.0),
+                               grad(perm(bb + 1), x - 1.0, y - 1.0, z - 1.0))));
+        // the theoretical bound is slightly larger than one
+        n.max(-1.0).min(1.0)
+    }
+
+    /// Fractal Brownian motion: sum of `octaves` layers of noise, each of
+    /// twice the frequency and half the amplitude of the previous one.
+    /// Normalized to `[-1, 1]`.
+    pub fn fbm(&self, p: Point, octaves: u32) -> f64 {
+        self.octaves(p, octaves, |n| n)
+    }
+
+    /// Like `fbm`, but sums the absolute values of the noise, which gives
+    /// sharp creases. Normalized to `[0, 1]`.
+    pub fn turbulence(&self, p: Point, octaves: u32) -> f64 {
+        self.octaves(p, octaves, f64::abs)
+    }
+
+    fn octaves<F: Fn(f64) -> f64>(&self, p: Point, octaves: u32, f: F) -> f64 {
+        let origin = Point::new(0.0, 0.0, 0.0);
+        let mut sum = 0.0;
+        let mut total_amplitude = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = 1.0;
+        for _ in 0..octaves {
+            sum += amplitude * f(self.noise(origin + (p - origin) * frequency));
+            total_amplitude += amplitude;
+            amplitude *= 0.5;
+            frequency *= 2.0;
+        }
+        if total_amplitude == 0.0 { 0.0 } else { sum / total_amplitude }
+    }
+}
+
+fn wrap(x: f64) -> usize {
+    (x as i64 & 255) as usize
+}
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f64, a: f64, b: f64) -> f64 {
+    a + t * (b - a)
+}
+
+/// Dot product with one of the 12 gradients pointing to the edges of a cube.
+fn grad(hash: usize, x: f64, y: f64, z: f64) -> f64 {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 { y } else if h == 12 || h == 14 { x } else { z };
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+
+/// Cellular noise: distance to the nearest of feature points scattered one
+/// per unit cube.
+pub struct Worley {
+    seed: u64,
+}
+
+impl Worley {
+    pub fn new(seed: u32) -> Worley {
+        Worley { seed: seed as u64 }
+    }
+
+    pub fn distance(&self, p: Point) -> f64 {
+        let cell = (p[0].floor(), p[1].floor(), p[2].floor());
+        let mut nearest = ::std::f64::INFINITY;
+        for dx in -1..2 {
+            for dy in -1..2 {
+                for dz in -1..2 {
+                    let c = (cell.0 + dx as f64, cell.1 + dy as f64, cell.2 + dz as f64);
+                    let feature = self.feature_point(c);
+                    nearest = nearest.min((feature - p).length());
+                }
+            }
+        }
+        nearest
+    }
+
+    fn feature_point(&self, cell: (f64, f64, f64)) -> Point {
+        let hash = (cell.0 as i64 as u64).wrapping_mul(0x8DA6_B343)
+            ^ (cell.1 as i64 as u64).wrapping_mul(0xD816_3841)
+            ^ (cell.2 as i64 as u64).wrapping_mul(0xCB1A_B31F)
+            ^ self.seed.wrapping_mul(0x9E37_79B9);
+        let mut rng = XorShift(hash | 1);
+        let mut coordinate = || (rng.next() >> 11) as f64 / (1u64 << 53) as f64;
+        Point::new(cell.0 + coordinate(), cell.1 + coordinate(), cell.2 + coordinate())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geom::Point;
+    use utils::props::check_prop;
+
+    #[test]
+    fn test_noise_is_deterministic_and_bounded() {
+        let (a, b, other) = (Perlin::new(42), Perlin::new(42), Perlin::new(7));
+        let (worley, worley_again) = (Worley::new(42), Worley::new(42));
+        let mut differences = 0;
+        check_prop(|p: Point| {
+            let p = Point::new(p[0] % 100.0, p[1] % 100.0, p[2] % 100.0);
+            let n = a.noise(p);
+            assert_eq!(n, b.noise(p));
+            assert!(-1.0 <= n && n <= 1.0);
+            if n != other.noise(p) {
+                differences += 1;
+            }
+            let f = a.fbm(p, 6);
+            assert!(-1.0 <= f && f <= 1.0);
+            let t = a.turbulence(p, 6);
+            assert!(0.0 <= t && t <= 1.0);
+
+            let d = worley.distance(p);
+            assert_eq!(d, worley_again.distance(p));
+            // the feature point of the cell itself is within its diagonal
+            assert!(0.0 <= d && d <= 3f64.sqrt());
+        });
+        assert!(differences > 900);
+
+        // noise vanishes at the lattice points
+        assert_eq!(a.noise(Point::new(3.0, -5.0, 17.0)), 0.0);
+    }
+}
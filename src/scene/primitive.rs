@@ -1,29 +1,60 @@
 use std::cmp::Ordering;
+use std::sync::Arc;
 
-use geom::shape::{self, Shape};
+use geom::UnitVector;
+use geom::shape::{self, Shape, Bound, BoundBox};
 use super::material::Material;
 
 pub struct Primitive {
-    pub shape: Box<Shape>,
+    /// Shared between the primitives which instance the same shape.
+    pub shape: Arc<Shape>,
     pub material_idx: usize,
+    /// Materials of the slots of the shape from 1 on, the slot 0 and the
+    /// slots beyond these get `material_idx`.
+    slot_materials: Vec<usize>,
 }
 
 impl Primitive {
-    pub fn new<S>(shape: S, material_idx: usize) -> Primitive
-        where S: Shape + 'static
-    {
+    pub fn new(shape: Arc<Shape>, material_idx: usize) -> Primitive {
         Primitive {
-            shape: Box::new(shape),
-            material_idx: material_idx
+            shape: shape,
+            material_idx: material_idx,
+            slot_materials: Vec::new(),
         }
     }
+
+    pub fn with_slot_materials(mut self, slot_materials: Vec<usize>) -> Primitive {
+        self.slot_materials = slot_materials;
+        self
+    }
+
+    /// Index of the material of the `material_slot` of the shape.
+    pub fn material_at(&self, material_slot: usize) -> usize {
+        match material_slot {
+            0 => self.material_idx,
+            slot => self.slot_materials.get(slot - 1).cloned().unwrap_or(self.material_idx),
+        }
+    }
+}
+
+impl Bound for Primitive {
+    fn bound(&self) -> BoundBox {
+        self.shape.bound()
+    }
 }
 
 
 #[derive(Clone, Copy)]
 pub struct Intersection<'a> {
+    /// The normal of `geom` is the geometric one, which tells the sides of
+    /// the surface apart.
     pub geom: shape::Intersection,
-    pub material: &'a Material
+    /// Normal which the scattering is evaluated around, it accounts for the
+    /// bump and normal maps.
+    pub shading_normal: UnitVector,
+    pub material: &'a Material,
+    /// Approximate width of the surface area seen through one pixel.
+    pub footprint: f64,
 }
 
 impl<'a> Ord for Intersection<'a> {
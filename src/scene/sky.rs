@@ -0,0 +1,255 @@
+use std::error::Error;
+use std::f64::consts::PI;
+
+use rand;
+use utils::datastructures::Matrix;
+
+use color::Color;
+use geom::{Dot, UnitVector, Vector};
+use super::config::SkyConfig;
+use super::environment::{Environment, direction_at};
+use super::material::around;
+
+
+/// Radiance of a luminance of 1 kcd/m^2, chosen so that a white surface lit
+/// by a high sun is about white.
+const KCD_RADIANCE: f64 = 0.03;
+
+/// Illuminance of the sun above the atmosphere in kilolux.
+const SUN_ILLUMINANCE: f64 = 127.0;
+
+/// Pixels of the image of the sky.
+const SKY_RESOLUTION: [u32; 2] = [256, 128];
+
+
+/// Sky of the Preetham model as an environment, and the sun if it is lit.
+pub fn read_sky(config: SkyConfig) -> Result<(Environment, Option<Sun>), Box<Error>> {
+    if !(0.0 < config.sun_elevation && config.sun_elevation <= 90.0) {
+        return Err(From::from("sun_elevation should be between 0 and 90 degrees"));
+    }
+    if !(2.0 <= config.turbidity && config.turbidity <= 10.0) {
+        return Err(From::from("turbidity should be between 2 and 10"));
+    }
+    let intensity = config.intensity.unwrap_or(1.0);
+    if intensity < 0.0 {
+        return Err(From::from("sky intensity should not be negative"));
+    }
+    let n_samples = config.n_samples.unwrap_or(1);
+    if n_samples == 0 {
+        return Err(From::from("sky should have at least one sample"));
+    }
+
+    let sky = Preetham::new(config.sun_elevation.to_radians(),
+                            config.sun_azimuth.to_radians(),
+                            config.turbidity);
+    let scale = KCD_RADIANCE * intensity;
+    let (w, h) = (SKY_RESOLUTION[0], SKY_RESOLUTION[1]);
+    let mut image = Matrix::fill([w, h], Color::new(0.0, 0.0, 0.0));
+    for y in 0..h {
+        for x in 0..w {
+            let u = (x as f64 + 0.5) / w as f64;
+            let t = (y as f64 + 0.5) / h as f64;
+            image[[x, y]] = sky.radiance(direction_at(u, t)) * scale;
+        }
+    }
+    let environment = Environment::from_image(image, 1.0, 0.0, n_samples);
+
+    let sun = match config.sun {
+        None => None,
+        Some(sun) => {
+            let diameter = sun.angular_diameter.unwrap_or(0.53);
+            if !(0.0 < diameter && diameter < 180.0) {
+                return Err(From::from("angular_diameter should be between 0 and 180 degrees"));
+            }
+            let n_samples = sun.n_samples.unwrap_or(1);
+            if n_samples == 0 {
+                return Err(From::from("sun should have at least one sample"));
+            }
+            Some(Sun {
+                direction: sky.sun,
+                cos_radius: (diameter.to_radians() / 2.0).cos(),
+                irradiance: sky.sun_transmittance() * (SUN_ILLUMINANCE * scale),
+                n_samples: n_samples,
+            })
+        }
+    };
+    Ok((environment, sun))
+}
+
+
+/// Directional light of a distant disk, which gives soft shadows.
+pub struct Sun {
+    direction: UnitVector,
+    /// Cosine of the angular radius of the disk.
+    cos_radius: f64,
+    /// Irradiance of a surface facing the sun.
+    irradiance: Color,
+    n_samples: u32,
+}
+
+impl Sun {
+    /// Number of shadow rays which should be used to estimate the light
+    /// arriving at a point.
+    pub fn n_samples(&self) -> u32 {
+        self.n_samples
+    }
+
+    pub fn irradiance(&self) -> Color {
+        self.irradiance
+    }
+
+    /// Uniformly distributed direction towards the disk.
+    pub fn sample(&self) -> UnitVector {
+        let cos = 1.0 - rand::random::<f64>() * (1.0 - self.cos_radius);
+        around(self.direction, cos)
+    }
+
+    /// Light arriving along a ray going in the `direction`, which is bright
+    /// only if the ray hits the disk.
+    pub fn radiance(&self, direction: UnitVector) -> Color {
+        if direction.dot(self.direction) < self.cos_radius {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        let solid_angle = 2.0 * PI * (1.0 - self.cos_radius);
+        self.irradiance / solid_angle
+    }
+}
+
+
+/// Luminance and chromaticity of the sky from "A Practical Analytic Model
+/// for Daylight" by Preetham, Shirley and Smits. Below the horizon the sky
+/// is black.
+struct Preetham {
+    sun: UnitVector,
+    /// Zenith angle of the sun.
+    theta_sun: f64,
+    turbidity: f64,
+    /// Coefficients of the Perez distribution for `Y`, `x` and `y`.
+    perez: [[f64; 5]; 3],
+    /// `Y` in kcd/m^2, `x` and `y` at the zenith.
+    zenith: [f64; 3],
+}
+
+impl Preetham {
+    /// `azimuth` of the sun is counterclockwise around `y` from `-z`.
+    fn new(elevation: f64, azimuth: f64, turbidity: f64) -> Preetham {
+        let t = turbidity;
+        let sun = Vector::new(-elevation.cos() * azimuth.sin(),
+                              elevation.sin(),
+                              -elevation.cos() * azimuth.cos());
+        let theta = PI / 2.0 - elevation;
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta);
+        let polynomial = |c: [f64; 4]| {
+            c[0] * theta.powi(3) + c[1] * theta.powi(2) + c[2] * theta + c[3]
+        };
+        let chromaticity = |c2: [f64; 4], c1: [f64; 4], c0: [f64; 4]| {
+            t * t * polynomial(c2) + t * polynomial(c1) + polynomial(c0)
+        };
+        Preetham {
+            sun: sun.direction(),
+            theta_sun: theta,
+            turbidity: t,
+            perez: [
+                [0.1787 * t - 1.4630, -0.3554 * t + 0.4275, -0.0227 * t + 5.3251,
+                 0.1206 * t - 2.5771, -0.0670 * t + 0.3703],
+                [-0.0193 * t - 0.2592, -0.0665 * t + 0.0008, -0.0004 * t + 0.2125,
+                 -0.0641 * t - 0.8989, -0.0033 * t + 0.0452],
+                [-0.0167 * t - 0.2608, -0.0950 * t + 0.0092, -0.0079 * t + 0.2102,
+                 -0.0441 * t - 1.6537, -0.0109 * t + 0.0529],
+            ],
+            zenith: [
+                (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192,
+                chromaticity([0.00166, -0.00375, 0.00209, 0.0],
+                             [-0.02903, 0.06377, -0.03202, 0.00394],
+                             [0.11693, -0.21196, 0.06052, 0.25886]),
+                chromaticity([0.00275, -0.00610, 0.00317, 0.0],
+                             [-0.04214, 0.08970, -0.04153, 0.00516],
+                             [0.15346, -0.26756, 0.06670, 0.26688]),
+            ],
+        }
+    }
+
+    fn radiance(&self, direction: UnitVector) -> Color {
+        let cos_theta = direction[1];
+        if cos_theta <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        let gamma = direction.dot(self.sun).max(-1.0).min(1.0).acos();
+        let value = |k: usize| {
+            let f = |theta_cos: f64, gamma: f64| {
+                let p = &self.perez[k];
+                (1.0 + p[0] * (p[1] / theta_cos).exp())
+                    * (1.0 + p[2] * (p[3] * gamma).exp() + p[4] * gamma.cos() * gamma.cos())
+            };
+            self.zenith[k] * f(cos_theta, gamma) / f(1.0, self.theta_sun)
+        };
+        xyy_to_rgb(value(1), value(2), value(0))
+    }
+
+    /// Fraction of the sunlight which passes through the atmosphere at red,
+    /// green and blue wavelengths, scattered by molecules and by aerosols.
+    fn sun_transmittance(&self) -> Color {
+        let theta = self.theta_sun.to_degrees();
+        let air_mass = 1.0 / (self.theta_sun.cos() + 0.15 * (93.885 - theta).powf(-1.253));
+        let beta = 0.04608 * self.turbidity - 0.04586;
+        let transmittance = |lambda: f64| {
+            // the wavelength in micrometers
+            let rayleigh = (-0.008735 * lambda.powf(-4.08) * air_mass).exp();
+            let aerosol = (-beta * lambda.powf(-1.3) * air_mass).exp();
+            rayleigh * aerosol
+        };
+        Color::new(transmittance(0.68), transmittance(0.55), transmittance(0.44))
+    }
+}
+
+/// Linear sRGB of the chromaticity `x`, `y` and the luminance `big_y`,
+/// negative components are clipped.
+fn xyy_to_rgb(x: f64, y: f64, big_y: f64) -> Color {
+    let big_x = x * big_y / y;
+    let big_z = (1.0 - x - y) * big_y / y;
+    let r = 3.2406 * big_x - 1.5372 * big_y - 0.4986 * big_z;
+    let g = -0.9689 * big_x + 1.8758 * big_y + 0.0415 * big_z;
+    let b = 0.0557 * big_x - 0.2040 * big_y + 1.0570 * big_z;
+    Color::new(r.max(0.0), g.max(0.0), b.max(0.0))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geom::Vector;
+
+    #[test]
+    fn test_daylight() {
+        let sky = Preetham::new(30f64.to_radians(), 90f64.to_radians(), 3.0);
+        assert!((Vector::from(sky.sun) - Vector::new(-0.75f64.sqrt(), 0.5, 0.0)).is_almost_zero());
+
+        // the zenith of a clear sky is blue, the sky around the sun is
+        // brighter than the opposite one
+        let zenith = sky.radiance(Vector::new(0.0, 1.0, 0.0).direction()).rgb();
+        assert!(zenith[2] > zenith[0]);
+        assert!(3.0 < sky.zenith[0] && sky.zenith[0] < 10.0);
+        let near = sky.radiance(Vector::new(-1.0, 0.3, 0.0).direction()).luminance();
+        let opposite = sky.radiance(Vector::new(1.0, 0.3, 0.0).direction()).luminance();
+        assert!(near > 2.0 * opposite);
+
+        // the setting sun is red
+        let high = Preetham::new(80f64.to_radians(), 0.0, 3.0).sun_transmittance().rgb();
+        let low = Preetham::new(3f64.to_radians(), 0.0, 3.0).sun_transmittance().rgb();
+        assert!(high[2] > 0.5 && high[2] / high[0] > 0.7);
+        assert!(low[2] / low[0] < 0.5 * high[2] / high[0]);
+
+        let sun = Sun {
+            direction: sky.sun,
+            cos_radius: 0.99,
+            irradiance: Color::new(1.0, 1.0, 1.0),
+            n_samples: 1,
+        };
+        for _ in 0..100 {
+            let d = sun.sample();
+            assert!(d.dot(sky.sun) >= 0.99 - 1e-9);
+            assert!(sun.radiance(d).grayscale() > 0.0);
+        }
+        assert_eq!(sun.radiance(-sky.sun).grayscale(), 0.0);
+    }
+}
@@ -0,0 +1,624 @@
+use std::error::Error;
+use std::f64::consts::PI;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+use png;
+use utils::datastructures::Matrix;
+
+use geom::Point;
+use color::Color;
+use super::material::Texture;
+use super::primitive::Intersection;
+use super::config::{TextureFiltering, TextureWrap};
+use super::noise::{Perlin, Worley};
+
+
+/// Texture which maps texture coordinates of the surface onto an image.
+/// `v` goes from the bottom to the top of the image.
+pub struct ImageTexture {
+    /// Mip-map pyramid: each level is half the size of the previous one.
+    /// Only the first level is kept if mip-mapping is not used.
+    levels: Vec<Matrix<Color>>,
+    filtering: TextureFiltering,
+    wrap: TextureWrap,
+    scale: [f64; 2],
+}
+
+impl ImageTexture {
+    pub fn new(image: Matrix<Color>,
+               filtering: TextureFiltering,
+               wrap: TextureWrap,
+               scale: [f64; 2]) -> ImageTexture {
+        assert!(image.width() > 0 && image.height() > 0);
+        let mut levels = vec![image];
+        if let TextureFiltering::MipMap = filtering {
+            while let Some(next) = downsample(levels.last().unwrap()) {
+                levels.push(next);
+            }
+        }
+        ImageTexture {
+            levels: levels,
+            filtering: filtering,
+            wrap: wrap,
+            scale: scale,
+        }
+    }
+
+    pub fn open(path: &str,
+                filtering: TextureFiltering,
+                wrap: TextureWrap,
+                scale: [f64; 2]) -> Result<ImageTexture, Box<Error>> {
+        let image = read_image(path)
+            .map_err(|e| format!("can't read texture {}: {}", path, e))?;
+        Ok(ImageTexture::new(image, filtering, wrap, scale))
+    }
+
+    /// Color of the texture at `uv`, averaged over the area of about `width`
+    /// in texture coordinates if mip-mapping is enabled.
+    pub fn lookup(&self, uv: [f64; 2], width: f64) -> Color {
+        match self.filtering {
+            TextureFiltering::Nearest => self.nearest(0, uv),
+            TextureFiltering::Bilinear => self.bilinear(0, uv),
+            TextureFiltering::MipMap => {
+                let image = &self.levels[0];
+                let texels = width * image.width().max(image.height()) as f64;
+                let max_level = (self.levels.len() - 1) as f64;
+                let level = if texels > 1.0 { texels.log2().min(max_level) } else { 0.0 };
+                let lower = level.floor();
+                let t = level - lower;
+                let lower = lower as usize;
+                if t == 0.0 {
+                    self.bilinear(lower, uv)
+                } else {
+                    self.bilinear(lower, uv) * (1.0 - t) + self.bilinear(lower + 1, uv) * t
+                }
+            }
+        }
+    }
+
+    fn texel(&self, level: usize, x: i64, y: i64) -> Color {
+        let image = &self.levels[level];
+        let (w, h) = (image.width() as i64, image.height() as i64);
+        let (x, y) = match self.wrap {
+            TextureWrap::Repeat => (((x % w) + w) % w, ((y % h) + h) % h),
+            TextureWrap::Clamp => (x.max(0).min(w - 1), y.max(0).min(h - 1)),
+        };
+        image[[x as u32, y as u32]]
+    }
+
+    /// Continuous texel coordinates of `uv`, with texel centers at
+    /// half-integers.
+    fn texel_coordinates(&self, level: usize, uv: [f64; 2]) -> (f64, f64) {
+        let image = &self.levels[level];
+        (uv[0] * image.width() as f64, (1.0 - uv[1]) * image.height() as f64)
+    }
+
+    fn nearest(&self, level: usize, uv: [f64; 2]) -> Color {
+        let (x, y) = self.texel_coordinates(level, uv);
+        self.texel(level, x.floor() as i64, y.floor() as i64)
+    }
+
+    fn bilinear(&self, level: usize, uv: [f64; 2]) -> Color {
+        let (x, y) = self.texel_coordinates(level, uv);
+        let (x, y) = (x - 0.5, y - 0.5);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        self.texel(level, x0, y0) * ((1.0 - tx) * (1.0 - ty))
+            + self.texel(level, x0 + 1, y0) * (tx * (1.0 - ty))
+            + self.texel(level, x0, y0 + 1) * ((1.0 - tx) * ty)
+            + self.texel(level, x0 + 1, y0 + 1) * (tx * ty)
+    }
+}
+
+impl Texture<Color> for ImageTexture {
+    fn at(&self, i: &Intersection) -> Color {
+        let uv = i.geom.local_coordinates;
+        let uv = [uv[0] * self.scale[0], uv[1] * self.scale[1]];
+        let width = i.footprint * i.geom.uv_density * self.scale[0].abs().max(self.scale[1].abs());
+        self.lookup(uv, width)
+    }
+}
+
+
+/// Values which procedural textures can interpolate between.
+pub trait Blend: Copy + Send + Sync {
+    /// `self` for `t == 0`, `other` for `t == 1`.
+    fn blend(self, other: Self, t: f64) -> Self;
+}
+
+impl Blend for f64 {
+    fn blend(self, other: f64, t: f64) -> f64 {
+        self * (1.0 - t) + other * t
+    }
+}
+
+impl Blend for Color {
+    fn blend(self, other: Color, t: f64) -> Color {
+        self * (1.0 - t) + other * t
+    }
+}
+
+
+#[derive(Debug, Clone, Copy)]
+pub enum NoisePattern {
+    Perlin,
+    Fbm,
+    Turbulence,
+    /// Stripes along the `x` axis distorted by turbulence.
+    Marble,
+    /// Rings around the `y` axis slightly distorted by noise.
+    Wood,
+    /// Distance to the nearest cell center, looks like cells or stones.
+    Worley,
+}
+
+
+/// Solid texture which blends `low` and `high` values according to a noise
+/// pattern. `scale` is the size of the noise features in the scene units.
+pub struct NoiseTexture<T> {
+    pattern: NoisePattern,
+    perlin: Perlin,
+    worley: Worley,
+    scale: f64,
+    octaves: u32,
+    low: T,
+    high: T,
+}
+
+impl<T: Blend> NoiseTexture<T> {
+    pub fn new(pattern: NoisePattern, low: T, high: T,
+               scale: f64, octaves: u32, seed: u32) -> NoiseTexture<T> {
+        assert!(scale > 0.0, "noise scale should be positive");
+        NoiseTexture {
+            pattern: pattern,
+            perlin: Perlin::new(seed),
+            worley: Worley::new(seed),
+            scale: scale,
+            octaves: octaves,
+            low: low,
+            high: high,
+        }
+    }
+
+    /// Value of the pattern in `[0, 1]`.
+    fn value(&self, p: Point) -> f64 {
+        let origin = Point::new(0.0, 0.0, 0.0);
+        let p = origin + (p - origin) / self.scale;
+        let value = match self.pattern {
+            NoisePattern::Perlin => 0.5 + 0.5 * self.perlin.noise(p),
+            NoisePattern::Fbm => 0.5 + 0.5 * self.perlin.fbm(p, self.octaves),
+            NoisePattern::Turbulence => self.perlin.turbulence(p, self.octaves),
+            NoisePattern::Marble => {
+                let phase = p[0] + 4.0 * self.perlin.turbulence(p, self.octaves);
+                0.5 + 0.5 * (PI * phase).sin()
+            }
+            NoisePattern::Wood => {
+                let r = (p[0] * p[0] + p[2] * p[2]).sqrt();
+                let r = r + 0.25 * self.perlin.fbm(p, self.octaves);
+                r - r.floor()
+            }
+            NoisePattern::Worley => self.worley.distance(p),
+        };
+        value.max(0.0).min(1.0)
+    }
+}
+
+impl<T: Blend> Texture<T> for NoiseTexture<T> {
+    fn at(&self, i: &Intersection) -> T {
+        self.low.blend(self.high, self.value(i.geom.point))
+    }
+}
+
+
+/// Scalar texture made of the brightness of a color texture.
+pub struct Grayscale(pub Box<Texture<Color>>);
+
+impl Texture<f64> for Grayscale {
+    fn at(&self, i: &Intersection) -> f64 {
+        self.0.at(i).grayscale()
+    }
+}
+
+
+/// Next level of the mip-map pyramid, `None` if the image is a single texel.
+fn downsample(image: &Matrix<Color>) -> Option<Matrix<Color>> {
+    let (w, h) = (image.width(), image.height());
+    if w == 1 && h == 1 {
+        return None;
+    }
+    let (nw, nh) = ((w / 2).max(1), (h / 2).max(1));
+    let mut result = Matrix::fill([nw, nh], Color::new(0.0, 0.0, 0.0));
+    for y in 0..nh {
+        for x in 0..nw {
+            let at = |dx: u32, dy: u32| image[[(2 * x + dx).min(w - 1), (2 * y + dy).min(h - 1)]];
+            result[[x, y]] = (at(0, 0) + at(1, 0) + at(0, 1) + at(1, 1)) / 4.0;
+        }
+    }
+    Some(result)
+}
+
+
+/// Reads a PNG, PPM, Radiance HDR or PFM image, choosing the format by the
+/// file extension. Colors of the high dynamic range formats are not limited
+/// to `[0, 1]`.
+pub fn read_image(path: &str) -> Result<Matrix<Color>, Box<Error>> {
+    let extension = Path::new(path).extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase());
+    let file = fs::File::open(path)?;
+    let read_all = || -> Result<Vec<u8>, Box<Error>> {
+        let mut data = Vec::new();
+        io::BufReader::new(&file).read_to_end(&mut data)?;
+        Ok(data)
+    };
+    match extension.as_ref().map(|e| e.as_str()) {
+        Some("png") => read_png(io::BufReader::new(file)),
+        Some("ppm") | Some("pnm") => read_ppm(&read_all()?),
+        Some("hdr") | Some("pic") => read_hdr(&read_all()?),
+        Some("pfm") => read_pfm(&read_all()?),
+        _ => Err(From::from("unsupported image format, expected .png, .ppm, .hdr or .pfm")),
+    }
+}
+
+fn read_png(source: io::BufReader<fs::File>) -> Result<Matrix<Color>, Box<Error>> {
+    let mut decoder = png::Decoder::new(source);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
+    let mut buf = vec![0; reader.output_buffer_size().ok_or("image is too large")?];
+    let frame = reader.next_frame(&mut buf)?;
+    let channels = match frame.color_type {
+        png::ColorType::Grayscale => 1,
+        png::ColorType::GrayscaleAlpha => 2,
+        png::ColorType::Rgb => 3,
+        png::ColorType::Rgba => 4,
+        png::ColorType::Indexed => return Err(From::from("unexpected indexed png")),
+    };
+
+    let (w, h) = (frame.width, frame.height);
+    let mut image = Matrix::fill([w, h], Color::new(0.0, 0.0, 0.0));
+    for y in 0..h {
+        let row = &buf[y as usize * frame.line_size..];
+        for x in 0..w {
+            let px = &row[(x * channels) as usize..];
+            let c = |i: usize| px[i] as f64 / 255.0;
+            image[[x, y]] = if channels < 3 {
+                Color::new(c(0), c(0), c(0))
+            } else {
+                Color::new(c(0), c(1), c(2))
+            };
+        }
+    }
+    Ok(image)
+}
+
+/// Reads ASCII (`P3`) and binary (`P6`) PPM images.
+fn read_ppm(data: &[u8]) -> Result<Matrix<Color>, Box<Error>> {
+    let mut pos = 0;
+    let mut header = Vec::new();
+    while header.len() < 4 {
+        header.push(ppm_token(data, &mut pos).ok_or("truncated ppm header")?);
+    }
+    let number = |s: &str| s.parse::<u32>().map_err(|_| format!("invalid ppm header: {}", s));
+    let (w, h, max_value) = (number(&header[1])?, number(&header[2])?, number(&header[3])?);
+    if w == 0 || h == 0 || max_value == 0 || max_value > 65535 {
+        return Err(From::from("invalid ppm header"));
+    }
+
+    let n_samples = 3 * w as usize * h as usize;
+    let samples = match header[0].as_str() {
+        "P3" => {
+            // each sample takes at least a byte, so the header can't make
+            // this allocate more than the data
+            let mut samples = Vec::with_capacity(n_samples.min(data.len()));
+            for _ in 0..n_samples {
+                let token = ppm_token(data, &mut pos).ok_or("truncated ppm data")?;
+                samples.push(number(&token)?);
+            }
+            samples
+        }
+        "P6" => {
+            // a single whitespace character separates the header from the data
+            if pos >= data.len() {
+                return Err(From::from("truncated ppm header"));
+            }
+            let data = &data[pos + 1..];
+            let bytes_per_sample = if max_value < 256 { 1 } else { 2 };
+            if data.len() < n_samples * bytes_per_sample {
+                return Err(From::from("truncated ppm data"));
+            }
+            (0..n_samples).map(|i| if bytes_per_sample == 1 {
+                data[i] as u32
+            } else {
+                (data[2 * i] as u32) << 8 | data[2 * i + 1] as u32
+            }).collect()
+        }
+        magic => return Err(From::from(format!("unsupported ppm format: {}", magic))),
+    };
+
+    let mut image = Matrix::fill([w, h], Color::new(0.0, 0.0, 0.0));
+    for y in 0..h {
+        for x in 0..w {
+            let i = 3 * (y * w + x) as usize;
+            let c = |s: u32| s.min(max_value) as f64 / max_value as f64;
+            image[[x, y]] = Color::new(c(samples[i]), c(samples[i + 1]), c(samples[i + 2]));
+        }
+    }
+    Ok(image)
+}
+
+/// Reads Radiance RGBE images with flat or run-length encoded scanlines,
+/// stored from the top row down.
+fn read_hdr(data: &[u8]) -> Result<Matrix<Color>, Box<Error>> {
+    let mut pos = 0;
+    let mut next_line = || -> Result<String, Box<Error>> {
+        let end = data[pos..].iter().position(|&b| b == b'\n').ok_or("truncated hdr header")?;
+        let line = String::from_utf8_lossy(&data[pos..pos + end]).into_owned();
+        pos += end + 1;
+        Ok(line)
+    };
+    if !next_line()?.starts_with("#?") {
+        return Err(From::from("not a Radiance hdr file"));
+    }
+    // the header ends with an empty line, the resolution follows it
+    loop {
+        let line = next_line()?;
+        if line.is_empty() {
+            break;
+        }
+        if line.starts_with("FORMAT=") && line != "FORMAT=32-bit_rle_rgbe" {
+            return Err(From::from("unsupported hdr format, expected 32-bit_rle_rgbe"));
+        }
+    }
+    let resolution = next_line()?;
+    let resolution = resolution.split_whitespace().collect::<Vec<_>>();
+    let (w, h) = match &resolution[..] {
+        &["-Y", h, "+X", w] => match (w.parse::<u32>(), h.parse::<u32>()) {
+            (Ok(w), Ok(h)) if w > 0 && h > 0 => (w, h),
+            _ => return Err(From::from("invalid hdr resolution")),
+        },
+        _ => return Err(From::from("unsupported hdr orientation, expected -Y h +X w")),
+    };
+
+    // a run takes four bytes for up to 255 pixels, or two bytes per channel
+    // for up to 127 pixels, so much shorter data can't hold the image. Only
+    // old style runs of runs could pack more, but writers don't use them.
+    if ((data.len() - pos) as u64) < (w as u64 * h as u64 + 63) / 64 {
+        return Err(From::from("truncated hdr data"));
+    }
+    let mut image = Matrix::fill([w, h], Color::new(0.0, 0.0, 0.0));
+    let mut scanline = vec![[0u8; 4]; w as usize];
+    let mut byte = || -> Result<u8, Box<Error>> {
+        let b = *data.get(pos).ok_or("truncated hdr data")?;
+        pos += 1;
+        Ok(b)
+    };
+    for y in 0..h {
+        let start = [byte()?, byte()?, byte()?, byte()?];
+        let is_rle = 8 <= w && w < 32768 && start[0] == 2 && start[1] == 2 && start[2] < 128;
+        if is_rle {
+            if ((start[2] as u32) << 8 | start[3] as u32) != w {
+                return Err(From::from("invalid hdr scanline width"));
+            }
+            for channel in 0..4 {
+                let mut x = 0;
+                while x < w as usize {
+                    let count = byte()? as usize;
+                    let is_run = count > 128;
+                    let n = if is_run { count - 128 } else { count };
+                    if n == 0 || x + n > w as usize {
+                        return Err(From::from("invalid hdr run length"));
+                    }
+                    let value = if is_run { byte()? } else { 0 };
+                    for pixel in &mut scanline[x..x + n] {
+                        pixel[channel] = if is_run { value } else { byte()? };
+                    }
+                    x += n;
+                }
+            }
+        } else {
+            // flat pixels, old style runs repeat the previous pixel
+            let mut x = 0;
+            let mut shift = 0;
+            let mut pixel = start;
+            loop {
+                if pixel[0] == 1 && pixel[1] == 1 && pixel[2] == 1 {
+                    let n = (pixel[3] as usize) << shift;
+                    if x == 0 || x + n > w as usize {
+                        return Err(From::from("invalid hdr run length"));
+                    }
+                    let previous = scanline[x - 1];
+                    for p in &mut scanline[x..x + n] {
+                        *p = previous;
+                    }
+                    x += n;
+                    shift += 8;
+                } else {
+                    scanline[x] = pixel;
+                    x += 1;
+                    shift = 0;
+                }
+                if x == w as usize {
+                    break;
+                }
+                pixel = [byte()?, byte()?, byte()?, byte()?];
+            }
+        }
+        for (x, rgbe) in scanline.iter().enumerate() {
+            image[[x as u32, y]] = if rgbe[3] == 0 {
+                Color::new(0.0, 0.0, 0.0)
+            } else {
+                let f = 2f64.powi(rgbe[3] as i32 - (128 + 8));
+                Color::new(rgbe[0] as f64 * f, rgbe[1] as f64 * f, rgbe[2] as f64 * f)
+            };
+        }
+    }
+    Ok(image)
+}
+
+/// Reads color (`PF`) and grayscale (`Pf`) portable float maps, stored from
+/// the bottom row up. The sign of the scale tells the byte order.
+fn read_pfm(data: &[u8]) -> Result<Matrix<Color>, Box<Error>> {
+    let mut pos = 0;
+    let mut header = Vec::new();
+    while header.len() < 4 {
+        header.push(ppm_token(data, &mut pos).ok_or("truncated pfm header")?);
+    }
+    let channels = match header[0].as_str() {
+        "PF" => 3,
+        "Pf" => 1,
+        magic => return Err(From::from(format!("unsupported pfm format: {}", magic))),
+    };
+    let number = |s: &str| s.parse::<u32>().map_err(|_| format!("invalid pfm header: {}", s));
+    let (w, h) = (number(&header[1])?, number(&header[2])?);
+    let scale = header[3].parse::<f64>().map_err(|_| "invalid pfm scale")?;
+    if w == 0 || h == 0 || scale == 0.0 {
+        return Err(From::from("invalid pfm header"));
+    }
+
+    // a single whitespace character separates the header from the data
+    if pos >= data.len() {
+        return Err(From::from("truncated pfm header"));
+    }
+    let data = &data[pos + 1..];
+    let n_samples = channels * w as usize * h as usize;
+    if data.len() < 4 * n_samples {
+        return Err(From::from("truncated pfm data"));
+    }
+    let sample = |i: usize| {
+        let b = [data[4 * i], data[4 * i + 1], data[4 * i + 2], data[4 * i + 3]];
+        let value = if scale < 0.0 { f32::from_le_bytes(b) } else { f32::from_be_bytes(b) };
+        // negative and NaN values can't be colors
+        (value as f64).max(0.0)
+    };
+    let mut image = Matrix::fill([w, h], Color::new(0.0, 0.0, 0.0));
+    for y in 0..h {
+        for x in 0..w {
+            let i = channels * ((h - 1 - y) * w + x) as usize;
+            image[[x, y]] = if channels == 3 {
+                Color::new(sample(i), sample(i + 1), sample(i + 2))
+            } else {
+                Color::new(sample(i), sample(i), sample(i))
+            };
+        }
+    }
+    Ok(image)
+}
+
+/// Next whitespace separated token of a PPM header, skipping comments.
+fn ppm_token(data: &[u8], pos: &mut usize) -> Option<String> {
+    loop {
+        while *pos < data.len() && (data[*pos] as char).is_whitespace() {
+            *pos += 1;
+        }
+        if *pos < data.len() && data[*pos] == b'#' {
+            while *pos < data.len() && data[*pos] != b'\n' {
+                *pos += 1;
+            }
+        } else {
+            break;
+        }
+    }
+    let start = *pos;
+    while *pos < data.len() && !(data[*pos] as char).is_whitespace() {
+        *pos += 1;
+    }
+    if start == *pos {
+        None
+    } else {
+        Some(String::from_utf8_lossy(&data[start..*pos]).into_owned())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use utils::props::check_prop;
+    use color::Color;
+    use scene::config::{TextureFiltering, TextureWrap};
+
+    fn checker() -> Matrix<Color> {
+        let mut image = Matrix::fill([4, 4], Color::new(0.0, 0.0, 0.0));
+        for y in 0..4 {
+            for x in 0..4 {
+                if (x + y) % 2 == 0 {
+                    image[[x, y]] = Color::new(1.0, 1.0, 1.0);
+                }
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn test_image_texture_lookups() {
+        let nearest = ImageTexture::new(checker(), TextureFiltering::Nearest,
+                                        TextureWrap::Repeat, [1.0, 1.0]);
+        let clamped = ImageTexture::new(checker(), TextureFiltering::Bilinear,
+                                        TextureWrap::Clamp, [1.0, 1.0]);
+        let mipmap = ImageTexture::new(checker(), TextureFiltering::MipMap,
+                                       TextureWrap::Repeat, [1.0, 1.0]);
+        assert_eq!(mipmap.levels.len(), 3);
+
+        check_prop(|(u, v): (f64, f64)| {
+            // the top left texel is white, v goes upwards
+            let white = nearest.lookup([u * 0.25, 1.0 - v * 0.25], 0.0).grayscale();
+            assert!(white > 0.999);
+            let repeated = nearest.lookup([u * 0.25 + 3.0, 1.0 - v * 0.25 - 2.0], 0.0).grayscale();
+            assert!(repeated > 0.999);
+
+            let g = clamped.lookup([u, v], 0.0).grayscale();
+            assert!(0.0 <= g && g <= 1.0 + 1e-9);
+            let corner = clamped.lookup([-u, 1.0 + v], 0.0).grayscale();
+            assert!(corner > 0.999);
+
+            // wide footprint averages the whole checkerboard
+            let average = mipmap.lookup([u, v], 2.0).grayscale();
+            assert!((average - 0.5).abs() < 1e-9);
+        });
+    }
+
+    #[test]
+    fn test_read_ppm() {
+        let ascii = read_ppm(b"P3\n# comment\n2 1\n255\n255 0 0  0 0 255\n").unwrap();
+        let binary = read_ppm(b"P6 2 1 255\n\xff\x00\x00\x00\x00\xff").unwrap();
+        for image in &[ascii, binary] {
+            assert_eq!((image.width(), image.height()), (2, 1));
+            assert!((image[[0, 0]].grayscale() - 1.0 / 3.0).abs() < 1e-9);
+            assert!((image[[1, 0]].grayscale() - 1.0 / 3.0).abs() < 1e-9);
+        }
+        assert!(read_ppm(b"P6 2 1 255\n\xff").is_err());
+        assert!(read_ppm(b"P6 2 1 255").is_err());
+    }
+
+    #[test]
+    fn test_read_hdr_and_pfm() {
+        // a flat pixel repeated by an old style run
+        let mut flat = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 2\n".to_vec();
+        flat.extend_from_slice(&[128, 64, 32, 129, 1, 1, 1, 1]);
+        let mut rle = b"#?RGBE\n\n-Y 1 +X 8\n".to_vec();
+        rle.extend_from_slice(&[2, 2, 0, 8, 136, 128, 136, 64, 8]);
+        rle.extend_from_slice(&[32; 8]);
+        rle.extend_from_slice(&[136, 129]);
+        for image in &[read_hdr(&flat).unwrap(), read_hdr(&rle).unwrap()] {
+            assert_eq!(image.height(), 1);
+            for x in 0..image.width() {
+                assert_eq!(image[[x, 0]].rgb(), [1.0, 0.5, 0.25]);
+            }
+        }
+        assert!(read_hdr(&rle[..rle.len() - 1]).is_err());
+        assert!(read_hdr(b"#?RGBE\n\n-Y 100000 +X 100000\n\x02\x02").is_err());
+
+        let mut pfm = b"PF\n1 2\n-1.0\n".to_vec();
+        for &v in &[1.0f32, 2.0, 3.0, 4.0, 5.0, 6.0] {
+            pfm.extend_from_slice(&v.to_le_bytes());
+        }
+        let image = read_pfm(&pfm).unwrap();
+        // the rows go upwards
+        assert_eq!(image[[0, 0]].rgb(), [4.0, 5.0, 6.0]);
+        assert_eq!(image[[0, 1]].rgb(), [1.0, 2.0, 3.0]);
+        assert!(read_pfm(b"PF 1 1 -1.0").is_err());
+    }
+}